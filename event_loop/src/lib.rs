@@ -96,11 +96,112 @@
 //! Incrementing!
 //! ```
 
-use std::{future::Future, marker::PhantomData};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
 use futures::future::BoxFuture;
 use tokio::{sync::mpsc::UnboundedReceiver, task::JoinHandle};
 
+/// How often to check on tasks while waiting for them to finish in
+/// [`EventLoop::shutdown`]
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How many rounds of queued messages are still handled after the shutdown
+/// timeout, in case handlers keep producing more
+const SHUTDOWN_DRAIN_ROUNDS: usize = 16;
+
+/// Gives the name a message is counted under in [`EventLoopMetrics`]
+type MessageNamer<M> = fn(&M) -> &'static str;
+
+/// What to do when the queue of messages waiting for the next cycle is full,
+/// see [`EventLoop::with_max_queue_len`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Drop the oldest queued messages to make room
+    DropOldest,
+    /// Stop reading from sources until the queue has been worked through.
+    /// Messages emitted by handlers are still queued, so nothing is lost.
+    PauseSources,
+}
+
+/// Counts what the event loop has done since it was created. Cloning gives
+/// another handle to the same counters, so they can be read from elsewhere
+/// while the loop runs. See [`EventLoop::with_metrics`].
+#[derive(Debug, Clone, Default)]
+pub struct EventLoopMetrics {
+    counters: Arc<MetricsCounters>,
+}
+
+#[derive(Debug, Default)]
+struct MetricsCounters {
+    /// Messages handled, by the name of their type
+    messages: Mutex<BTreeMap<&'static str, u64>>,
+    tasks_spawned: AtomicU64,
+    tasks_completed: AtomicU64,
+    /// Tasks which panicked, or were aborted for running too long
+    tasks_failed: AtomicU64,
+    dropped_messages: AtomicU64,
+}
+
+/// The values of the [`EventLoopMetrics`] at a point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventLoopMetricsSnapshot {
+    pub messages: BTreeMap<&'static str, u64>,
+    pub tasks_spawned: u64,
+    pub tasks_completed: u64,
+    pub tasks_failed: u64,
+    pub dropped_messages: u64,
+}
+
+impl EventLoopMetrics {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Panics
+    /// If the lock was poisoned
+    #[must_use]
+    pub fn snapshot(&self) -> EventLoopMetricsSnapshot {
+        let c = &self.counters;
+        EventLoopMetricsSnapshot {
+            messages: c.messages.lock().expect("Metrics lock poisoned").clone(),
+            tasks_spawned: c.tasks_spawned.load(Ordering::Relaxed),
+            tasks_completed: c.tasks_completed.load(Ordering::Relaxed),
+            tasks_failed: c.tasks_failed.load(Ordering::Relaxed),
+            dropped_messages: c.dropped_messages.load(Ordering::Relaxed),
+        }
+    }
+
+    fn add_message(&self, name: &'static str) {
+        *self
+            .counters
+            .messages
+            .lock()
+            .expect("Metrics lock poisoned")
+            .entry(name)
+            .or_default() += 1;
+    }
+
+    fn add(counter: &AtomicU64, count: u64) {
+        counter.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+struct HandlerEntry<H> {
+    priority: i32,
+    /// Disabled handlers are skipped, but keep their place and state
+    enabled: bool,
+    handler: H,
+}
+
 pub struct EventLoop<S, M, H>
 where
     S: Send,
@@ -108,9 +209,19 @@ where
     H: MessageHandler<S, M, M>,
 {
     sources: Vec<Box<dyn MessageSource<M> + 'static + Send>>,
-    handlers: Vec<H>,
+    /// Kept sorted by descending priority, then by the order they were added
+    handlers: Vec<HandlerEntry<H>>,
     queue: Vec<M>,
-    async_tasks: Vec<JoinHandle<Option<M>>>,
+    /// Running tasks along with when they were started
+    async_tasks: Vec<(JoinHandle<Option<M>>, Instant)>,
+    /// Tasks which run for longer than this are aborted
+    task_timeout: Option<Duration>,
+    max_queue_len: Option<(usize, QueuePolicy)>,
+    /// How many messages have been dropped because the queue was full
+    dropped_messages: u64,
+    /// Where to count what happens, and how to name messages when counting
+    /// them
+    metrics: Option<(EventLoopMetrics, MessageNamer<M>)>,
 
     state: PhantomData<S>,
 }
@@ -128,6 +239,10 @@ where
             handlers: Vec::new(),
             queue: Vec::new(),
             async_tasks: Vec::new(),
+            task_timeout: None,
+            max_queue_len: None,
+            dropped_messages: 0,
+            metrics: None,
             state: PhantomData,
         }
     }
@@ -138,19 +253,107 @@ where
         self
     }
 
+    /// Aborts any async tasks which are still running after `timeout`, so
+    /// they can't hang around forever. Aborted tasks don't produce a message.
+    #[must_use]
+    pub const fn with_task_timeout(mut self, timeout: Duration) -> Self {
+        self.task_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits how many messages are handled each cycle, counting both those
+    /// queued by handlers and those read from sources, so the queue can't
+    /// grow forever if messages arrive faster than they're handled. The queue
+    /// is always emptied each cycle, so neither policy can deadlock even if
+    /// handlers respond to their own messages.
     #[must_use]
-    pub fn add_handler(mut self, handler: impl Into<H>) -> Self {
-        self.handlers.push(handler.into());
+    pub const fn with_max_queue_len(mut self, max: usize, policy: QueuePolicy) -> Self {
+        self.max_queue_len = Some((max, policy));
         self
     }
 
+    /// Counts messages handled and tasks run in `metrics` from now on
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: EventLoopMetrics) -> Self
+    where
+        M: NamedMessage,
+    {
+        self.metrics = Some((metrics, |m| m.name()));
+        self
+    }
+
+    /// How many messages have been dropped because the queue was full
+    #[must_use]
+    pub const fn dropped_messages(&self) -> u64 {
+        self.dropped_messages
+    }
+
+    /// Adds a handler with the default priority of 0
+    #[must_use]
+    pub fn add_handler(self, handler: impl Into<H>) -> Self {
+        self.add_handler_with_priority(handler, 0)
+    }
+
+    /// Adds a handler which will be given each message before any handlers
+    /// with a lower priority. Handlers with the same priority are given
+    /// messages in the order they were added.
+    #[must_use]
+    pub fn add_handler_with_priority(mut self, handler: impl Into<H>, priority: i32) -> Self {
+        let index = self.handlers.partition_point(|h| h.priority >= priority);
+        self.handlers.insert(
+            index,
+            HandlerEntry {
+                priority,
+                enabled: true,
+                handler: handler.into(),
+            },
+        );
+        self
+    }
+
+    /// Enables or disables all handlers with the given name. Disabled handlers
+    /// aren't given any messages until they're enabled again. Returns whether
+    /// any handlers had that name.
+    pub fn set_handler_enabled(&mut self, name: &str, enabled: bool) -> bool
+    where
+        H: NamedHandler,
+    {
+        let mut found = false;
+        for h in self
+            .handlers
+            .iter_mut()
+            .filter(|h| h.handler.name() == name)
+        {
+            h.enabled = enabled;
+            found = true;
+        }
+        found
+    }
+
+    /// Whether the handler with the given name is enabled, or `None` if there
+    /// is no such handler
+    #[must_use]
+    pub fn handler_enabled(&self, name: &str) -> Option<bool>
+    where
+        H: NamedHandler,
+    {
+        self.handlers
+            .iter()
+            .find(|h| h.handler.name() == name)
+            .map(|h| h.enabled)
+    }
+
     pub fn handle_message(&mut self, mut message: M, state: &mut S) -> Vec<Action<M>> {
         let mut out = Vec::new();
 
+        if let Some((metrics, name)) = &self.metrics {
+            metrics.add_message(name(&message));
+        }
+
         message.preprocess(state);
 
-        for h in &mut self.handlers {
-            match h.handle_message(state, &message) {
+        for h in self.handlers.iter_mut().filter(|h| h.enabled) {
+            match h.handler.handle_message(state, &message) {
                 Some(Handled(Internal::Single(m))) => out.push(m),
                 Some(Handled(Internal::Batch(ms))) => out.extend(ms),
                 None => {}
@@ -182,49 +385,175 @@ where
     /// state with them, dipatches any futures generated or adds the new messages back into the queue
     #[allow(clippy::future_not_send)]
     pub async fn execute_cycle(&mut self, state: &mut S) -> Option<()> {
-        let mut messages = Vec::new();
+        let mut messages = std::mem::take(&mut self.queue);
 
-        // Check sources
-        messages.append(&mut self.queue);
+        // Check sources, leaving the rest for later once there's too much to
+        // get through
+        let limit = match self.max_queue_len {
+            Some((max, QueuePolicy::PauseSources)) => max.max(messages.len()),
+            _ => usize::MAX,
+        };
         for s in &mut self.sources {
-            while let Some(m) = s.next_message() {
+            while messages.len() < limit {
+                let Some(m) = s.next_message() else {
+                    break;
+                };
                 messages.push(m);
             }
         }
 
         // Check async tasks
-        let mut finished_tasks = Vec::new();
-        for (i, j) in self.async_tasks.iter_mut().enumerate() {
-            if j.is_finished() {
-                finished_tasks.push(i);
-                match j.await {
+        messages.extend(self.finished_tasks().await);
+
+        self.drop_oldest(&mut messages);
+
+        if messages.is_empty() {
+            return None;
+        }
+
+        // Handle messages
+        let actions = self.handle_messages(messages, state);
+        self.dispatch(actions);
+
+        Some(())
+    }
+
+    /// Stops reading from sources and waits up to `timeout` for any running
+    /// async tasks to finish, handling the messages they produce as usual so
+    /// nothing in flight is lost when exiting. Messages already queued are
+    /// still handled once the timeout is up, and tasks still running then are
+    /// aborted.
+    #[allow(clippy::future_not_send)]
+    pub async fn shutdown(&mut self, state: &mut S, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        if !self.async_tasks.is_empty() {
+            tracing::info!(
+                "Waiting for {} tasks to finish before exiting.",
+                self.async_tasks.len()
+            );
+        }
+
+        while Instant::now() < deadline {
+            let mut messages = std::mem::take(&mut self.queue);
+            messages.extend(self.finished_tasks().await);
+
+            if messages.is_empty() {
+                if self.async_tasks.is_empty() {
+                    return;
+                }
+                tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+                continue;
+            }
+
+            let actions = self.handle_messages(messages, state);
+            self.dispatch(actions);
+        }
+
+        for _ in 0..SHUTDOWN_DRAIN_ROUNDS {
+            let messages = std::mem::take(&mut self.queue);
+            if messages.is_empty() {
+                break;
+            }
+            let actions = self.handle_messages(messages, state);
+            self.dispatch(actions);
+        }
+
+        if self.async_tasks.is_empty() {
+            return;
+        }
+        tracing::warn!(
+            "{} tasks were still pending after {timeout:?}, aborting them.",
+            self.async_tasks.len()
+        );
+        for (task, _) in self.async_tasks.drain(..) {
+            task.abort();
+        }
+    }
+
+    /// Takes the results of any async tasks that have finished, and aborts
+    /// those which have run for longer than the task timeout.
+    #[allow(clippy::future_not_send)]
+    async fn finished_tasks(&mut self) -> Vec<M> {
+        let mut messages = Vec::new();
+        let now = Instant::now();
+        let mut running = Vec::with_capacity(self.async_tasks.len());
+        let metrics = self.metrics.as_ref().map(|(m, _)| &m.counters);
+        for (task, started) in std::mem::take(&mut self.async_tasks) {
+            if task.is_finished() {
+                let result = task.await;
+                if let Some(c) = metrics {
+                    let counter = if result.is_ok() {
+                        &c.tasks_completed
+                    } else {
+                        &c.tasks_failed
+                    };
+                    EventLoopMetrics::add(counter, 1);
+                }
+
+                match result {
                     Ok(Some(m)) => messages.push(m),
                     Ok(None) => {}
+                    Err(e) if e.is_cancelled() => {
+                        tracing::debug!("Task was cancelled");
+                    }
                     Err(e) => {
                         tracing::error!("Task paniced: {e}");
                     }
                 }
+            } else if self
+                .task_timeout
+                .is_some_and(|timeout| now.duration_since(started) > timeout)
+            {
+                // Dropping the handle after aborting is fine, the task is
+                // cancelled the next time it yields.
+                tracing::warn!(
+                    "Aborting task which has been running for {:?}",
+                    now.duration_since(started)
+                );
+                task.abort();
+                if let Some(c) = metrics {
+                    EventLoopMetrics::add(&c.tasks_failed, 1);
+                }
+            } else {
+                running.push((task, started));
             }
         }
-        for i in finished_tasks.into_iter().rev() {
-            self.async_tasks.remove(i);
-        }
-
-        if messages.is_empty() {
-            return None;
-        }
+        self.async_tasks = running;
+        messages
+    }
 
-        // Handle messages
-        for a in self.handle_messages(messages, state) {
+    /// Queues new messages for the next cycle and spawns any futures
+    fn dispatch(&mut self, actions: Vec<Action<M>>) {
+        for a in actions {
             match a {
                 Action::Message(m) => self.queue.push(m),
                 Action::Future(f) => {
-                    self.async_tasks.push(tokio::task::spawn(f));
+                    self.async_tasks
+                        .push((tokio::task::spawn(f), Instant::now()));
+                    if let Some((metrics, _)) = &self.metrics {
+                        EventLoopMetrics::add(&metrics.counters.tasks_spawned, 1);
+                    }
                 }
             }
         }
+    }
 
-        Some(())
+    /// Drops the oldest of `messages` if there are more than the queue allows
+    fn drop_oldest(&mut self, messages: &mut Vec<M>) {
+        let Some((max, QueuePolicy::DropOldest)) = self.max_queue_len else {
+            return;
+        };
+        if messages.len() <= max {
+            return;
+        }
+
+        let excess = messages.len() - max;
+        messages.drain(..excess);
+        self.dropped_messages += excess as u64;
+        if let Some((metrics, _)) = &self.metrics {
+            EventLoopMetrics::add(&metrics.counters.dropped_messages, excess as u64);
+        }
+        tracing::warn!("Message queue is full, dropped {excess} messages.");
     }
 }
 
@@ -232,6 +561,20 @@ pub trait MessageHandler<S, IM, OM> {
     fn handle_message(&mut self, state: &S, message: &IM) -> Option<Handled<OM>>;
 }
 
+/// Handlers which can be looked up by name, e.g. to enable or disable them
+/// with [`EventLoop::set_handler_enabled`]. Implemented for the handler enum
+/// generated by [`define_events!`], where the name is that of the variant.
+pub trait NamedHandler {
+    fn name(&self) -> &'static str;
+}
+
+/// Messages which can be told apart by name, e.g. to count them with
+/// [`EventLoop::with_metrics`]. Implemented for the message enum generated by
+/// [`define_events!`], where the name is that of the variant.
+pub trait NamedMessage {
+    fn name(&self) -> &'static str;
+}
+
 impl<S, IM, OM, T> MessageHandler<S, IM, OM> for &T {
     fn handle_message(&mut self, _state: &S, _message: &IM) -> Option<Handled<OM>> {
         None
@@ -281,6 +624,19 @@ impl<M> Handled<M> {
         Some(Self(Internal::Single(Action::Future(Box::pin(future)))))
     }
 
+    /// Like [`Handled::future`], but waits for `delay` before running the
+    /// future. The message produced is queued as usual once it's ready. The
+    /// delay counts towards the task timeout, if one is set.
+    pub fn delayed(
+        delay: Duration,
+        future: impl Future<Output = Option<M>> + 'static + Send,
+    ) -> Option<Self> {
+        Self::future(async move {
+            tokio::time::sleep(delay).await;
+            future.await
+        })
+    }
+
     pub fn multiple(commands: impl IntoIterator<Item = Option<Self>>) -> Option<Self> {
         let mut batch = Vec::new();
 
@@ -333,6 +689,21 @@ impl<M, I: Into<M>> MessageSource<M> for tokio::sync::mpsc::Receiver<I> {
     }
 }
 
+impl<M, I: Into<M> + Clone> MessageSource<M> for tokio::sync::broadcast::Receiver<I> {
+    fn next_message(&mut self) -> Option<M> {
+        loop {
+            match self.try_recv() {
+                Ok(m) => return Some(m.into()),
+                // Skip what was missed and carry on from the oldest message still available
+                Err(tokio::sync::broadcast::error::TryRecvError::Lagged(skipped)) => {
+                    tracing::warn!("Message source fell behind, skipped {skipped} messages.");
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
 pub fn try_get<T>(message: &impl Is<T>) -> Option<&T> {
     message.try_get()
 }
@@ -378,6 +749,15 @@ macro_rules! define_events {
             }
         }
 
+        impl event_loop::NamedMessage for $message_enum {
+            fn name(&self) -> &'static str {
+                match self {
+                    $message_enum::None => "None",
+                    $($message_enum::$message(_) => stringify!($message)),+
+                }
+            }
+        }
+
         // Impl Is
         $(
             impl event_loop::Is<$message> for $message_enum {
@@ -434,6 +814,14 @@ macro_rules! define_events {
             }
         }
 
+        impl event_loop::NamedHandler for $handler_enum {
+            fn name(&self) -> &'static str {
+                match self {
+                    $($handler_enum::$handler(_) => stringify!($handler)),+
+                }
+            }
+        }
+
         $(
             impl From<$handler> for $handler_enum {
                 fn from(val: $handler) -> Self {
@@ -443,3 +831,342 @@ macro_rules! define_events {
         )+
     };
 }
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc, sync::mpsc, time::Duration};
+
+    use tokio::sync::broadcast;
+
+    use super::{
+        EventLoop, EventLoopMetrics, Handled, Message, MessageHandler, MessageSource, NamedHandler,
+        NamedMessage, QueuePolicy,
+    };
+
+    struct Ping;
+    impl Message<()> for Ping {}
+    impl NamedMessage for Ping {
+        fn name(&self) -> &'static str {
+            "Ping"
+        }
+    }
+
+    /// Replies to every message it's given
+    struct Echo;
+    impl MessageHandler<(), Ping, Ping> for Echo {
+        fn handle_message(&mut self, _state: &(), _message: &Ping) -> Option<Handled<Ping>> {
+            Handled::single(Ping)
+        }
+    }
+    impl NamedHandler for Echo {
+        fn name(&self) -> &'static str {
+            "Echo"
+        }
+    }
+
+    /// Starts a task which never finishes for every message it's given
+    struct Stall;
+    impl MessageHandler<(), Ping, Ping> for Stall {
+        fn handle_message(&mut self, _state: &(), _message: &Ping) -> Option<Handled<Ping>> {
+            Handled::future(std::future::pending())
+        }
+    }
+
+    /// Counts down to zero, waiting a second between each message
+    struct SlowCountdown;
+    impl MessageHandler<Vec<u32>, Tick, Tick> for SlowCountdown {
+        fn handle_message(&mut self, _state: &Vec<u32>, message: &Tick) -> Option<Handled<Tick>> {
+            let next = message.0.checked_sub(1)?;
+            Handled::delayed(Duration::from_secs(1), async move { Some(Tick(next)) })
+        }
+    }
+
+    /// Notes down its id whenever it's given a message
+    struct Record(u32, Rc<RefCell<Vec<u32>>>);
+    impl MessageHandler<(), Ping, Ping> for Record {
+        fn handle_message(&mut self, _state: &(), _message: &Ping) -> Option<Handled<Ping>> {
+            self.1.borrow_mut().push(self.0);
+            None
+        }
+    }
+
+    /// Records the countdown in the state
+    struct Tick(u32);
+    impl Message<Vec<u32>> for Tick {
+        fn update_state(self, state: &mut Vec<u32>) {
+            state.push(self.0);
+        }
+    }
+    impl NamedMessage for Tick {
+        fn name(&self) -> &'static str {
+            "Tick"
+        }
+    }
+
+    /// Counts down to zero, one message at a time
+    struct Countdown;
+    impl MessageHandler<Vec<u32>, Tick, Tick> for Countdown {
+        fn handle_message(&mut self, _state: &Vec<u32>, message: &Tick) -> Option<Handled<Tick>> {
+            let next = message.0.checked_sub(1)?;
+            Handled::single(Tick(next))
+        }
+    }
+    impl NamedHandler for Countdown {
+        fn name(&self) -> &'static str {
+            "Countdown"
+        }
+    }
+
+    /// Handles messages without replying
+    struct Ignore;
+    impl MessageHandler<Vec<u32>, Tick, Tick> for Ignore {
+        fn handle_message(&mut self, _state: &Vec<u32>, _message: &Tick) -> Option<Handled<Tick>> {
+            None
+        }
+    }
+
+    /// Runs cycles until there's nothing left to do
+    async fn run_until_idle<H: MessageHandler<Vec<u32>, Tick, Tick>>(
+        event_loop: &mut EventLoop<Vec<u32>, Tick, H>,
+        state: &mut Vec<u32>,
+    ) {
+        for _ in 0..100 {
+            if event_loop.execute_cycle(state).await.is_none() {
+                return;
+            }
+        }
+        panic!("Event loop never ran out of messages");
+    }
+
+    #[test]
+    pub fn shutdown_drains_queue() {
+        let mut event_loop: EventLoop<Vec<u32>, Tick, Countdown> =
+            EventLoop::new().add_handler(Countdown);
+        let mut state = Vec::new();
+
+        // Even with no time to wait, messages already queued and the ones
+        // they produce are still handled
+        event_loop.queue_message(Tick(3));
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("Couldn't build runtime")
+            .block_on(event_loop.shutdown(&mut state, std::time::Duration::ZERO));
+        assert_eq!(state, [3, 2, 1, 0]);
+    }
+
+    #[test]
+    pub fn broadcast_source() {
+        let (tx, mut rx_a) = broadcast::channel::<u32>(16);
+        let mut rx_b = tx.subscribe();
+
+        for i in 0..10 {
+            tx.send(i).expect("No receivers");
+        }
+
+        // Both sources should see every message
+        for rx in [&mut rx_a, &mut rx_b] {
+            let received: Vec<u32> = std::iter::from_fn(|| rx.next_message()).collect();
+            assert_eq!(received, (0..10).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    pub fn broadcast_source_lagged() {
+        let (tx, mut rx) = broadcast::channel::<u32>(4);
+        for i in 0..10 {
+            tx.send(i).expect("No receivers");
+        }
+
+        // The oldest messages were overwritten, but the rest are still read
+        let received: Vec<u32> = std::iter::from_fn(|| rx.next_message()).collect();
+        assert_eq!(received, [6, 7, 8, 9]);
+    }
+
+    #[test]
+    pub fn disabled_handler_is_skipped() {
+        let mut event_loop: EventLoop<(), Ping, Echo> = EventLoop::new().add_handler(Echo);
+        assert_eq!(event_loop.handler_enabled("Echo"), Some(true));
+        assert_eq!(event_loop.handle_message(Ping, &mut ()).len(), 1);
+
+        assert!(event_loop.set_handler_enabled("Echo", false));
+        assert_eq!(event_loop.handler_enabled("Echo"), Some(false));
+        assert!(event_loop.handle_message(Ping, &mut ()).is_empty());
+
+        assert!(event_loop.set_handler_enabled("Echo", true));
+        assert_eq!(event_loop.handle_message(Ping, &mut ()).len(), 1);
+
+        assert!(!event_loop.set_handler_enabled("Missing", false));
+        assert_eq!(event_loop.handler_enabled("Missing"), None);
+    }
+
+    #[test]
+    pub fn metrics_count_messages() {
+        let metrics = EventLoopMetrics::new();
+        let mut event_loop: EventLoop<(), Ping, Echo> = EventLoop::new()
+            .add_handler(Echo)
+            .with_metrics(metrics.clone());
+
+        event_loop.handle_messages(vec![Ping, Ping], &mut ());
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.messages.get("Ping"), Some(&2));
+        assert_eq!(snapshot.tasks_spawned, 0);
+    }
+
+    #[tokio::test]
+    pub async fn drop_oldest_caps_sources() {
+        let (tx, rx) = mpsc::channel();
+        let mut event_loop: EventLoop<Vec<u32>, Tick, Ignore> = EventLoop::new()
+            .add_source(Box::new(rx))
+            .add_handler(Ignore)
+            .with_max_queue_len(4, QueuePolicy::DropOldest);
+        let mut state = Vec::new();
+
+        for i in 0..10 {
+            tx.send(Tick(i)).expect("Receiver dropped");
+        }
+
+        // Everything read in one cycle is capped, not just the queue
+        assert!(event_loop.execute_cycle(&mut state).await.is_some());
+        assert_eq!(state, [6, 7, 8, 9]);
+        assert_eq!(event_loop.dropped_messages(), 6);
+        assert!(event_loop.execute_cycle(&mut state).await.is_none());
+    }
+
+    #[tokio::test]
+    pub async fn pause_sources_reads_what_fits() {
+        let (tx, rx) = mpsc::channel();
+        let mut event_loop: EventLoop<Vec<u32>, Tick, Ignore> = EventLoop::new()
+            .add_source(Box::new(rx))
+            .add_handler(Ignore)
+            .with_max_queue_len(4, QueuePolicy::PauseSources);
+        let mut state = Vec::new();
+
+        for i in 0..10 {
+            tx.send(Tick(i)).expect("Receiver dropped");
+        }
+
+        assert!(event_loop.execute_cycle(&mut state).await.is_some());
+        assert_eq!(state, [0, 1, 2, 3]);
+
+        // The rest are left in the source for later cycles
+        run_until_idle(&mut event_loop, &mut state).await;
+        assert_eq!(state, (0..10).collect::<Vec<_>>());
+        assert_eq!(event_loop.dropped_messages(), 0);
+    }
+
+    #[tokio::test]
+    pub async fn drop_oldest_with_handler_replying_to_itself() {
+        let (tx, rx) = mpsc::channel();
+        let mut event_loop: EventLoop<Vec<u32>, Tick, Countdown> = EventLoop::new()
+            .add_source(Box::new(rx))
+            .add_handler(Countdown)
+            .with_max_queue_len(2, QueuePolicy::DropOldest);
+        let mut state = Vec::new();
+
+        for _ in 0..3 {
+            event_loop.queue_message(Tick(3));
+        }
+        tx.send(Tick(10)).expect("Receiver dropped");
+
+        // Two of the queued countdowns are dropped, the rest run to the end
+        run_until_idle(&mut event_loop, &mut state).await;
+        assert_eq!(event_loop.dropped_messages(), 2);
+        assert_eq!(state, [3, 10, 2, 9, 1, 8, 0, 7, 6, 5, 4, 3, 2, 1, 0]);
+    }
+
+    #[tokio::test]
+    pub async fn pause_sources_with_handler_replying_to_itself() {
+        let (tx, rx) = mpsc::channel();
+        let mut event_loop: EventLoop<Vec<u32>, Tick, Countdown> = EventLoop::new()
+            .add_source(Box::new(rx))
+            .add_handler(Countdown)
+            .with_max_queue_len(2, QueuePolicy::PauseSources);
+        let mut state = Vec::new();
+
+        for _ in 0..3 {
+            event_loop.queue_message(Tick(3));
+        }
+        tx.send(Tick(10)).expect("Receiver dropped");
+
+        // The countdowns keep the queue full, so the source waits until
+        // they've finished
+        run_until_idle(&mut event_loop, &mut state).await;
+        assert_eq!(event_loop.dropped_messages(), 0);
+        let mut expected: Vec<u32> = [3, 2, 1, 0].iter().flat_map(|&i| [i; 3]).collect();
+        expected.extend((0..=10).rev());
+        assert_eq!(state, expected);
+    }
+
+    #[tokio::test]
+    pub async fn task_timeout_aborts_stalled_tasks() {
+        let metrics = EventLoopMetrics::new();
+        let mut event_loop: EventLoop<(), Ping, Stall> = EventLoop::new()
+            .add_handler(Stall)
+            .with_task_timeout(Duration::from_millis(10))
+            .with_metrics(metrics.clone());
+
+        event_loop.queue_message(Ping);
+        assert!(event_loop.execute_cycle(&mut ()).await.is_some());
+        assert_eq!(event_loop.async_tasks.len(), 1);
+
+        // Still running, but not for long enough to be aborted yet
+        assert!(event_loop.execute_cycle(&mut ()).await.is_none());
+        assert_eq!(event_loop.async_tasks.len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(event_loop.execute_cycle(&mut ()).await.is_none());
+        assert!(event_loop.async_tasks.is_empty());
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.tasks_spawned, 1);
+        assert_eq!(snapshot.tasks_failed, 1);
+
+        // The loop carries on as usual afterwards
+        event_loop.queue_message(Ping);
+        assert!(event_loop.execute_cycle(&mut ()).await.is_some());
+        assert_eq!(event_loop.async_tasks.len(), 1);
+    }
+
+    #[test]
+    pub fn handlers_run_by_priority() {
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let record = |id| Record(id, order.clone());
+        let mut event_loop: EventLoop<(), Ping, Record> = EventLoop::new()
+            .add_handler(record(0))
+            .add_handler_with_priority(record(1), -5)
+            .add_handler_with_priority(record(2), 10)
+            .add_handler(record(3))
+            .add_handler_with_priority(record(4), 10)
+            .add_handler_with_priority(record(5), -5);
+
+        // Highest priority first, and in the order they were added otherwise
+        event_loop.handle_message(Ping, &mut ());
+        assert_eq!(*order.borrow(), [2, 4, 0, 3, 1, 5]);
+    }
+
+    #[tokio::test]
+    pub async fn delayed_message_is_queued_after_delay() {
+        tokio::time::pause();
+        let mut event_loop: EventLoop<Vec<u32>, Tick, SlowCountdown> =
+            EventLoop::new().add_handler(SlowCountdown);
+        let mut state = Vec::new();
+
+        event_loop.queue_message(Tick(1));
+        assert!(event_loop.execute_cycle(&mut state).await.is_some());
+        assert_eq!(event_loop.async_tasks.len(), 1);
+        // Let the task start waiting before moving the clock
+        tokio::task::yield_now().await;
+
+        // Nothing arrives before the delay is up
+        tokio::time::advance(Duration::from_millis(900)).await;
+        assert!(event_loop.execute_cycle(&mut state).await.is_none());
+        assert_eq!(state, [1]);
+
+        // Then it comes back through the queue like any other message
+        tokio::time::advance(Duration::from_millis(200)).await;
+        tokio::task::yield_now().await;
+        assert!(event_loop.execute_cycle(&mut state).await.is_some());
+        assert_eq!(state, [1, 0]);
+        assert!(event_loop.async_tasks.is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{net::IpAddr, path::PathBuf};
 
 use clap::{ArgAction, Parser};
 
@@ -9,12 +9,26 @@ pub struct Args {
     /// Override the port to host the web-ui and API on
     #[arg(short, long)]
     pub port: Option<u16>,
+    /// Override the address to host the web-ui and API on, e.g. 0.0.0.0 to
+    /// allow access from other machines
+    #[arg(long)]
+    pub bind: Option<IpAddr>,
     /// Override the config file to use
     #[arg(short, long)]
     pub config: Option<String>,
     /// Override the playerlist to use
     #[arg(long)]
     pub playerlist: Option<String>,
+    /// Import marked players from a TF2 Bot Detector playerlist on startup
+    #[arg(long)]
+    pub import_tf2bd: Option<PathBuf>,
+    /// Export marked players to a TF2 Bot Detector playerlist, then exit
+    #[arg(long)]
+    pub export_tf2bd: Option<PathBuf>,
+    /// Load friends lists from a JSON file mapping SteamIDs to their friends,
+    /// instead of only fetching them from the Steam API
+    #[arg(long)]
+    pub friend_graph: Option<PathBuf>,
     /// Override the default tf2 directory
     #[arg(short = 'd', long)]
     pub tf2_dir: Option<String>,
@@ -36,6 +50,9 @@ pub struct Args {
     /// Override the default masterbase endpoint
     #[arg(long)]
     pub mb_host: Option<String>,
+    /// Read the whole console.log on startup instead of only new lines
+    #[arg(long, action=ArgAction::SetTrue, default_value_t=false)]
+    pub replay_console_log: bool,
     /// Launch the web-ui in the default browser on startup
     #[arg(long, action=ArgAction::SetTrue, default_value_t=false)]
     pub autolaunch_ui: bool,
@@ -52,6 +69,19 @@ pub struct Args {
     #[arg(long, action=ArgAction::SetTrue, default_value_t=false)]
     pub print_votes: bool,
 
+    /// Enable API endpoints intended for debugging, such as sending test events
+    #[arg(long, action=ArgAction::SetTrue, default_value_t=false)]
+    pub debug_endpoints: bool,
+
+    /// Add any missing TF2 launch options to Steam's config. Steam must be
+    /// closed for this to work.
+    #[arg(long, action=ArgAction::SetTrue, default_value_t=false)]
+    pub fix_launch_options: bool,
+    /// Print the launch options --fix-launch-options would set without
+    /// changing anything
+    #[arg(long, action=ArgAction::SetTrue, default_value_t=false, requires = "fix_launch_options")]
+    pub dry_run: bool,
+
     /// Serve web-ui files from this directory
     #[arg(short, long)]
     pub web_dir: Option<PathBuf>,
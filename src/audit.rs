@@ -0,0 +1,82 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use steamid_ng::SteamID;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    player::serialize_maybe_steamid_as_string,
+    settings::{ConfigFilesError, Settings},
+};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditAction {
+    Kick,
+    Report,
+}
+
+/// A single line in the audit log, recording an action taken against another
+/// player.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub action: AuditAction,
+    #[serde(serialize_with = "serialize_maybe_steamid_as_string")]
+    pub steamid: Option<SteamID>,
+    pub reason: String,
+    pub outcome: String,
+}
+
+impl AuditEntry {
+    #[must_use]
+    pub fn new(action: AuditAction, steamid: Option<SteamID>, reason: impl Display) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            action,
+            steamid,
+            reason: reason.to_string(),
+            outcome: String::new(),
+        }
+    }
+}
+
+/// Where the audit log should be written, which is in the config directory
+/// unless the user has chosen somewhere else.
+///
+/// # Errors
+/// If no path has been set and the config directory could not be located
+pub fn audit_log_path(settings: &Settings) -> Result<PathBuf, ConfigFilesError> {
+    settings.audit_log_path().map_or_else(
+        || Settings::locate_config_directory().map(|dir| dir.join("audit.log")),
+        |path| Ok(path.to_path_buf()),
+    )
+}
+
+/// Appends an entry to the audit log. Failures are logged rather than
+/// returned since there's nothing else the caller could do about them.
+pub async fn record(path: &Path, entry: &AuditEntry) {
+    if let Err(e) = append(path, entry).await {
+        tracing::error!("Failed to write to audit log {path:?}: {e}");
+    }
+}
+
+async fn append(path: &Path, entry: &AuditEntry) -> Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use event_loop::{try_get, Handled, Is, MessageHandler};
+
+use crate::{
+    events::{InternalPreferences, Preferences},
+    player::SteamInfo,
+    settings::{ConfigFilesError, Settings},
+    state::MACState,
+    steam_api::ProfileLookupResult,
+};
+
+/// Locates (and creates) the directory that downloaded avatars are stored in.
+///
+/// # Errors
+/// If the config directory could not be located or the avatar directory could
+/// not be created.
+pub fn locate_avatar_cache_directory() -> Result<PathBuf, ConfigFilesError> {
+    let dir = Settings::locate_config_directory()?.join("avatars");
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| ConfigFilesError::IO(dir.to_string_lossy().into(), e))?;
+    Ok(dir)
+}
+
+/// Where the avatar with the given hash is (or would be) stored in the cache.
+/// Returns `None` if the hash is not a valid avatar hash, so it is safe to call
+/// with untrusted input.
+#[must_use]
+pub fn avatar_path(dir: &Path, hash: &str) -> Option<PathBuf> {
+    if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some(dir.join(format!("{hash}.jpg")))
+}
+
+// Handlers *************************
+
+/// Downloads the avatars of any looked up profiles into a local cache so the
+/// UI doesn't need to fetch them from the Steam CDN every time.
+pub struct AvatarCache {
+    requested: HashSet<String>,
+}
+
+impl AvatarCache {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            requested: HashSet::new(),
+        }
+    }
+
+    fn cache_avatars<'a, M>(
+        &mut self,
+        infos: impl IntoIterator<Item = &'a SteamInfo>,
+    ) -> Option<Handled<M>> {
+        let dir = match locate_avatar_cache_directory() {
+            Ok(dir) => dir,
+            Err(e) => {
+                tracing::error!("Couldn't open avatar cache: {e}");
+                return Handled::none();
+            }
+        };
+
+        Handled::multiple(infos.into_iter().filter_map(|si| {
+            let path = avatar_path(&dir, &si.pfp_hash)?;
+            if path.is_file() || !self.requested.insert(si.pfp_hash.clone()) {
+                return None;
+            }
+
+            let url = si.pfp_url.clone();
+            Some(Handled::future(async move {
+                if let Err(e) = download_avatar(&url, &path).await {
+                    tracing::error!("Failed to cache avatar {url}: {e:?}");
+                }
+                None
+            }))
+        }))
+    }
+}
+
+impl Default for AvatarCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<IM, OM> MessageHandler<MACState, IM, OM> for AvatarCache
+where
+    IM: Is<ProfileLookupResult> + Is<Preferences>,
+{
+    fn handle_message(&mut self, state: &MACState, message: &IM) -> Option<Handled<OM>> {
+        // Cache everything we already know about once the option is enabled
+        if let Some(Preferences {
+            internal:
+                Some(InternalPreferences {
+                    cache_avatars: Some(true),
+                    ..
+                }),
+            external: _,
+        }) = try_get(message)
+        {
+            return self.cache_avatars(state.players.steam_info.values());
+        }
+
+        if !state.settings.cache_avatars() {
+            return Handled::none();
+        }
+
+        let ProfileLookupResult(Ok(results), _) = try_get::<ProfileLookupResult>(message)? else {
+            return Handled::none();
+        };
+
+        self.cache_avatars(results.iter().filter_map(|(_, r)| r.as_ref().ok()))
+    }
+}
+
+// Utility ***************************************
+
+async fn download_avatar(url: &str, path: &Path) -> Result<()> {
+    tracing::debug!("Caching avatar {url}");
+
+    let response = reqwest::get(url).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Server returned {}", response.status()));
+    }
+
+    let bytes = response.bytes().await?;
+    tokio::fs::write(path, bytes)
+        .await
+        .with_context(|| format!("Failed to write {path:?}"))?;
+
+    Ok(())
+}
@@ -1,25 +1,40 @@
 use std::{
+    collections::HashMap,
     fmt::{Debug, Display},
     io::ErrorKind,
-    sync::Arc,
-    time::Duration,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use event_loop::{try_get, Handled, Is, MessageHandler};
 use rcon::Connection;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use steamid_ng::SteamID;
 use thiserror::Error;
-use tokio::{net::TcpStream, sync::Mutex, time::timeout};
+use tokio::{
+    net::TcpStream,
+    sync::{Mutex, MutexGuard},
+    time::timeout,
+};
 
-use super::console::RawConsoleOutput;
+use super::console::{ConsoleOutput, RawConsoleOutput};
 use crate::{
-    events::Refresh,
-    player::{PlayerState, Team},
+    audit::{self, AuditAction, AuditEntry},
+    demo::{DemoEvent, DemoMessage},
+    events::{Refresh, RefreshReason},
+    io::regexes::ServerIP,
+    player::{GameInfo, PlayerState, Team},
     player_records::Verdict,
     state::MACState,
+    stats::SessionStats,
+    warnings::{self, AppWarning},
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum KickReason {
     None,
@@ -86,8 +101,68 @@ enum ErrorState {
     Current(Error),
 }
 
+/// Whether commands can currently be sent to the game over RCON
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum RconState {
+    Connected,
+    Disconnected,
+    AuthFailed,
+}
+
 // Messages ***************************
 
+/// The RCON connection has changed state since it was last reported
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RconStateChanged {
+    pub state: RconState,
+}
+impl<S> event_loop::Message<S> for RconStateChanged {}
+
+/// Parts of the game's response to a kick vote which mean it couldn't be
+/// called right now, going by its `GameUI_vote_failed_*` messages
+const KICK_FAILURE_PHRASES: &[&str] = &[
+    "cannot callvote",
+    "cannot call vote",
+    "cannot call a vote",
+    "can't call vote",
+    "can't call a vote",
+    "cannot call another vote",
+    "can't call another vote",
+    "another vote is in progress",
+    "vote is already in progress",
+    "not allowed to call votes",
+    "has disabled that issue",
+    "cannot call this vote",
+    "cannot kick",
+];
+
+/// The game's response to a [`Command::Kick`]
+#[derive(Debug, Clone)]
+pub struct KickResult {
+    /// The uid of the player the kick was called on
+    pub userid: String,
+    pub output: String,
+}
+impl<S> event_loop::Message<S> for KickResult {}
+
+impl KickResult {
+    /// The line of the response saying why the vote couldn't be called, if it
+    /// failed
+    #[must_use]
+    pub fn failure(&self) -> Option<&str> {
+        self.output.lines().map(str::trim).find(|l| {
+            let l = l.to_lowercase();
+            KICK_FAILURE_PHRASES.iter().any(|p| l.contains(p))
+        })
+    }
+}
+
+/// Emitted once TF2 has been found running after the client started
+/// without it.
+#[derive(Debug, Clone, Copy)]
+pub struct Tf2Launched;
+impl<S> event_loop::Message<S> for Tf2Launched {}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum Command {
@@ -95,6 +170,7 @@ pub enum Command {
     Status,
     Say(String),
     SayTeam(String),
+    SayParty(String),
     Kick {
         /// The uid of the player as returned by [`Command::Status`] or
         /// [`Command::G15`]
@@ -103,6 +179,9 @@ pub enum Command {
         reason: KickReason,
     },
     Custom(String),
+    /// Does nothing. Takes the place of commands which were filtered out so
+    /// the rest of a sequence still runs as expected.
+    Noop,
 }
 impl<S> event_loop::Message<S> for Command {}
 
@@ -114,36 +193,57 @@ impl Display for Command {
             Self::Kick { player, reason } => {
                 write!(f, "callvote kick \"{player} {reason}\"")
             }
-            Self::Say(message) => write!(f, "say \"{message}\""),
-            Self::SayTeam(message) => write!(f, "say_team \"{message}\""),
+            Self::Say(message) => write!(f, "say \"{}\"", chat_message(message)),
+            Self::SayTeam(message) => write!(f, "say_team \"{}\"", chat_message(message)),
+            Self::SayParty(message) => write!(f, "say_party \"{}\"", chat_message(message)),
             Self::Custom(command) => write!(f, "{command}"),
+            Self::Noop => Ok(()),
         }
     }
 }
 
+/// The console has no way to escape quotes, so any in the message are swapped
+/// for single quotes to stop them ending the argument early. Line breaks would
+/// end the command, so they're replaced too.
+fn chat_message(message: &str) -> String {
+    message.replace('"', "'").replace(['\r', '\n'], " ")
+}
+
 // Handlers ****************************
 
 pub struct CommandManager {
     inner: Arc<Mutex<CommandManagerInner>>,
     refresh_status: bool,
+    /// How many prioritised commands are waiting to be sent
+    priority_waiting: Arc<AtomicUsize>,
+    /// The connection state as last updated by the inner manager
+    rcon_state: Arc<std::sync::Mutex<RconState>>,
+    /// The connection state as last reported with [`RconStateChanged`]
+    reported_rcon_state: RconState,
+    /// Whether RCON is being held off until TF2 launches
+    waiting_for_tf2: bool,
 }
 
 struct CommandManagerInner {
     connection: Option<Connection<TcpStream>>,
     password: String,
     port: u16,
+    /// When the last command was sent, to keep them spaced out
+    last_command: Option<Instant>,
+    rcon_state: Arc<std::sync::Mutex<RconState>>,
 
     current_err_state: ErrorState,
     previous_err_state: ErrorState,
 }
 
 impl CommandManagerInner {
-    async fn run_command<M: Is<RawConsoleOutput>>(
+    async fn run_command(
         &mut self,
-        cmd: Command,
+        cmd: &Command,
         port: u16,
         password: String,
-    ) -> Option<M> {
+        min_interval: Duration,
+    ) -> Option<String> {
         let needs_reconnect = password != self.password
             || port != self.port
             || match self.current_err_state {
@@ -165,6 +265,7 @@ impl CommandManagerInner {
                     // on what the error was. Since we are now connected, if the error state
                     // indicates never connected, we can assume first time
                     // connect Otherwise this is a reconnect.
+                    warnings::resolve(&AppWarning::RconAuthFailed);
                     match self.current_err_state {
                         ErrorState::Current(_) => {
                             tracing::info!("Succesfully reconnected to RCon");
@@ -176,8 +277,14 @@ impl CommandManagerInner {
                     };
                     std::mem::swap(&mut self.current_err_state, &mut self.previous_err_state);
                     self.current_err_state = ErrorState::Okay;
+                    self.set_rcon_state(RconState::Connected);
                 }
                 Err(e) => {
+                    self.set_rcon_state(if matches!(e, Error::Rcon(rcon::Error::Auth)) {
+                        RconState::AuthFailed
+                    } else {
+                        RconState::Disconnected
+                    });
                     std::mem::swap(&mut self.current_err_state, &mut self.previous_err_state);
                     self.current_err_state = ErrorState::Current(e);
 
@@ -193,6 +300,9 @@ impl CommandManagerInner {
                                     "{e} (This is expected behaviour if TF2 is not open)"
                                 );
                             }
+                            ErrorState::Current(Error::Rcon(rcon::Error::Auth)) => {
+                                warnings::raise(AppWarning::RconAuthFailed);
+                            }
                             // We have entered an error state from some other state, or the error
                             // state has changed. Report it!
                             ErrorState::Current(err) => {
@@ -206,6 +316,15 @@ impl CommandManagerInner {
         }
 
         if let Some(rcon) = &mut self.connection {
+            // TF2 drops output if commands arrive too quickly
+            if let Some(last) = self.last_command {
+                let wait = (last + min_interval).saturating_duration_since(Instant::now());
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                }
+            }
+            self.last_command = Some(Instant::now());
+
             tracing::debug!("Running command \"{}\"", cmd);
             let result = rcon.cmd(&format!("{cmd}")).await.map_err(|e| {
                 self.connection = None;
@@ -214,11 +333,12 @@ impl CommandManagerInner {
 
             match result {
                 Ok(out) => {
-                    return Some(RawConsoleOutput(out).into());
+                    return Some(out);
                 }
                 Err(e) => {
                     self.previous_err_state = ErrorState::Okay;
                     self.current_err_state = ErrorState::Current(e.into());
+                    self.set_rcon_state(RconState::Disconnected);
                 }
             }
         }
@@ -226,6 +346,10 @@ impl CommandManagerInner {
         None
     }
 
+    fn set_rcon_state(&self, state: RconState) {
+        *self.rcon_state.lock().expect("RCON state lock poisoned") = state;
+    }
+
     async fn try_reconnect(&mut self) -> Result<(), Error> {
         if self.current_err_state == ErrorState::Never {
             tracing::debug!("Attempting to connect to RCon");
@@ -259,13 +383,15 @@ impl CommandManagerInner {
 }
 
 impl CommandManagerInner {
-    fn new() -> Self {
+    fn new(rcon_state: Arc<std::sync::Mutex<RconState>>) -> Self {
         Self {
             connection: None,
             current_err_state: ErrorState::Never,
             previous_err_state: ErrorState::Never,
             password: String::new(),
             port: 27015,
+            last_command: None,
+            rcon_state,
         }
     }
 }
@@ -273,25 +399,131 @@ impl CommandManagerInner {
 impl CommandManager {
     #[must_use]
     pub fn new() -> Self {
+        let rcon_state = Arc::new(std::sync::Mutex::new(RconState::Disconnected));
         Self {
-            inner: Arc::new(Mutex::new(CommandManagerInner::new())),
+            inner: Arc::new(Mutex::new(CommandManagerInner::new(rcon_state.clone()))),
             refresh_status: false,
+            priority_waiting: Arc::new(AtomicUsize::new(0)),
+            rcon_state,
+            reported_rcon_state: RconState::Disconnected,
+            waiting_for_tf2: false,
+        }
+    }
+
+    /// Don't connect to RCON until [`Tf2Launched`] is received. Commands
+    /// sent in the meantime are refused.
+    #[must_use]
+    pub const fn waiting_for_tf2(mut self, waiting: bool) -> Self {
+        self.waiting_for_tf2 = waiting;
+        self
+    }
+
+    /// Reports the RCON connection state if it has changed since it was last
+    /// reported.
+    ///
+    /// # Panics
+    /// If the state lock was poisoned
+    fn rcon_state_changed<OM: Is<RconStateChanged>>(
+        &mut self,
+        stats: &SessionStats,
+    ) -> Option<Handled<OM>> {
+        let state = *self.rcon_state.lock().expect("RCON state lock poisoned");
+        if state == self.reported_rcon_state {
+            return None;
+        }
+
+        if state == RconState::Connected {
+            stats.add_rcon_connection();
         }
+        self.reported_rcon_state = state;
+        Handled::single(RconStateChanged { state })
     }
 
-    fn run_command<OM: Is<RawConsoleOutput>>(
+    /// Returns an event which runs the command. If an audit entry is provided,
+    /// it is written to the audit log along with whether the command was sent.
+    fn run_command<OM: Is<RawConsoleOutput> + Is<KickResult>>(
         &mut self,
+        state: &MACState,
         command: &Command,
-        port: u16,
-        password: String,
+        priority: bool,
+        audit: Option<(PathBuf, AuditEntry)>,
     ) -> Option<Handled<OM>> {
         let inner = self.inner.clone();
+        let priority_waiting = self.priority_waiting.clone();
         let cmd = command.clone();
+        let port = state.settings.rcon_port();
+        let password = state.settings.rcon_password().to_owned();
+        let min_interval = Duration::from_millis(state.settings.rcon_command_interval_ms());
+        let priority = priority && state.settings.prioritize_rcon_commands();
         Handled::future(async move {
-            let mut inner = inner.lock().await;
-            inner.run_command(cmd, port, password).await
+            let mut inner = Self::wait_for_turn(&inner, &priority_waiting, priority).await;
+            let out = inner.run_command(&cmd, port, password, min_interval).await;
+            drop(inner);
+
+            if let Some((path, mut entry)) = audit {
+                entry.outcome = if out.is_some() { "sent" } else { "failed" }.into();
+                audit::record(&path, &entry).await;
+            }
+
+            // Kick responses go to whoever is trying to kick first, and are
+            // passed on as console output once they've been seen
+            match cmd {
+                Command::Kick { player, .. } => out.map(|output| {
+                    KickResult {
+                        userid: player,
+                        output,
+                    }
+                    .into()
+                }),
+                _ => out.map(|out| RawConsoleOutput(out).into()),
+            }
         })
     }
+
+    /// Waits until a command can be sent. Commands without priority give way
+    /// to any prioritised ones waiting, otherwise they're sent in order.
+    async fn wait_for_turn<'a>(
+        inner: &'a Mutex<CommandManagerInner>,
+        priority_waiting: &AtomicUsize,
+        priority: bool,
+    ) -> MutexGuard<'a, CommandManagerInner> {
+        if priority {
+            priority_waiting.fetch_add(1, Ordering::SeqCst);
+            let guard = inner.lock().await;
+            priority_waiting.fetch_sub(1, Ordering::SeqCst);
+            return guard;
+        }
+
+        loop {
+            let guard = inner.lock().await;
+            if priority_waiting.load(Ordering::SeqCst) == 0 {
+                return guard;
+            }
+
+            // Go to the back of the queue, behind the prioritised commands
+            drop(guard);
+            tokio::task::yield_now().await;
+        }
+    }
+
+    /// Prepares an audit entry for kick commands
+    fn audit_kick(state: &MACState, command: &Command) -> Option<(PathBuf, AuditEntry)> {
+        let Command::Kick { player, reason } = command else {
+            return None;
+        };
+
+        let path = audit::audit_log_path(&state.settings)
+            .map_err(|e| tracing::error!("Couldn't locate audit log: {e}"))
+            .ok()?;
+        let steamid = state
+            .players
+            .game_info
+            .iter()
+            .find(|(_, gi)| gi.userid == *player)
+            .map(|(&s, _)| s);
+
+        Some((path, AuditEntry::new(AuditAction::Kick, steamid, reason)))
+    }
 }
 
 impl Default for CommandManager {
@@ -302,40 +534,275 @@ impl Default for CommandManager {
 
 impl<IM, OM> MessageHandler<MACState, IM, OM> for CommandManager
 where
-    IM: Is<Command> + Is<Refresh>,
-    OM: Is<RawConsoleOutput>,
+    IM: Is<Command> + Is<Refresh> + Is<Tf2Launched> + Is<KickResult>,
+    OM: Is<RawConsoleOutput> + Is<RconStateChanged> + Is<KickResult>,
 {
     fn handle_message(
         &mut self,
         state: &MACState,
         message: &IM,
     ) -> Option<event_loop::Handled<OM>> {
-        let port = state.settings.rcon_port();
-        let pwd = state.settings.rcon_password();
+        if let Some(KickResult { output, .. }) = try_get(message) {
+            return Handled::single(RawConsoleOutput(output.clone()));
+        }
+
+        if try_get::<Tf2Launched>(message).is_some() {
+            tracing::info!("TF2 has launched.");
+            self.waiting_for_tf2 = false;
+            return None;
+        }
+
+        if self.waiting_for_tf2 {
+            if let Some(command) = try_get::<Command>(message) {
+                if !matches!(command, Command::Noop) {
+                    tracing::warn!("Not running \"{command}\", TF2 hasn't launched yet.");
+                }
+            }
+            return None;
+        }
+
+        // Commands run in the background, so changes to the connection are
+        // picked up the next time something is handled
+        let handled = self.handle_command(state, message);
+        match self.rcon_state_changed(&state.session_stats) {
+            Some(changed) => Handled::multiple([Some(changed), handled]),
+            None => handled,
+        }
+    }
+}
+
+impl CommandManager {
+    fn handle_command<IM, OM>(&mut self, state: &MACState, message: &IM) -> Option<Handled<OM>>
+    where
+        IM: Is<Command> + Is<Refresh>,
+        OM: Is<RawConsoleOutput> + Is<KickResult>,
+    {
+        if let Some(refresh) = try_get::<Refresh>(message) {
+            // Alternate between the two on the timer, but get everything
+            // straight away when asked to or when the server changes.
+            if refresh.reason != RefreshReason::Timer {
+                return Handled::multiple([
+                    self.run_command(state, &Command::Status, true, None),
+                    self.run_command(state, &Command::G15, true, None),
+                ]);
+            }
 
-        if try_get::<Refresh>(message).is_some() {
             self.refresh_status = !self.refresh_status;
             if self.refresh_status {
-                return self.run_command(&Command::Status, port, pwd.to_owned());
+                return self.run_command(state, &Command::Status, false, None);
+            }
+            return self.run_command(state, &Command::G15, false, None);
+        }
+
+        let command = try_get::<Command>(message)?;
+        match command {
+            Command::Noop => {
+                tracing::debug!("Skipping no-op command");
+                return None;
+            }
+            Command::Kick { .. } => state.session_stats.add_kick(),
+            _ => {}
+        }
+        let audit = Self::audit_kick(state, command);
+        self.run_command(state, command, true, audit)
+    }
+}
+
+/// How long to wait before trying to kick the same bot again
+const AUTOKICK_COOLDOWN: Duration = Duration::from_secs(30);
+/// The longest to wait before trying again when the kick keeps failing
+const MAX_AUTOKICK_COOLDOWN: Duration = Duration::from_secs(300);
+/// How long a vote stays open in TF2, `sv_vote_timer_duration`
+const VOTE_DURATION: Duration = Duration::from_secs(15);
+
+struct KickAttempt {
+    /// The uid of the bot when the kick was attempted, so another player
+    /// taking their slot isn't mistaken for them
+    userid: String,
+    at: Instant,
+    /// How many attempts in a row the game refused to call the vote
+    failures: u32,
+}
+
+impl KickAttempt {
+    /// Waits longer after each failure, so a bot that can't be kicked right
+    /// now isn't retried every cycle
+    fn cooldown(&self) -> Duration {
+        AUTOKICK_COOLDOWN
+            .saturating_mul(2u32.saturating_pow(self.failures))
+            .min(MAX_AUTOKICK_COOLDOWN)
+    }
+}
+
+pub struct DumbAutoKick {
+    user_team: Option<Team>,
+    /// The last attempt to kick each bot that is still around
+    last_attempt: HashMap<SteamID, KickAttempt>,
+    /// The shortest time between kick votes, or zero to kick every bot at once
+    vote_interval: Duration,
+    last_vote: Option<Instant>,
+    /// When the vote currently running on the server will have finished
+    vote_ends: Option<Instant>,
+}
+
+impl DumbAutoKick {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            user_team: None,
+            last_attempt: HashMap::new(),
+            vote_interval: Duration::ZERO,
+            last_vote: None,
+            vote_ends: None,
+        }
+    }
+
+    /// Someone else called a vote, so there's no point calling one until it's
+    /// over
+    fn vote_started(&mut self, now: Instant) {
+        self.vote_ends = Some(now + VOTE_DURATION);
+    }
+
+    /// Tracks the user's current team. Kick attempts made while the user was
+    /// on another team are forgotten, so every bot on the new team is
+    /// reconsidered straight away.
+    fn update_user_team(&mut self, team: Team) {
+        if self.user_team.is_some_and(|t| t != team) {
+            tracing::debug!("User changed team to {team:?}, re-evaluating autokicks.");
+            self.last_attempt.clear();
+        }
+
+        self.user_team = Some(team);
+    }
+
+    /// Picks the bots which should be kicked now, returning their userids
+    fn bots_to_kick<'a>(
+        &mut self,
+        bots: impl IntoIterator<Item = (SteamID, &'a GameInfo)>,
+        now: Instant,
+    ) -> Vec<String> {
+        let Some(user_team) = self.user_team else {
+            return Vec::new();
+        };
+        let bots: Vec<_> = bots.into_iter().collect();
+
+        // Start over with any bot that has left, or whose slot was taken by
+        // someone else
+        self.last_attempt
+            .retain(|s, a| bots.iter().any(|(b, gi)| b == s && gi.userid == a.userid));
+
+        if self.vote_ends.is_some_and(|t| now < t) {
+            return Vec::new();
+        }
+
+        // Only one vote can run at a time, so with an interval set just one
+        // bot is kicked per vote
+        let limit = if self.vote_interval.is_zero() {
+            usize::MAX
+        } else if self
+            .last_vote
+            .is_some_and(|t| now.duration_since(t) < self.vote_interval)
+        {
+            return Vec::new();
+        } else {
+            1
+        };
+
+        let kicks: Vec<String> = bots
+            .into_iter()
+            .filter(|(_, gi)| {
+                gi.team == user_team
+                    && gi.team != Team::Unassigned
+                    && gi.state == PlayerState::Active
+            })
+            .filter_map(|(s, gi)| {
+                let failures = match self.last_attempt.get(&s) {
+                    Some(a) if now.duration_since(a.at) < a.cooldown() => return None,
+                    Some(a) => a.failures,
+                    None => 0,
+                };
+
+                self.last_attempt.insert(
+                    s,
+                    KickAttempt {
+                        userid: gi.userid.clone(),
+                        at: now,
+                        failures,
+                    },
+                );
+                Some(gi.userid.clone())
+            })
+            .take(limit)
+            .collect();
+
+        if !kicks.is_empty() {
+            self.last_vote = Some(now);
+        }
+
+        kicks
+    }
+
+    /// Backs off from bots the game wouldn't let us call a kick on
+    fn handle_kick_result(&mut self, result: &KickResult) {
+        let Some(attempt) = self
+            .last_attempt
+            .values_mut()
+            .find(|a| a.userid == result.userid)
+        else {
+            return;
+        };
+
+        if let Some(reason) = result.failure() {
+            if reason
+                .to_lowercase()
+                .contains("vote is already in progress")
+            {
+                self.vote_ends = Some(Instant::now() + VOTE_DURATION);
             }
-            return self.run_command(&Command::G15, port, pwd.to_owned());
+            attempt.failures = attempt.failures.saturating_add(1);
+            tracing::debug!(
+                "Couldn't call kick on {}, waiting {}s before trying again: {reason}",
+                result.userid,
+                attempt.cooldown().as_secs()
+            );
+        } else {
+            attempt.failures = 0;
         }
+    }
+}
 
-        self.run_command(try_get::<Command>(message)?, port, pwd.to_owned())
+impl Default for DumbAutoKick {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-pub struct DumbAutoKick;
 impl<IM, OM> MessageHandler<MACState, IM, OM> for DumbAutoKick
 where
-    IM: Is<Refresh>,
+    IM: Is<Refresh> + Is<KickResult> + Is<DemoMessage>,
     OM: Is<Command>,
 {
     fn handle_message(&mut self, state: &MACState, message: &IM) -> Option<Handled<OM>> {
-        let _ = try_get(message)?;
+        if let Some(result) = try_get::<KickResult>(message) {
+            self.handle_kick_result(result);
+            return None;
+        }
+
+        if let Some(demo) = try_get::<DemoMessage>(message) {
+            if matches!(
+                demo.event,
+                DemoEvent::VoteStarted(_) | DemoEvent::VoteOptions(_)
+            ) {
+                self.vote_started(Instant::now());
+            }
+            return None;
+        }
+
+        let _ = try_get::<Refresh>(message)?;
         if !state.settings.autokick_bots() {
             return None;
         }
+        self.vote_interval = state.settings.autokick_vote_interval();
 
         let user_team = state
             .players
@@ -343,8 +810,9 @@ where
             .as_ref()
             .and_then(|s| state.players.game_info.get(s))
             .map(|gi| gi.team)?;
+        self.update_user_team(user_team);
 
-        let to_kick = state
+        let bots = state
             .players
             .connected
             .iter()
@@ -355,19 +823,339 @@ where
                     .get(*s)
                     .is_some_and(|r| r.verdict() == Verdict::Bot)
             })
-            .filter_map(|s| state.players.game_info.get(s))
-            .filter(|gi| {
-                gi.team == user_team
-                    && gi.team != Team::Unassigned
-                    && gi.state == PlayerState::Active
-            })
-            .map(|gi| gi.userid.clone())
+            .filter_map(|&s| state.players.game_info.get(&s).map(|gi| (s, gi)));
+
+        let to_kick = self
+            .bots_to_kick(bots, Instant::now())
+            .into_iter()
             .map(|id| Command::Kick {
                 player: id,
-                reason: KickReason::Cheating,
+                reason: state.settings.autokick_reason(),
             })
             .map(|c| Handled::single(c));
 
         Handled::multiple(to_kick)
     }
 }
+
+/// Requests a [`Refresh`] whenever the user joins a different server, so the
+/// new player list is fetched without waiting for the timer.
+pub struct RefreshOnServerChange {
+    last_server: Option<String>,
+}
+
+impl RefreshOnServerChange {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { last_server: None }
+    }
+}
+
+impl Default for RefreshOnServerChange {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<IM, OM> MessageHandler<MACState, IM, OM> for RefreshOnServerChange
+where
+    IM: Is<ConsoleOutput>,
+    OM: Is<Refresh>,
+{
+    fn handle_message(&mut self, _: &MACState, message: &IM) -> Option<Handled<OM>> {
+        let ConsoleOutput::ServerIP(ServerIP(ip)) = try_get(message)? else {
+            return None;
+        };
+        if self.last_server.as_ref() == Some(ip) {
+            return None;
+        }
+        self.last_server = Some(ip.clone());
+
+        Handled::single(Refresh {
+            reason: RefreshReason::ServerChange,
+        })
+    }
+}
+
+/// How long to wait between each auto-exec command
+const AUTOEXEC_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Commands which could be used to do something harmful if they made their
+/// way into the auto-exec list
+const AUTOEXEC_BLOCKED: &[&str] = &[
+    "alias",
+    "bind",
+    "connect",
+    "disconnect",
+    "exec",
+    "exit",
+    "quit",
+    "rcon",
+    "rcon_password",
+    "retry",
+];
+
+/// Checks that an auto-exec command is a single, allowed console command.
+/// Returns the trimmed command if so.
+fn sanitize_autoexec(command: &str) -> Option<&str> {
+    let command = command.trim();
+    if command.is_empty() || command.contains([';', '\n', '\r', '"']) {
+        return None;
+    }
+
+    let name = command.split_whitespace().next()?.to_lowercase();
+    if AUTOEXEC_BLOCKED.contains(&name.as_str()) {
+        return None;
+    }
+
+    Some(command)
+}
+
+/// Turns the user's auto-exec commands into the commands to run. Disallowed
+/// commands are replaced with [`Command::Noop`] rather than removed, so the
+/// ones after them still run at the same point in the sequence.
+fn autoexec_sequence(commands: &[String]) -> Vec<Command> {
+    commands
+        .iter()
+        .map(|c| {
+            sanitize_autoexec(c).map_or_else(
+                || {
+                    tracing::warn!("Not running disallowed auto-exec command \"{c}\"");
+                    Command::Noop
+                },
+                |sanitized| Command::Custom(sanitized.to_string()),
+            )
+        })
+        .collect()
+}
+
+/// Runs the user's auto-exec commands once each time they join a new server.
+pub struct AutoExec {
+    last_server: Option<String>,
+}
+
+impl AutoExec {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { last_server: None }
+    }
+}
+
+impl Default for AutoExec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<IM, OM> MessageHandler<MACState, IM, OM> for AutoExec
+where
+    IM: Is<ConsoleOutput>,
+    OM: Is<Command>,
+{
+    fn handle_message(&mut self, state: &MACState, message: &IM) -> Option<Handled<OM>> {
+        let ConsoleOutput::ServerIP(ServerIP(ip)) = try_get(message)? else {
+            return None;
+        };
+        if self.last_server.as_ref() == Some(ip) {
+            return None;
+        }
+        self.last_server = Some(ip.clone());
+
+        let commands = autoexec_sequence(state.settings.autoexec_commands());
+        if commands.iter().all(|c| matches!(c, Command::Noop)) {
+            return None;
+        }
+
+        tracing::info!(
+            "Joined {ip}, running {} auto-exec commands.",
+            commands.len()
+        );
+        // Space the commands out so we don't flood RCON
+        Handled::multiple(commands.into_iter().zip(1u32..).map(|(command, i)| {
+            Handled::delayed(AUTOEXEC_INTERVAL * i, async move { Some(command.into()) })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unreadable_literal)]
+
+    use std::time::{Duration, Instant};
+
+    use steamid_ng::SteamID;
+
+    use super::{
+        autoexec_sequence, sanitize_autoexec, Command, DumbAutoKick, KickResult, AUTOKICK_COOLDOWN,
+    };
+    use crate::player::{GameInfo, Team};
+
+    fn player(team: Team, userid: &str) -> GameInfo {
+        let mut gi = GameInfo::default();
+        gi.team = team;
+        gi.userid = userid.into();
+        gi
+    }
+
+    #[test]
+    pub fn chat_quotes_escaped() {
+        assert_eq!(
+            Command::SayParty("a \"bot\"\nhere".into()).to_string(),
+            "say_party \"a 'bot' here\""
+        );
+        assert_eq!(Command::Say("hi".into()).to_string(), "say \"hi\"");
+    }
+
+    #[test]
+    pub fn autokick_team_switch() {
+        let a = SteamID::from(76561197960265729);
+        let b = SteamID::from(76561197960265730);
+        let now = Instant::now();
+        let mut autokick = DumbAutoKick::new();
+
+        // Only the bot on our team is kicked, and only once
+        let bot_a = player(Team::Red, "1");
+        let bot_b = player(Team::Blu, "2");
+        autokick.update_user_team(Team::Red);
+        assert_eq!(
+            autokick.bots_to_kick([(a, &bot_a), (b, &bot_b)], now),
+            ["1"]
+        );
+        assert!(autokick
+            .bots_to_kick([(a, &bot_a), (b, &bot_b)], now)
+            .is_empty());
+
+        // Both bots end up on the team we switched to, so both should be kicked
+        // even though one was only just attempted
+        let bot_a = player(Team::Blu, "1");
+        autokick.update_user_team(Team::Blu);
+        let mut kicks = autokick.bots_to_kick([(a, &bot_a), (b, &bot_b)], now);
+        kicks.sort();
+        assert_eq!(kicks, ["1", "2"]);
+    }
+
+    #[test]
+    pub fn kick_failure() {
+        let result = |output: &str| KickResult {
+            userid: "1".into(),
+            output: output.into(),
+        };
+
+        for output in [
+            "Cannot call a vote while another vote is in progress.",
+            "You can't call another vote for 120 seconds.",
+            "Spectators are not allowed to call votes.",
+            "Server has disabled that issue.",
+            "Cannot kick server admin.",
+        ] {
+            assert_eq!(result(output).failure(), Some(output));
+        }
+
+        assert_eq!(
+            result("] callvote kick \"1 cheating\"\n  Cannot call vote: vote cooldown  ").failure(),
+            Some("Cannot call vote: vote cooldown")
+        );
+
+        // Other output mentioning waiting or being disallowed isn't a failure
+        for output in [
+            "",
+            "] callvote kick \"1 cheating\"",
+            "Player: wait for me",
+            "Custom crosshairs are not allowed on this server",
+        ] {
+            assert_eq!(result(output).failure(), None);
+        }
+    }
+
+    #[test]
+    pub fn autokick_backs_off_after_failure() {
+        let a = SteamID::from(76561197960265729);
+        let now = Instant::now();
+        let mut autokick = DumbAutoKick::new();
+        autokick.update_user_team(Team::Red);
+
+        let bot = player(Team::Red, "1");
+        assert_eq!(autokick.bots_to_kick([(a, &bot)], now), ["1"]);
+        autokick.handle_kick_result(&KickResult {
+            userid: "1".into(),
+            output: "Cannot callvote: vote cooldown".into(),
+        });
+
+        // The usual cooldown isn't enough after a failure
+        let later = now + AUTOKICK_COOLDOWN;
+        assert!(autokick.bots_to_kick([(a, &bot)], later).is_empty());
+        assert_eq!(
+            autokick.bots_to_kick([(a, &bot)], now + AUTOKICK_COOLDOWN * 2),
+            ["1"]
+        );
+
+        // Someone else taking the slot starts over
+        autokick.handle_kick_result(&KickResult {
+            userid: "1".into(),
+            output: "Cannot callvote".into(),
+        });
+        let new_bot = player(Team::Red, "2");
+        assert!(autokick.bots_to_kick([], later).is_empty());
+        assert_eq!(autokick.bots_to_kick([(a, &new_bot)], later), ["2"]);
+    }
+
+    #[test]
+    pub fn autokick_one_vote_at_a_time() {
+        let a = SteamID::from(76561197960265729);
+        let b = SteamID::from(76561197960265730);
+        let now = Instant::now();
+        let mut autokick = DumbAutoKick::new();
+        autokick.update_user_team(Team::Red);
+        autokick.vote_interval = Duration::from_secs(30);
+
+        let bot_a = player(Team::Red, "1");
+        let bot_b = player(Team::Red, "2");
+        assert_eq!(
+            autokick.bots_to_kick([(a, &bot_a), (b, &bot_b)], now).len(),
+            1
+        );
+        let later = now + Duration::from_secs(10);
+        assert!(autokick
+            .bots_to_kick([(a, &bot_a), (b, &bot_b)], later)
+            .is_empty());
+
+        // Nothing is called while someone else's vote is running
+        autokick.vote_started(later);
+        let during_vote = now + Duration::from_secs(20);
+        assert!(autokick
+            .bots_to_kick([(a, &bot_a), (b, &bot_b)], during_vote)
+            .is_empty());
+        assert_eq!(
+            autokick
+                .bots_to_kick([(a, &bot_a), (b, &bot_b)], now + Duration::from_secs(30))
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    pub fn autoexec_sanitize() {
+        assert_eq!(
+            sanitize_autoexec(" fov_desired 90 "),
+            Some("fov_desired 90")
+        );
+        assert_eq!(sanitize_autoexec("record mydemo"), Some("record mydemo"));
+        assert_eq!(sanitize_autoexec("fov_desired 90; quit"), None);
+        assert_eq!(sanitize_autoexec("RCON_PASSWORD hunter2"), None);
+        assert_eq!(sanitize_autoexec("bind x \"quit\""), None);
+        assert_eq!(sanitize_autoexec("   "), None);
+    }
+
+    #[test]
+    pub fn autoexec_filtered_command_is_noop() {
+        let commands = ["fov_desired 90", "quit", "cl_drawhud 1"].map(String::from);
+        let sequence: Vec<String> = autoexec_sequence(&commands)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        // The filtered command is still in the sequence, it just does nothing
+        assert_eq!(sequence, ["fov_desired 90", "", "cl_drawhud 1"]);
+        assert!(matches!(autoexec_sequence(&commands)[1], Command::Noop));
+    }
+}
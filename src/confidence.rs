@@ -0,0 +1,400 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use event_loop::{try_get, Handled, Is, MessageHandler};
+use serde::{Deserialize, Serialize};
+use steamid_ng::SteamID;
+
+use crate::{
+    events::{Refresh, UserUpdate, UserUpdates},
+    new_players::NewPlayers,
+    player::{serialize_steamid_as_string, Players, SteamInfo},
+    player_records::Verdict,
+    state::MACState,
+};
+
+/// The key in a player's `custom_data` that the confidence score is stored under
+pub const AUTO_CONFIDENCE_KEY: &str = "auto_confidence";
+
+/// The score at which a player is considered flagged by the heuristics
+pub const AUTO_FLAG_SCORE: u32 = 5;
+
+/// Accounts younger than this (in days) are considered new
+const NEW_ACCOUNT_DAYS: i64 = 60;
+
+/// A heuristic suggesting that a player may be a cheater or bot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Signal {
+    /// The Steam account was created recently
+    NewAccount,
+    /// The account has VAC or game bans on record
+    SteamBans,
+    /// The account is friends with someone marked as a cheater or bot
+    CheaterFriends,
+    /// The account was banned recently, see [`RecentBans`]
+    RecentBan,
+}
+
+impl Signal {
+    /// How much this signal contributes to the confidence score. Signals that
+    /// are harder to explain away are weighted more heavily.
+    #[must_use]
+    pub const fn weight(self) -> u32 {
+        match self {
+            Self::NewAccount => 1,
+            Self::SteamBans | Self::RecentBan => 2,
+            Self::CheaterFriends => 3,
+        }
+    }
+}
+
+/// How confident we are that a player is suspicious, based on how many
+/// heuristics agree. Stored in the player's `custom_data` under
+/// [`AUTO_CONFIDENCE_KEY`], and added to by each handler which looks for
+/// suspicious players. This is purely informational and never changes a
+/// player's verdict; that is left to the user.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoConfidence {
+    pub score: u32,
+    pub signals: Vec<Signal>,
+}
+
+impl AutoConfidence {
+    /// Reads the confidence stored in a player's `custom_data`, if any
+    #[must_use]
+    pub fn from_custom_data(custom_data: &serde_json::Value) -> Option<Self> {
+        serde_json::from_value(custom_data.get(AUTO_CONFIDENCE_KEY)?.clone()).ok()
+    }
+
+    /// Evaluates the heuristics which only need the information we already
+    /// have on a player.
+    #[must_use]
+    pub fn evaluate(players: &Players, steamid: SteamID) -> Vec<Signal> {
+        let mut signals = Vec::new();
+
+        if let Some(si) = players.steam_info.get(&steamid) {
+            let age_days = si
+                .time_created
+                .map(|created| (Utc::now().timestamp() - created) / (60 * 60 * 24));
+            if age_days.is_some_and(|days| days < NEW_ACCOUNT_DAYS) {
+                signals.push(Signal::NewAccount);
+            }
+
+            if si.vac_bans > 0 || si.game_bans > 0 {
+                signals.push(Signal::SteamBans);
+            }
+        }
+
+        if players.friend_info.get(&steamid).is_some_and(|fi| {
+            fi.iter()
+                .any(|f| matches!(players.verdict(f.steamid), Verdict::Cheater | Verdict::Bot))
+        }) {
+            signals.push(Signal::CheaterFriends);
+        }
+
+        signals
+    }
+
+    /// Adds any of `signals` the player doesn't already have to their score.
+    /// Returns the update to store it in their `custom_data`, or `None` if
+    /// nothing changed.
+    #[must_use]
+    pub fn add_signals(
+        players: &Players,
+        steamid: SteamID,
+        signals: &[Signal],
+    ) -> Option<UserUpdate> {
+        let mut confidence = players.auto_confidence(steamid).unwrap_or_default();
+        let before = confidence.signals.len();
+        for &signal in signals {
+            if !confidence.signals.contains(&signal) {
+                confidence.signals.push(signal);
+            }
+        }
+        if confidence.signals.len() == before {
+            return None;
+        }
+        confidence.score = confidence.signals.iter().copied().map(Signal::weight).sum();
+
+        let mut custom_data = serde_json::Map::new();
+        custom_data.insert(
+            AUTO_CONFIDENCE_KEY.into(),
+            serde_json::to_value(confidence).ok()?,
+        );
+        Some(UserUpdate {
+            local_verdict: None,
+            custom_data: Some(custom_data.into()),
+            muted: None,
+        })
+    }
+
+    /// Whether the score is high enough for the player to be considered
+    /// flagged
+    #[must_use]
+    pub const fn is_flagged(&self) -> bool {
+        self.score >= AUTO_FLAG_SCORE
+    }
+}
+
+// Handlers *************************
+
+/// Adds to the confidence scores of connected players as more information
+/// about them becomes available. Muted players aren't scored, so they never
+/// get flagged.
+pub struct ConfidenceScorer;
+
+impl<IM, OM> MessageHandler<MACState, IM, OM> for ConfidenceScorer
+where
+    IM: Is<Refresh>,
+    OM: Is<UserUpdates>,
+{
+    fn handle_message(&mut self, state: &MACState, message: &IM) -> Option<Handled<OM>> {
+        let _ = try_get::<Refresh>(message)?;
+
+        let updates: HashMap<SteamID, UserUpdate> = state
+            .players
+            .connected
+            .iter()
+            .filter(|&&s| !state.players.is_muted(s))
+            .filter_map(|&s| {
+                let signals = AutoConfidence::evaluate(&state.players, s);
+                Some((s, AutoConfidence::add_signals(&state.players, s, &signals)?))
+            })
+            .collect();
+
+        if updates.is_empty() {
+            return Handled::none();
+        }
+
+        Handled::single(UserUpdates(updates))
+    }
+}
+
+/// How many days ago the player's most recent ban was issued, accounting for
+/// time passed since their info was fetched.
+#[must_use]
+pub fn days_since_ban(steam_info: &SteamInfo) -> Option<i64> {
+    let days_since_fetch = (Utc::now() - steam_info.fetched).num_days();
+    steam_info
+        .days_since_last_ban
+        .map(|days| days + days_since_fetch)
+}
+
+/// How many days ago the player was banned, if it was within `threshold_days`
+#[must_use]
+pub fn recent_ban(steam_info: &SteamInfo, threshold_days: u32) -> Option<i64> {
+    let days = days_since_ban(steam_info)?;
+    (days <= i64::from(threshold_days)).then_some(days)
+}
+
+/// Someone on the server was banned within the last
+/// [`crate::settings::Settings::recent_ban_days`] days
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentlyBanned {
+    #[serde(rename = "steamID64", serialize_with = "serialize_steamid_as_string")]
+    pub steamid: SteamID,
+    pub name: Option<String>,
+    pub days_since_ban: i64,
+}
+impl<S> event_loop::Message<S> for RecentlyBanned {}
+
+/// Lets the user know when someone on the server was banned recently, which
+/// is a much stronger sign than an old ban. Can optionally mark them as
+/// suspicious if the user hasn't already given them a verdict.
+pub struct RecentBans {
+    notified: HashSet<SteamID>,
+}
+
+impl RecentBans {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            notified: HashSet::new(),
+        }
+    }
+}
+
+impl Default for RecentBans {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<IM, OM> MessageHandler<MACState, IM, OM> for RecentBans
+where
+    IM: Is<Refresh>,
+    OM: Is<UserUpdates> + Is<RecentlyBanned>,
+{
+    fn handle_message(&mut self, state: &MACState, message: &IM) -> Option<Handled<OM>> {
+        let _ = try_get::<Refresh>(message)?;
+
+        let threshold = state.settings.recent_ban_days();
+        if threshold == 0 {
+            return Handled::none();
+        }
+
+        let recently_banned: Vec<(SteamID, i64)> = state
+            .players
+            .connected
+            .iter()
+            .filter(|&&s| !state.players.is_muted(s))
+            .filter_map(|&s| {
+                let days = recent_ban(state.players.steam_info.get(&s)?, threshold)?;
+                Some((s, days))
+            })
+            .collect();
+
+        if recently_banned.is_empty() {
+            return Handled::none();
+        }
+
+        let mut out = Vec::new();
+        let mut updates = HashMap::new();
+        for (steamid, days) in recently_banned {
+            // Checked every time rather than once, in case another handler's
+            // update to the score overwrote it
+            if let Some(update) =
+                AutoConfidence::add_signals(&state.players, steamid, &[Signal::RecentBan])
+            {
+                updates.insert(steamid, update);
+            }
+
+            if !self.notified.insert(steamid) {
+                continue;
+            }
+            tracing::info!(
+                "{} was banned {days} days ago.",
+                state.players.get_name(steamid).unwrap_or("Unknown player")
+            );
+
+            out.push(Handled::single(RecentlyBanned {
+                steamid,
+                name: state.players.get_name(steamid).map(ToString::to_string),
+                days_since_ban: days,
+            }));
+
+            if state.settings.mark_recently_banned()
+                && state.players.verdict(steamid) == Verdict::Player
+            {
+                updates
+                    .entry(steamid)
+                    .or_insert(UserUpdate {
+                        local_verdict: None,
+                        custom_data: None,
+                        muted: None,
+                    })
+                    .local_verdict = Some(Verdict::Suspicious);
+            }
+        }
+
+        if !updates.is_empty() {
+            out.push(Handled::single(UserUpdates(updates)));
+        }
+
+        Handled::multiple(out)
+    }
+}
+
+/// Marks the user's friends from `trusted_friends` as trusted when they join,
+/// unless they've already been given a verdict.
+pub struct TrustFriends;
+
+impl<IM, OM> MessageHandler<MACState, IM, OM> for TrustFriends
+where
+    IM: Is<NewPlayers>,
+    OM: Is<UserUpdates>,
+{
+    fn handle_message(&mut self, state: &MACState, message: &IM) -> Option<Handled<OM>> {
+        let new_players = try_get::<NewPlayers>(message)?;
+
+        let trusted = state.settings.trusted_friends();
+        let updates: HashMap<SteamID, UserUpdate> = new_players
+            .0
+            .iter()
+            .filter(|&s| trusted.contains(s))
+            // Don't override a verdict the user has set themselves
+            .filter(|&&s| state.players.verdict(s) == Verdict::Player)
+            .map(|&s| {
+                tracing::info!(
+                    "Marking {} as trusted.",
+                    state.players.get_name(s).unwrap_or("Unknown player")
+                );
+                (
+                    s,
+                    UserUpdate {
+                        local_verdict: Some(Verdict::Trusted),
+                        custom_data: None,
+                        muted: None,
+                    },
+                )
+            })
+            .collect();
+
+        if updates.is_empty() {
+            return Handled::none();
+        }
+
+        Handled::single(UserUpdates(updates))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use serde_json::json;
+
+    use super::{recent_ban, AutoConfidence, Signal};
+    use crate::player::{ProfileVisibility, SteamInfo};
+
+    fn banned(days_ago: Option<i64>, fetched_days_ago: i64) -> SteamInfo {
+        SteamInfo {
+            account_name: String::new(),
+            profile_url: String::new(),
+            pfp_url: String::new(),
+            pfp_hash: String::new(),
+            profile_visibility: ProfileVisibility::Public,
+            time_created: None,
+            country_code: None,
+            vac_bans: i64::from(days_ago.is_some()),
+            game_bans: 0,
+            days_since_last_ban: days_ago,
+            fetched: Utc::now() - Duration::days(fetched_days_ago),
+        }
+    }
+
+    #[test]
+    pub fn recent_ban_threshold() {
+        assert_eq!(recent_ban(&banned(None, 0), 30), None);
+        assert_eq!(recent_ban(&banned(Some(10), 0), 30), Some(10));
+        assert_eq!(recent_ban(&banned(Some(30), 0), 30), Some(30));
+        assert_eq!(recent_ban(&banned(Some(31), 0), 30), None);
+
+        // Time since the profile was fetched counts too
+        assert_eq!(recent_ban(&banned(Some(25), 10), 30), None);
+        assert_eq!(recent_ban(&banned(Some(15), 10), 30), Some(25));
+    }
+
+    #[test]
+    pub fn confidence_from_custom_data() {
+        let custom_data = json!({
+            "auto_confidence": { "score": 4, "signals": ["newAccount", "cheaterFriends"] },
+            "note": "something else",
+        });
+        assert_eq!(
+            AutoConfidence::from_custom_data(&custom_data),
+            Some(AutoConfidence {
+                score: 4,
+                signals: vec![Signal::NewAccount, Signal::CheaterFriends],
+            })
+        );
+
+        assert_eq!(AutoConfidence::from_custom_data(&json!({})), None);
+        assert_eq!(
+            AutoConfidence::from_custom_data(&json!({ "auto_confidence": "garbage" })),
+            None
+        );
+    }
+}
@@ -8,9 +8,9 @@ use crate::{
         filewatcher::FileWatcher,
         g15::{G15Player, Parser},
         regexes::{
-            ChatMessage, DemoStop, Hostname, Map, PlayerCount, PlayerKill, ServerIP, StatusLine,
-            REGEX_CHAT, REGEX_DEMOSTOP, REGEX_HOSTNAME, REGEX_IP, REGEX_KILL, REGEX_MAP,
-            REGEX_PLAYERCOUNT, REGEX_STATUS,
+            ChatMessage, DemoStop, Hostname, Map, PlayerCount, PlayerKill, SelfKicked, ServerIP,
+            StatusLine, REGEX_CHAT, REGEX_DEMOSTOP, REGEX_HOSTNAME, REGEX_IP, REGEX_KILL,
+            REGEX_MAP, REGEX_PLAYERCOUNT, REGEX_SELFKICKED, REGEX_STATUS,
         },
     },
     state::MACState,
@@ -41,11 +41,14 @@ impl<M: Is<RawConsoleOutput>> MessageSource<M> for ConsoleLog {
     }
 }
 impl ConsoleLog {
+    /// Watches the log for new lines, or reads it from the start first if
+    /// `from_start` is set.
+    ///
     /// # Panics
     /// If tokio fails to spawn the task.
     #[allow(clippy::unused_async)]
-    pub async fn new(log_file_path: PathBuf) -> Self {
-        let (console_rx, mut log_watcher) = FileWatcher::new(log_file_path);
+    pub async fn new(log_file_path: PathBuf, from_start: bool) -> Self {
+        let (console_rx, mut log_watcher) = FileWatcher::new(log_file_path, from_start);
         tokio::task::spawn(async move {
             log_watcher.file_watch_loop().await;
         });
@@ -69,6 +72,8 @@ pub enum ConsoleOutput {
     PlayerCount(PlayerCount),
     G15(Vec<G15Player>),
     DemoStop(DemoStop),
+    /// The user was kicked or banned from the server
+    SelfKicked(SelfKicked),
 }
 impl Message<MACState> for ConsoleOutput {
     fn preprocess(&mut self, state: &MACState) {
@@ -99,6 +104,7 @@ pub struct ConsoleParser {
     regex_map: Regex,
     regex_playercount: Regex,
     regex_demostop: Regex,
+    regex_selfkicked: Regex,
 }
 
 impl Default for ConsoleParser {
@@ -113,6 +119,7 @@ impl Default for ConsoleParser {
             regex_map: Regex::new(REGEX_MAP).expect("Compile static regex"),
             regex_playercount: Regex::new(REGEX_PLAYERCOUNT).expect("Compile static regex"),
             regex_demostop: Regex::new(REGEX_DEMOSTOP).expect("Compile static regex"),
+            regex_selfkicked: Regex::new(REGEX_SELFKICKED).expect("Compile static regex"),
         }
     }
 }
@@ -174,6 +181,11 @@ where
                 let demostop = DemoStop::parse(&caps);
                 out.push(Handled::single(ConsoleOutput::DemoStop(demostop)));
             }
+            // Match the user being kicked
+            if let Some(caps) = self.regex_selfkicked.captures(line) {
+                let kicked = SelfKicked::parse(&caps);
+                out.push(Handled::single(ConsoleOutput::SelfKicked(kicked)));
+            }
         }
 
         // Check for G15
@@ -1,20 +1,28 @@
 use bitbuffer::{BitError, BitRead, BitReadBuffer, BitReadStream, LittleEndian};
+use chrono::{DateTime, Utc};
 use event_loop::{try_get, Handled, Is, MessageHandler, MessageSource};
+use flate2::read::MultiGzDecoder;
 use notify::{event::ModifyKind, Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::{metadata, File},
     io::{Read, Seek},
     path::{Path, PathBuf},
     sync::{
+        atomic::{AtomicBool, Ordering},
         mpsc::{self, Receiver, TryRecvError},
         Arc,
     },
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 use steamid_ng::SteamID;
 use tf_demo_parser::demo::{
-    gameevent_gen::{VoteCastEvent, VoteOptionsEvent, VoteStartedEvent},
+    gameevent_gen::{
+        MvmBeginWaveEvent, MvmPickupCurrencyEvent, PlayerConnectClientEvent, PlayerConnectEvent,
+        PlayerDisconnectEvent, TeamPlayRoundStartEvent, TeamPlayRoundWinEvent, VoteCastEvent,
+        VoteOptionsEvent, VoteStartedEvent,
+    },
     gamevent::GameEvent,
     header::Header,
     message::{gameevent::GameEventMessage, Message},
@@ -25,14 +33,22 @@ use tf_demo_parser::demo::{
     },
 };
 use thiserror::Error;
-use tokio::sync::{Mutex, MutexGuard};
+use tokio::sync::{mpsc::UnboundedSender, Mutex, MutexGuard};
 
 use crate::{
-    events::UserUpdates,
-    masterbase::{DemoSession, ReportReason},
+    audit::{self, AuditAction, AuditEntry},
+    confidence::AutoConfidence,
+    events::{InternalPreferences, Preferences, UserUpdates},
+    masterbase::{self, DemoSession, ReportReason},
     new_players::NewPlayers,
-    settings::Settings,
+    player::Team,
+    player_records::Verdict,
+    settings::{
+        ConfigFilesError, DemoEventKind, PreexistingDemoMode, ReportPolicy, Settings,
+        DEMO_LATE_BYTES_LEN, DEMO_LATE_BYTES_OFFSET,
+    },
     state::MACState,
+    web::{WebError, WebRequest, WebResponse},
 };
 
 #[allow(clippy::module_name_repetitions)]
@@ -41,7 +57,23 @@ pub struct DemoMessage {
     pub tick: u32,
     pub event: DemoEvent,
 }
-impl<S> event_loop::Message<S> for DemoMessage {}
+impl event_loop::Message<MACState> for DemoMessage {
+    fn update_state(self, state: &mut MACState) {
+        // The demo knows exactly when someone joined, unless we've already
+        // seen them under this userid and it's just a reconnect
+        if let DemoEvent::PlayerConnected {
+            steamid: Some(steamid),
+            userid,
+            ..
+        } = self.event
+        {
+            if state.server.demo_player(userid) != Some(steamid) {
+                state.players.connected_since.insert(steamid, Utc::now());
+            }
+        }
+        state.server.handle_demo_event(&self.event);
+    }
+}
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Clone)]
@@ -49,6 +81,35 @@ pub enum DemoEvent {
     VoteStarted(VoteStartedEvent),
     VoteOptions(Box<VoteOptionsEvent>),
     VoteCast(VoteCastEvent, Option<SteamID>),
+    /// A new Mann vs. Machine wave has started (1-indexed)
+    MvmWaveStarted {
+        wave: u32,
+        max_waves: u32,
+    },
+    /// Credits were picked up in Mann vs. Machine
+    MvmCurrencyCollected(u32),
+    /// A new round has started
+    RoundStart {
+        /// Whether the whole game was reset, e.g. after a map change or
+        /// a team switch
+        full_reset: bool,
+    },
+    /// A round was won. `winner` is `None` for a stalemate.
+    RoundEnd {
+        winner: Option<Team>,
+    },
+    /// A player connected to the server. `steamid` is `None` for bots.
+    PlayerConnected {
+        steamid: Option<SteamID>,
+        name: String,
+        userid: u16,
+    },
+    /// A player disconnected from the server
+    PlayerDisconnected {
+        steamid: Option<SteamID>,
+        userid: u16,
+        reason: String,
+    },
     LatestTick,
 }
 
@@ -64,9 +125,111 @@ pub struct DemoBytes {
     pub file_path: PathBuf,
     pub id: usize,
     pub bytes: Vec<u8>,
+    /// The position the demo was read up to before a restart, if it's being
+    /// picked up again. The bytes are everything recorded so far, so it can be
+    /// uploaded whole, but events before this were already extracted last
+    /// time.
+    pub resumed_from: Option<u64>,
 }
 impl<S> event_loop::Message<S> for DemoBytes {}
 
+/// A compressed demo has stopped being written to, so it can be read
+#[derive(Debug, Clone)]
+pub struct CompressedDemo(pub PathBuf);
+impl<S> event_loop::Message<S> for CompressedDemo {}
+
+/// A compressed demo which has been inflated, so it can be parsed and uploaded
+pub struct DecompressedDemo {
+    pub file_path: PathBuf,
+    pub bytes: Vec<u8>,
+}
+impl<S> event_loop::Message<S> for DecompressedDemo {}
+
+/// How often the read position in the current demo is saved
+const DEMO_PROGRESS_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+/// How long a compressed demo has to stop growing before it's read, since
+/// they can only be decompressed in one go
+const COMPRESSED_DEMO_SETTLE_TIME: Duration = Duration::from_secs(5);
+/// The most a compressed demo is inflated to, so a corrupt or malicious file
+/// can't use up all the memory. Hours long demos are still well under this.
+const MAX_DECOMPRESSED_DEMO_LEN: u64 = 1 << 30;
+
+/// Whether the file is a gzip compressed demo, i.e. ends in `.dem.gz`
+fn is_compressed_demo(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+        && path
+            .file_stem()
+            .is_some_and(|stem| Path::new(stem).extension().is_some_and(|ext| ext == "dem"))
+}
+
+/// The read position in the demo being recorded, saved so reading can pick up
+/// where it left off if the client is restarted while TF2 is still recording.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct DemoProgress {
+    path: PathBuf,
+    offset: u64,
+    /// Creation time of the demo in seconds since the epoch, to tell if it has
+    /// since been recorded over
+    created: Option<u64>,
+}
+
+impl DemoProgress {
+    fn new(path: &Path, offset: u64) -> Self {
+        let created = metadata(path).and_then(|m| m.created()).ok();
+        Self {
+            path: path.to_path_buf(),
+            offset,
+            created: created.and_then(Self::epoch_secs),
+        }
+    }
+
+    fn epoch_secs(time: SystemTime) -> Option<u64> {
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs())
+    }
+
+    fn locate() -> Result<PathBuf, ConfigFilesError> {
+        Settings::locate_config_directory().map(|dir| dir.join("demo_progress.json"))
+    }
+
+    fn load() -> Option<Self> {
+        let path = Self::locate().ok()?;
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                tracing::error!("Failed to read demo progress {path:?}: {e}");
+                return None;
+            }
+        };
+
+        serde_json::from_slice(&contents)
+            .map_err(|e| tracing::error!("Failed to parse demo progress {path:?}: {e}"))
+            .ok()
+    }
+
+    fn save(&self) {
+        let result = Self::locate().map_err(|e| e.to_string()).and_then(|path| {
+            let contents = serde_json::to_vec(self).map_err(|e| e.to_string())?;
+            std::fs::write(path, contents).map_err(|e| e.to_string())
+        });
+
+        if let Err(e) = result {
+            tracing::error!("Failed to save demo progress: {e}");
+        }
+    }
+
+    /// Whether this is the progress of the given demo, and the demo hasn't
+    /// been recorded over since.
+    fn matches(&self, path: &Path, created: Option<SystemTime>, len: u64) -> bool {
+        self.path == path
+            && self.created.is_some()
+            && self.created == created.and_then(Self::epoch_secs)
+            && len >= self.offset
+    }
+}
+
 #[allow(clippy::module_name_repetitions)]
 pub struct DemoWatcher {
     recv: Receiver<Event>,
@@ -77,13 +240,32 @@ pub struct DemoWatcher {
     current_demo: Option<PathBuf>,
     offset: u64,
 
+    /// When the watcher was started, to tell which demos were already being
+    /// recorded before then
+    launched: SystemTime,
+    preexisting_mode: PreexistingDemoMode,
+    /// A demo that was already being recorded at launch and is being ignored,
+    /// along with its size when last checked
+    skipped_demo: Option<(PathBuf, u64)>,
+
+    /// Progress saved by a previous run, used if the same demo is still being
+    /// recorded
+    saved_progress: Option<DemoProgress>,
+    last_progress_save: Instant,
+    /// Compressed demos waiting to stop growing, with their size when last
+    /// checked and when it last changed
+    pending_compressed: Vec<(PathBuf, u64, Instant)>,
+
     _watcher: RecommendedWatcher,
 }
 
 impl DemoWatcher {
     /// # Errors
     /// If the [`notify::Watcher`] could not be started.
-    pub fn new(demo_path: &Path) -> Result<Self, DemoWatcherError> {
+    pub fn new(
+        demo_path: &Path,
+        preexisting_mode: PreexistingDemoMode,
+    ) -> Result<Self, DemoWatcherError> {
         let (tx, rx) = mpsc::channel();
         let config = Config::default().with_poll_interval(Duration::from_secs(2));
 
@@ -108,10 +290,90 @@ impl DemoWatcher {
             current_id: 0,
             current_demo: None,
             offset: 0,
+            launched: SystemTime::now(),
+            preexisting_mode,
+            skipped_demo: None,
+            saved_progress: DemoProgress::load(),
+            last_progress_save: Instant::now(),
+            pending_compressed: Vec::new(),
             _watcher: watcher,
         })
     }
 
+    /// Where to start reading a demo that was found already being written to,
+    /// or `None` if it should be skipped.
+    fn initial_offset(
+        mode: PreexistingDemoMode,
+        created: Option<SystemTime>,
+        launched: SystemTime,
+        len: u64,
+    ) -> Option<u64> {
+        // A demo created after launch has been seen from the start, so it's fine
+        // to read. If the platform can't tell us, assume it's a new recording.
+        if created.map_or(true, |c| c >= launched) {
+            return Some(0);
+        }
+
+        match mode {
+            PreexistingDemoMode::Skip => None,
+            PreexistingDemoMode::ResumeFromOffset => Some(len),
+            PreexistingDemoMode::ReadFromStart => Some(0),
+        }
+    }
+
+    /// Starts watching a demo which was modified without us seeing it be
+    /// created.
+    fn adopt_demo(&mut self, path: &Path) -> Option<DemoBytes> {
+        let meta = metadata(path)
+            .map_err(|e| tracing::error!("Couldn't read metadata of demo {path:?}: {e}"))
+            .ok()?;
+
+        let offset = match &mut self.skipped_demo {
+            // Still the demo from before launch, keep ignoring it
+            Some((skipped, len)) if skipped == path && meta.len() >= *len => {
+                *len = meta.len();
+                return None;
+            }
+            // The skipped demo shrank, so a new one has been recorded over it
+            Some((skipped, _)) if skipped == path => {
+                self.skipped_demo = None;
+                0
+            }
+            _ => {
+                let created = meta.created().ok();
+
+                // Pick up where we left off if we were reading this demo before a restart
+                if let Some(progress) = self
+                    .saved_progress
+                    .take()
+                    .filter(|p| p.matches(path, created, meta.len()))
+                {
+                    tracing::info!(
+                        "Resuming demo {path:?} from saved position {}.",
+                        progress.offset
+                    );
+                    self.current_demo = Some(path.to_path_buf());
+                    return self.resumed_bytes(progress.offset);
+                }
+
+                let Some(offset) =
+                    Self::initial_offset(self.preexisting_mode, created, self.launched, meta.len())
+                else {
+                    tracing::info!(
+                        "Skipping demo {path:?} which was already being recorded at launch."
+                    );
+                    self.skipped_demo = Some((path.to_path_buf(), meta.len()));
+                    return None;
+                };
+                offset
+            }
+        };
+
+        self.current_demo = Some(path.to_path_buf());
+        self.offset = offset;
+        self.next_bytes()
+    }
+
     /// Return the next chunk of bytes for the current demo being watched
     ///
     /// # Errors
@@ -146,6 +408,13 @@ impl DemoWatcher {
         if read_bytes > 0 {
             tracing::debug!("Got {} demo bytes", read_bytes);
             self.offset += read_bytes as u64;
+
+            // Saved periodically rather than every read, so after a crash some
+            // bytes may be read again but none will be missed
+            if self.last_progress_save.elapsed() >= DEMO_PROGRESS_SAVE_INTERVAL {
+                self.last_progress_save = Instant::now();
+                DemoProgress::new(file_path, self.offset).save();
+            }
         }
 
         Ok(Some(out))
@@ -162,20 +431,102 @@ impl DemoWatcher {
                 id: self.current_id,
                 file_path,
                 bytes: b,
+                resumed_from: None,
             })
     }
+
+    /// The bytes of a demo being resumed from a saved position. The whole
+    /// demo is read again from the start, since a new session has to be
+    /// opened and the masterbase needs everything before the saved position
+    /// too.
+    fn resumed_bytes(&mut self, saved_offset: u64) -> Option<DemoBytes> {
+        self.offset = 0;
+        let bytes = self.next_bytes()?;
+
+        // Nothing was read past the header last time, so it's like a new demo
+        Some(DemoBytes {
+            resumed_from: (saved_offset > HEADER_LEN).then_some(saved_offset),
+            ..bytes
+        })
+    }
+
+    /// Notes that a compressed demo has been written to, so it can be read
+    /// once it's finished
+    fn track_compressed(&mut self, path: &Path) {
+        let Ok(len) = metadata(path).map(|m| m.len()) else {
+            return;
+        };
+
+        match self
+            .pending_compressed
+            .iter_mut()
+            .find(|(p, _, _)| p == path)
+        {
+            Some((_, last_len, _)) if *last_len == len => {}
+            Some((_, last_len, changed)) => {
+                *last_len = len;
+                *changed = Instant::now();
+            }
+            None => {
+                self.pending_compressed
+                    .push((path.to_path_buf(), len, Instant::now()));
+            }
+        }
+    }
+
+    /// Takes the first compressed demo which has stopped growing
+    fn next_compressed(&mut self) -> Option<CompressedDemo> {
+        let now = Instant::now();
+        let mut ready = None;
+        for (i, (path, last_len, changed)) in self.pending_compressed.iter_mut().enumerate() {
+            if now.duration_since(*changed) < COMPRESSED_DEMO_SETTLE_TIME {
+                continue;
+            }
+
+            // Still being written to
+            let len = metadata(&*path).map_or(0, |m| m.len());
+            if len != *last_len {
+                *last_len = len;
+                *changed = now;
+                continue;
+            }
+
+            ready = Some(i);
+            break;
+        }
+
+        let (file_path, _, _) = self.pending_compressed.remove(ready?);
+        Some(CompressedDemo(file_path))
+    }
 }
 
-impl<M: Is<DemoBytes>> MessageSource<M> for DemoWatcher {
+impl<M: Is<DemoBytes> + Is<CompressedDemo>> MessageSource<M> for DemoWatcher {
     fn next_message(&mut self) -> Option<M> {
         match self.recv.try_recv() {
             Ok(e) => {
                 let path = &e.paths[0];
+
+                // Can't be tailed like a demo being recorded, so wait for it to be finished
+                if is_compressed_demo(path)
+                    && matches!(
+                        e.kind,
+                        notify::event::EventKind::Create(_)
+                            | notify::event::EventKind::Modify(ModifyKind::Data(_))
+                    )
+                {
+                    self.track_compressed(path);
+                    return None;
+                }
+
                 match e.kind {
                     notify::event::EventKind::Create(_) => {
                         if path.extension().map_or(false, |ext| ext == "dem") {
                             self.current_demo = Some(path.clone());
                             self.offset = 0;
+                            // A new recording, so it's no longer one from before launch
+                            if self.skipped_demo.as_ref().is_some_and(|(p, _)| p == path) {
+                                self.skipped_demo = None;
+                            }
                         }
                         return self.next_bytes().map(Into::into);
                     }
@@ -187,9 +538,8 @@ impl<M: Is<DemoBytes>> MessageSource<M> for DemoWatcher {
                             // the player can be already connected to a
                             // server and recording a demo when the application is run.
                             // This should catch those cases.
-                            self.current_demo = Some(path.clone());
-                            self.offset = 0;
-                            return self.next_bytes().map(Into::into);
+                            let path = path.clone();
+                            return self.adopt_demo(&path).map(Into::into);
                         }
                     }
                     _ => {}
@@ -211,7 +561,7 @@ impl<M: Is<DemoBytes>> MessageSource<M> for DemoWatcher {
             return self.next_bytes().map(Into::into);
         }
 
-        None
+        self.next_compressed().map(Into::into)
     }
 }
 
@@ -228,6 +578,16 @@ pub struct DemoManager {
     current_demo: Option<OpenDemo>,
 
     session: DemoManagerSession,
+    /// Set when the masterbase rejects our key, so we stop trying to open
+    /// sessions until a new key is provided.
+    key_rejected: Arc<AtomicBool>,
+    /// Demos which were completely uploaded this session
+    uploaded: Arc<Mutex<HashSet<PathBuf>>>,
+    /// Where the late bytes are in the demo and how many there are
+    late_bytes: (u64, usize),
+    /// Players successfully reported in the current session, so they aren't
+    /// reported again when they rejoin or their score changes
+    reported: Arc<std::sync::Mutex<HashSet<(SteamID, ReportReason)>>>,
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -242,6 +602,12 @@ pub struct OpenDemo {
     pub handler: DemoHandler<GameStateAnalyser>,
     pub bytes: Vec<u8>,
     pub offset: usize,
+    /// Set once the demo couldn't be parsed, so no more time is spent on it
+    pub failed: bool,
+    /// The bit position events were already extracted up to before a
+    /// restart. Packets before it are still parsed, since later ones depend
+    /// on them, but their events are dropped.
+    pub skip_events_until: usize,
 }
 
 impl PartialEq for OpenDemo {
@@ -270,6 +636,15 @@ impl DemoManagerSession {
         }
     }
 
+    /// Gives up on a session which was never opened, so anything waiting for
+    /// it in [`DemoManagerSession::get`] stops waiting
+    async fn disable(&self) {
+        let mut guard = self.0.lock().await;
+        if matches!(*guard, Err(SessionMissingReason::Uninit)) {
+            *guard = Err(SessionMissingReason::Disabled);
+        }
+    }
+
     /// Returns an event which opens a new session.
     /// This event needs to be handled by the event loop to take effect.
     fn open_new_session<M: Is<DemoMessage>>(
@@ -277,6 +652,7 @@ impl DemoManagerSession {
         settings: &Settings,
         header: &Header,
         demo_name: &str,
+        key_rejected: Arc<AtomicBool>,
     ) -> Option<Handled<M>> {
         let host = settings.masterbase_host().to_owned();
         let key = settings.masterbase_key().to_owned();
@@ -285,22 +661,50 @@ impl DemoManagerSession {
         let http = settings.use_masterbase_http();
         let demo_name = demo_name.to_owned();
         let session = self.0.clone();
+        let retries = settings.masterbase_session_retries();
+        let retry_delay = Duration::from_secs(settings.masterbase_retry_delay());
 
         Handled::future(async move {
             let session = session;
+            // Held while retrying, so uploads wait for the session rather than
+            // being dropped
             let mut maybe_session = session.lock().await;
             assert!(maybe_session.is_err());
 
             // Create session
-            match DemoSession::new(host, key, &fake_ip, &map, &demo_name, http).await {
-                Ok(session) => {
-                    tracing::info!("Opened new demo session with Masterbase: {session:?}");
-                    *maybe_session = Ok(session);
-                }
-                Err(e) => {
-                    tracing::error!("Could not open new demo session: {e}");
-                    *maybe_session = Err(SessionMissingReason::Error);
+            let mut attempt = 0;
+            loop {
+                match DemoSession::new(host.clone(), key.clone(), &fake_ip, &map, &demo_name, http)
+                    .await
+                {
+                    Ok(session) => {
+                        tracing::info!("Opened new demo session with Masterbase: {session:?}");
+                        *maybe_session = Ok(session);
+                    }
+                    Err(masterbase::Error::Unauthorized) => {
+                        if !key_rejected.swap(true, Ordering::Relaxed) {
+                            tracing::error!("The masterbase rejected the provided key. Demo uploads have been disabled until a new key is set. You can provision a key at https://megaanticheat.com/provision");
+                        }
+                        *maybe_session = Err(SessionMissingReason::Disabled);
+                    }
+                    Err(e) if attempt < retries => {
+                        let delay = retry_delay.saturating_mul(2u32.saturating_pow(attempt));
+                        attempt += 1;
+                        tracing::warn!(
+                            "Could not open new demo session ({e}), retrying in {delay:?} (attempt {attempt}/{retries})"
+                        );
+                        tokio::time::sleep(delay).await;
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            "Could not open new demo session after {} attempts, {demo_name} won't be uploaded: {e}",
+                            attempt + 1
+                        );
+                        *maybe_session = Err(SessionMissingReason::Error);
+                    }
                 }
+                break;
             }
 
             None
@@ -317,9 +721,100 @@ impl DemoManager {
             current_demo: None,
 
             session: DemoManagerSession::new(SessionMissingReason::Disabled),
+            key_rejected: Arc::new(AtomicBool::new(false)),
+            uploaded: Arc::new(Mutex::new(HashSet::new())),
+            late_bytes: (DEMO_LATE_BYTES_OFFSET, DEMO_LATE_BYTES_LEN),
+            reported: Arc::new(std::sync::Mutex::new(HashSet::new())),
         }
     }
 
+    /// Parses a complete demo file from disk and returns all the events in
+    /// it, the same as if it were being recorded. Nothing is uploaded, so
+    /// this can be used to go back over old demos. If the demo is malformed,
+    /// the events up until that point are returned.
+    ///
+    /// # Errors
+    /// If the file couldn't be read
+    pub fn replay_file(
+        path: &Path,
+        disabled: &[DemoEventKind],
+    ) -> std::io::Result<Vec<DemoMessage>> {
+        let bytes = std::fs::read(path)?;
+        let mut demo = OpenDemo::new(path.to_path_buf(), 0);
+        Ok(demo.append_bytes(&bytes, disabled))
+    }
+
+    /// Inflates a compressed demo off the event loop, so it can be handled
+    /// once it's ready. See [`DemoManager::handle_decompressed_demo`].
+    fn handle_compressed_demo<M: Is<DecompressedDemo>>(path: &Path) -> Option<Handled<M>> {
+        let path = path.to_path_buf();
+        Handled::future(async move {
+            let file_path = path.clone();
+            match tokio::task::spawn_blocking(move || {
+                decompress_demo(&file_path, MAX_DECOMPRESSED_DEMO_LEN)
+            })
+            .await
+            {
+                Ok(Ok(bytes)) => Some(
+                    DecompressedDemo {
+                        file_path: path,
+                        bytes,
+                    }
+                    .into(),
+                ),
+                Ok(Err(e)) => {
+                    tracing::error!("Couldn't decompress demo {path:?}: {e}");
+                    None
+                }
+                Err(e) => {
+                    tracing::error!("Failed to decompress demo {path:?}: {e}");
+                    None
+                }
+            }
+        })
+    }
+
+    /// Extracts the events from a compressed demo the same way as
+    /// [`DemoManager::replay_file`], then uploads it in its own session if
+    /// uploads are enabled.
+    fn handle_decompressed_demo<M: Is<DemoMessage>>(
+        &self,
+        state: &MACState,
+        msg: &DecompressedDemo,
+    ) -> Option<Handled<M>> {
+        tracing::info!("Read compressed demo {:?}", msg.file_path);
+        let mut demo = OpenDemo::new(msg.file_path.clone(), 0);
+        let mut events: Vec<_> = demo
+            .append_bytes(&msg.bytes, state.settings.disabled_demo_events())
+            .into_iter()
+            .map(Handled::single)
+            .collect();
+
+        if !self.uploads_enabled(&state.settings) {
+            tracing::info!(
+                "Demo uploads are disabled, not uploading {:?}",
+                msg.file_path
+            );
+            return Handled::multiple(events);
+        }
+
+        match demo.header {
+            Some(header) => events.push(self.upload_compressed_demo(
+                state,
+                &msg.file_path,
+                &header,
+                msg.bytes.clone(),
+            )),
+            None => tracing::error!("Invalid header in compressed demo {:?}", msg.file_path),
+        }
+
+        Handled::multiple(events)
+    }
+
+    fn uploads_enabled(&self, settings: &Settings) -> bool {
+        settings.upload_demos() && !self.key_rejected.load(Ordering::Relaxed)
+    }
+
     /// Start tracking a new demo file. A demo must be being tracked before
     /// bytes can be appended.
     fn new_demo(&mut self, path: PathBuf, id: usize, uploads_enabled: bool) {
@@ -329,14 +824,7 @@ impl DemoManager {
 
         tracing::debug!("Watching new demo: {:?}", path);
 
-        self.current_demo = Some(OpenDemo {
-            file_path: path,
-            id,
-            header: None,
-            handler: DemoHandler::with_analyser(GameStateAnalyser::new()),
-            bytes: Vec::new(),
-            offset: 0,
-        });
+        self.current_demo = Some(OpenDemo::new(path, id));
 
         self.session = DemoManagerSession::new(if uploads_enabled {
             SessionMissingReason::Uninit
@@ -345,6 +833,23 @@ impl DemoManager {
         });
     }
 
+    /// Overrides where the late bytes are read from, in case the demo header
+    /// layout changes. See [`DEMO_LATE_BYTES_OFFSET`] and [`DEMO_LATE_BYTES_LEN`].
+    #[must_use]
+    pub fn with_late_bytes(mut self, offset: u64, len: usize) -> Self {
+        // The late bytes are part of the header, so anything past the end of
+        // it will never be read until the whole demo has been recorded
+        let end = offset.saturating_add(len as u64);
+        if end > HEADER_LEN {
+            tracing::warn!(
+                "Demo late bytes end at {end:#x}, past the end of the header at {HEADER_LEN:#x}. Demos may not be finalised properly."
+            );
+        }
+
+        self.late_bytes = (offset, len);
+        self
+    }
+
     fn current_demo_path(&self) -> Option<&Path> {
         self.current_demo.as_ref().map(|d| d.file_path.as_path())
     }
@@ -358,37 +863,8 @@ impl DemoManager {
             return Ok(None);
         };
 
-        let start_address = 0x420;
-        let bytes_to_read: usize = 16;
-        let min_valid_filelen: u64 = start_address + bytes_to_read as u64;
-
-        let current_metadata = metadata(file_path)?;
-
-        // Check the file is long enough to have data at the late byte address
-        match current_metadata.len().cmp(&min_valid_filelen) {
-            std::cmp::Ordering::Less => {
-                return Ok(None);
-            }
-            std::cmp::Ordering::Equal | std::cmp::Ordering::Greater => {}
-        }
-
-        let mut file = File::open(file_path)?;
-
-        file.seek(std::io::SeekFrom::Start(start_address))?;
-        let mut out = vec![0; bytes_to_read];
-        file.read_exact(&mut out)?;
-
-        // Check if the late bytes have been written to
-        // The first 8 bytes are always all zeroes until written to.
-        let written = out.len() > 8 && out.iter().take(8).any(|&b| b != 0);
-
-        if written {
-            tracing::debug!("Late bytes found in demo recording.");
-            Ok(Some(out))
-        } else {
-            tracing::debug!("No new bytes from demo.");
-            Ok(None)
-        }
+        let (offset, len) = self.late_bytes;
+        read_late_bytes(file_path, offset, len)
     }
 
     /// Returns an event that uploads the given bytes to the current session.
@@ -396,12 +872,19 @@ impl DemoManager {
     fn upload_bytes<M: Is<DemoMessage>>(&mut self, bytes: Vec<u8>) -> Option<Handled<M>> {
         // Loop while session is uninit
         let mut session = self.session.clone();
+        let file_path = self.current_demo_path().map(Path::to_path_buf);
         Handled::future(async move {
             let mut guard = session.get().await;
             match &mut *guard {
                 Ok(session) => {
                     let len = bytes.len();
-                    if let Err(e) = session.send_bytes(bytes).await {
+                    // Read from the file again if it has to be resent, rather
+                    // than keeping a copy of every chunk
+                    let rebuild = |offset| match &file_path {
+                        Some(path) => read_demo_range(path, offset, len),
+                        None => Err(std::io::ErrorKind::NotFound.into()),
+                    };
+                    if let Err(e) = session.send_bytes_or_resume(bytes, rebuild).await {
                         tracing::error!("Failed to upload demo chunk: {e}");
                         *guard = Err(SessionMissingReason::Error);
                         drop(guard);
@@ -427,6 +910,8 @@ impl DemoManager {
     /// This event needs to be handled by the event loop to take effect.
     fn handle_late_bytes<M: Is<DemoMessage>>(&self, late_bytes: Vec<u8>) -> Option<Handled<M>> {
         let mut session = self.session.clone();
+        let uploaded = self.uploaded.clone();
+        let demo_path = self.current_demo_path().map(Path::to_path_buf);
         Handled::future(async move {
             let mut session_lock = session.get().await;
             let Ok(session) = &mut *session_lock else {
@@ -442,6 +927,9 @@ impl DemoManager {
                         tracing::debug!(
                             "Uploaded late bytes to masterbase. Attempting to close session..."
                         );
+                        if let Some(path) = demo_path {
+                            uploaded.lock().await.insert(path);
+                        }
                     } else {
                         let s = status.as_str();
                         tracing::error!(
@@ -460,25 +948,102 @@ impl DemoManager {
         })
     }
 
+    /// Responds with the demos found in the places demos are usually recorded
+    /// to, along with any other folders demos have been seen in this session.
+    fn handle_list_demos<M>(
+        &self,
+        state: &MACState,
+        tx: &UnboundedSender<WebResponse>,
+    ) -> Option<Handled<M>> {
+        let tf_dir = state.settings.tf2_directory().join("tf");
+        let mut dirs = vec![tf_dir.join("demos"), tf_dir];
+        dirs.extend(
+            self.previous_demos
+                .iter()
+                .chain(&self.current_demo)
+                .filter_map(|d| d.file_path.parent().map(Path::to_path_buf)),
+        );
+        dirs.sort();
+        dirs.dedup();
+
+        let uploaded = self.uploaded.clone();
+        let tx = tx.clone();
+        Handled::future(async move {
+            let uploaded = uploaded.lock().await.clone();
+            let demos = tokio::task::spawn_blocking(move || list_demos(&dirs, &uploaded))
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!("Failed to list demos: {e}");
+                    Vec::new()
+                });
+
+            if tx
+                .send(serde_json::to_string(&demos).map_err(WebError::from))
+                .is_err()
+            {
+                tracing::error!("Failed to send response to API task.");
+            }
+            None
+        })
+    }
+
     /// Reports any other the players provided who are marked as bots to the masterbase
     fn report_players<M>(
         &mut self,
+        state: &MACState,
         players: impl Iterator<Item = (SteamID, ReportReason)>,
     ) -> Option<Handled<M>> {
-        Handled::multiple(players.map(|(s, r)| {
+        let players: Vec<_> = {
+            let reported = self
+                .reported
+                .lock()
+                .expect("Reported players lock poisoned");
+            players.filter(|p| !reported.contains(p)).collect()
+        };
+        if players.is_empty() {
+            return Handled::none();
+        }
+
+        let audit_path = audit::audit_log_path(&state.settings)
+            .map_err(|e| tracing::error!("Couldn't locate audit log: {e}"))
+            .ok();
+
+        Handled::multiple(players.into_iter().map(|(s, r)| {
             let mut session = self.session.clone();
+            let audit_path = audit_path.clone();
+            let stats = state.session_stats.clone();
+            let reported = self.reported.clone();
             Handled::future(async move {
                 let mut session_guard = session.get().await;
                 let Ok(session) = &mut *session_guard else {
                     return None;
                 };
 
+                // Reports are sent one at a time while holding the session, so
+                // one for the same player may have succeeded while this waited
+                if reported
+                    .lock()
+                    .expect("Reported players lock poisoned")
+                    .contains(&(s, r))
+                {
+                    return None;
+                }
+
                 let resp = session.report_player(s, r).await;
+                if resp.as_ref().is_ok_and(|resp| resp.status().is_success()) {
+                    reported
+                        .lock()
+                        .expect("Reported players lock poisoned")
+                        .insert((s, r));
+                }
                 drop(session_guard);
 
+                let mut entry = AuditEntry::new(AuditAction::Report, Some(s), format!("{r:?}"));
                 match resp {
                     Ok(resp) if resp.status().is_success() => {
                         tracing::info!("Reported {} as {r:?}", u64::from(s));
+                        entry.outcome = "reported".into();
+                        stats.add_report();
                     }
                     Ok(resp) => {
                         tracing::error!(
@@ -486,6 +1051,7 @@ impl DemoManager {
                             u64::from(s),
                             resp.status()
                         );
+                        entry.outcome = format!("failed: {}", resp.status());
                     }
                     Err(e) => {
                         tracing::error!(
@@ -493,14 +1059,132 @@ impl DemoManager {
                             u64::from(s),
                             e
                         );
+                        entry.outcome = format!("failed: {e}");
                     }
                 }
 
+                if let Some(path) = audit_path {
+                    audit::record(&path, &entry).await;
+                }
+
                 None
             })
         }))
     }
 
+    /// Why a player should be reported to the masterbase, if at all
+    fn report_reason(state: &MACState, steamid: SteamID) -> Option<ReportReason> {
+        let verdict = state.players.verdict(steamid);
+        ReportReason::try_from(verdict).ok().or_else(|| {
+            Self::auto_report_reason(
+                &state.settings,
+                verdict,
+                state.players.is_muted(steamid),
+                state.players.auto_confidence(steamid).as_ref(),
+            )
+        })
+    }
+
+    /// Players who haven't been marked by the user are only reported if they
+    /// have been flagged by the heuristics and the user has opted in to
+    /// reporting them. Muted players are left alone. The masterbase only
+    /// knows about bots and cheaters, so they're reported as cheaters.
+    fn auto_report_reason(
+        settings: &Settings,
+        verdict: Verdict,
+        muted: bool,
+        confidence: Option<&AutoConfidence>,
+    ) -> Option<ReportReason> {
+        let flagged = settings.report_policy() == ReportPolicy::IncludeAutomatic
+            && matches!(verdict, Verdict::Player | Verdict::Suspicious)
+            && !muted
+            && confidence.is_some_and(AutoConfidence::is_flagged);
+
+        flagged.then_some(ReportReason::Cheater)
+    }
+
+    /// Uploads a compressed demo in its own session
+    fn upload_compressed_demo<M>(
+        &self,
+        state: &MACState,
+        path: &Path,
+        header: &Header,
+        bytes: Vec<u8>,
+    ) -> Option<Handled<M>> {
+        let path = path.to_path_buf();
+        let server = header.server.clone();
+        let map = header.map.clone();
+        let host = state.settings.masterbase_host().to_owned();
+        let key = state.settings.masterbase_key().to_owned();
+        let http = state.settings.use_masterbase_http();
+        let (late_offset, late_len) = self.late_bytes;
+        let live_session = self.session.clone();
+        let key_rejected = self.key_rejected.clone();
+        let uploaded = self.uploaded.clone();
+
+        Handled::future(async move {
+            // Only one session can be open at a time, so don't interrupt the
+            // demo being recorded
+            if matches!(
+                live_session.0.try_lock().as_deref(),
+                Ok(Ok(_) | Err(SessionMissingReason::Uninit)) | Err(_)
+            ) {
+                tracing::warn!("Not uploading {path:?} while another demo is being uploaded.");
+                return None;
+            }
+
+            let late_bytes = usize::try_from(late_offset)
+                .ok()
+                .and_then(|start| bytes.get(start..start.checked_add(late_len)?))
+                .filter(|late| late_bytes_written(late))
+                .map(<[u8]>::to_vec);
+            let Some(late_bytes) = late_bytes else {
+                tracing::warn!("{path:?} wasn't finished being recorded, not uploading it.");
+                return None;
+            };
+
+            // The name it was recorded under, without the `.gz`
+            let demo_name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut session = match DemoSession::new(host, key, &server, &map, &demo_name, http)
+                .await
+            {
+                Ok(session) => session,
+                Err(masterbase::Error::Unauthorized) => {
+                    key_rejected.store(true, Ordering::Relaxed);
+                    tracing::error!("The masterbase rejected the provided key. Demo uploads have been disabled until a new key is set.");
+                    return None;
+                }
+                Err(e) => {
+                    tracing::error!("Could not open demo session for {path:?}: {e}");
+                    return None;
+                }
+            };
+
+            // Sent in one go, so it's always resent from the start
+            let rebuild = |_| decompress_demo(&path, MAX_DECOMPRESSED_DEMO_LEN);
+            if let Err(e) = session.send_bytes_or_resume(bytes, rebuild).await {
+                tracing::error!("Failed to upload compressed demo {path:?}: {e}");
+                return None;
+            }
+            match session.send_late_bytes(late_bytes).await {
+                Ok(resp) if resp.status().is_success() => {
+                    tracing::info!("Uploaded compressed demo {path:?}");
+                    uploaded.lock().await.insert(path);
+                }
+                Ok(resp) => tracing::error!(
+                    "Failed to upload late bytes of {path:?}: Server returned {}",
+                    resp.status()
+                ),
+                Err(e) => tracing::error!("Failed to upload late bytes of {path:?}: {e}"),
+            }
+
+            None
+        })
+    }
+
     fn handle_demo_bytes<M: Is<DemoMessage>>(
         &mut self,
         state: &MACState,
@@ -514,7 +1198,8 @@ impl DemoManager {
             .as_ref()
             .map_or(true, |d| !(d.file_path == msg.file_path && d.id == msg.id))
         {
-            self.new_demo(msg.file_path.clone(), msg.id, state.settings.upload_demos());
+            let uploads_enabled = self.uploads_enabled(&state.settings);
+            self.new_demo(msg.file_path.clone(), msg.id, uploads_enabled);
         }
 
         let demo = self
@@ -523,20 +1208,42 @@ impl DemoManager {
             .expect("self.new_demo should have guaranteed a valid demo is present.");
 
         let parsed_header = demo.header.is_some();
+        let already_failed = demo.failed;
 
         let mut events = Vec::new();
 
+        if let Some(saved_offset) = msg.resumed_from {
+            demo.resume_from(saved_offset);
+        }
+
         // Don't parse contents if the user only wants minimal parsing, except
         // if we still need to extract the headers.
         if !(parsed_header && state.settings.minimal_demo_parsing()) {
             events.extend(
-                demo.append_bytes(&msg.bytes)
+                demo.append_bytes(&msg.bytes, state.settings.disabled_demo_events())
                     .into_iter()
                     .map(Handled::single),
             );
         }
 
-        if !state.settings.upload_demos() {
+        if !self.uploads_enabled(&state.settings) {
+            return Handled::multiple(events);
+        }
+
+        // A session can't be opened without the header, so stop any uploads
+        // from waiting for one
+        if demo.failed && demo.header.is_none() {
+            if !already_failed {
+                tracing::warn!(
+                    "Couldn't read the header of {:?}, it won't be uploaded.",
+                    msg.file_path
+                );
+                let session = self.session.clone();
+                events.push(Handled::future(async move {
+                    session.disable().await;
+                    None
+                }));
+            }
             return Handled::multiple(events);
         }
 
@@ -550,21 +1257,27 @@ impl DemoManager {
         // Open new demo session if we've extracted the header
         if let Some(header) = demo.header.as_ref() {
             if !parsed_header {
-                events.push(
-                    self.session
-                        .open_new_session(&state.settings, header, &file_name),
-                );
+                self.reported
+                    .lock()
+                    .expect("Reported players lock poisoned")
+                    .clear();
+                events.push(self.session.open_new_session(
+                    &state.settings,
+                    header,
+                    &file_name,
+                    self.key_rejected.clone(),
+                ));
 
                 // Once a new session is opened, report any bots already on the server
                 events.push(
                     self.report_players(
+                        state,
                         // Go from SteamID to (SteamID, ReportReason) if the player is marked as a cheater or bot
                         state
                             .players
                             .connected
                             .iter()
-                            .map(|&p| (p, state.players.verdict(p)))
-                            .filter_map(|(s, r)| ReportReason::try_from(r).ok().map(|r| (s, r))),
+                            .filter_map(|&s| Self::report_reason(state, s).map(|r| (s, r))),
                     ),
                 );
             }
@@ -591,29 +1304,68 @@ impl Default for DemoManager {
 
 impl<IM, OM> MessageHandler<MACState, IM, OM> for DemoManager
 where
-    IM: Is<DemoBytes> + Is<NewPlayers> + Is<UserUpdates>,
-    OM: Is<DemoMessage>,
+    IM: Is<DemoBytes>
+        + Is<CompressedDemo>
+        + Is<DecompressedDemo>
+        + Is<NewPlayers>
+        + Is<UserUpdates>
+        + Is<Preferences>
+        + Is<WebRequest>,
+    OM: Is<DemoMessage> + Is<DecompressedDemo>,
 {
     fn handle_message(&mut self, state: &MACState, message: &IM) -> Option<Handled<OM>> {
+        if let Some(WebRequest::GetDemos(tx)) = try_get(message) {
+            return self.handle_list_demos(state, tx);
+        }
+
+        // Give a new key a chance if the old one was rejected
+        if let Some(Preferences {
+            internal:
+                Some(InternalPreferences {
+                    masterbase_key: Some(_),
+                    ..
+                }),
+            external: _,
+        }) = try_get(message)
+        {
+            if self.key_rejected.swap(false, Ordering::Relaxed) {
+                tracing::info!("New masterbase key set, re-enabling demo uploads.");
+            }
+            return None;
+        }
+
         // Report newly connecting bots
         if let Some(players) = try_get::<NewPlayers>(message) {
             return self.report_players(
+                state,
                 players
                     .0
                     .iter()
-                    .map(|&p| (p, state.players.verdict(p)))
-                    .filter_map(|(s, r)| ReportReason::try_from(r).ok().map(|r| (s, r))),
+                    .filter_map(|&s| Self::report_reason(state, s).map(|r| (s, r))),
             );
         }
 
-        // Report newly marked bots
+        // Report newly marked bots, and players newly flagged by the heuristics
         if let Some(updates) = try_get::<UserUpdates>(message) {
             return self.report_players(
-                updates
-                    .0
-                    .iter()
-                    .filter_map(|(&s, u)| u.local_verdict.map(|v| (s, v)))
-                    .filter_map(|(s, v)| ReportReason::try_from(v).ok().map(|r| (s, r))),
+                state,
+                updates.0.iter().filter_map(|(&s, u)| {
+                    if let Some(reason) = u.local_verdict.and_then(|v| v.try_into().ok()) {
+                        return Some((s, reason));
+                    }
+
+                    let confidence = u
+                        .custom_data
+                        .as_ref()
+                        .and_then(AutoConfidence::from_custom_data)?;
+                    let reason = Self::auto_report_reason(
+                        &state.settings,
+                        u.local_verdict.unwrap_or_else(|| state.players.verdict(s)),
+                        u.muted.unwrap_or_else(|| state.players.is_muted(s)),
+                        Some(&confidence),
+                    )?;
+                    Some((s, reason))
+                }),
             );
         }
 
@@ -622,92 +1374,380 @@ where
             return self.handle_demo_bytes(state, demo_bytes);
         }
 
+        if let Some(CompressedDemo(path)) = try_get(message) {
+            return Self::handle_compressed_demo(path);
+        }
+
+        if let Some(demo) = try_get::<DecompressedDemo>(message) {
+            return self.handle_decompressed_demo(state, demo);
+        }
+
         None
     }
 }
 
 impl OpenDemo {
+    #[must_use]
+    pub fn new(file_path: PathBuf, id: usize) -> Self {
+        Self {
+            file_path,
+            id,
+            header: None,
+            handler: DemoHandler::with_analyser(GameStateAnalyser::new()),
+            bytes: Vec::new(),
+            offset: 0,
+            failed: false,
+            skip_events_until: 0,
+        }
+    }
+
     /// Append the provided bytes to the current demo being watched, and handle
     /// any packets
-    fn append_bytes(&mut self, bytes: &[u8]) -> Vec<DemoMessage> {
+    fn append_bytes(&mut self, bytes: &[u8], disabled: &[DemoEventKind]) -> Vec<DemoMessage> {
         if bytes.is_empty() {
             return Vec::new();
         }
 
         self.bytes.extend_from_slice(bytes);
-        self.process_next_chunk()
+        if self.failed {
+            return Vec::new();
+        }
+
+        self.process_next_chunk(disabled)
+    }
+
+    /// Picks the demo up again after a restart. It's parsed from the start,
+    /// but events before `saved_offset` were already extracted, so they're
+    /// dropped rather than sent again.
+    fn resume_from(&mut self, saved_offset: u64) {
+        tracing::info!(
+            "Skipping events from {:?} before position {saved_offset} since they were read before a restart.",
+            self.file_path
+        );
+        self.skip_events_until =
+            usize::try_from(saved_offset.saturating_mul(8)).unwrap_or(usize::MAX);
     }
 
     /// Attempt to parse any new bytes that have been added since the last call
     /// as packets
-    #[allow(clippy::cognitive_complexity)]
-    fn process_next_chunk(&mut self) -> Vec<DemoMessage> {
+    fn process_next_chunk(&mut self, disabled: &[DemoEventKind]) -> Vec<DemoMessage> {
         tracing::debug!("New demo length: {}", self.bytes.len());
 
-        let mut out = Vec::new();
+        let chunk = parse_demo_chunk(
+            &mut self.header,
+            &mut self.handler,
+            &self.bytes,
+            self.offset,
+            self.skip_events_until,
+            disabled,
+        );
+        self.offset = chunk.offset;
+        if let Some(e) = chunk.error {
+            tracing::error!("{e}");
+            tracing::error!(
+                "Stopped parsing {:?}, events will be extracted again from the next demo.",
+                self.file_path
+            );
+            self.failed = true;
+        }
 
-        let buffer = BitReadBuffer::new(&self.bytes, LittleEndian);
-        let mut stream = BitReadStream::new(buffer);
-        stream
-            .set_pos(self.offset)
-            .expect("Couldn't set stream position");
-
-        // Parse header if there isn't one already
-        if self.header.is_none() {
-            match Header::read(&mut stream) {
-                Ok(header) => {
-                    self.handler.handle_header(&header);
-                    self.header = Some(header);
-                    self.offset = stream.pos();
-                }
-                Err(BitError::NotEnoughData {
-                    requested,
-                    bits_left,
-                }) => {
-                    tracing::warn!("Tried to read header but there were not enough bits. Requested: {}, Remaining: {}", requested, bits_left);
-                    return out;
-                }
-                Err(e) => {
-                    tracing::error!("Error reading demo header: {}", e);
-                    return out;
-                }
+        chunk.messages
+    }
+}
+
+/// How many bytes are at the start of every demo for the header
+const HEADER_LEN: u64 = 0x430;
+
+/// Reads the `len` bytes at `offset` in the demo, if they've been written to
+/// yet. They're all zero until the recording is finished.
+///
+/// # Errors
+/// On IO errors
+fn read_late_bytes(file_path: &Path, offset: u64, len: usize) -> std::io::Result<Option<Vec<u8>>> {
+    // Check the file is long enough to have data at the late byte address
+    // before reading them
+    let Some(min_valid_filelen) = offset.checked_add(len as u64) else {
+        return Ok(None);
+    };
+    if metadata(file_path)?.len() < min_valid_filelen {
+        return Ok(None);
+    }
+
+    let mut file = File::open(file_path)?;
+
+    file.seek(std::io::SeekFrom::Start(offset))?;
+    let mut out = vec![0; len];
+    file.read_exact(&mut out)?;
+
+    if late_bytes_written(&out) {
+        tracing::debug!("Late bytes found in demo recording.");
+        Ok(Some(out))
+    } else {
+        tracing::debug!("No new bytes from demo.");
+        Ok(None)
+    }
+}
+
+/// Reads `len` bytes at `offset` in the demo, e.g. to send them again
+///
+/// # Errors
+/// On IO errors, or if the demo is shorter than that now
+fn read_demo_range(file_path: &Path, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(file_path)?;
+    file.seek(std::io::SeekFrom::Start(offset))?;
+    let mut out = vec![0; len];
+    file.read_exact(&mut out)?;
+    Ok(out)
+}
+
+/// Whether the late bytes have been written to. The first 8 bytes are always
+/// all zeroes until they are.
+fn late_bytes_written(late_bytes: &[u8]) -> bool {
+    late_bytes.len() > 8 && late_bytes.iter().take(8).any(|&b| b != 0)
+}
+
+/// Inflates a compressed demo, failing if it would be more than `limit` bytes
+///
+/// # Errors
+/// If the file couldn't be read or decompressed, or is too large
+fn decompress_demo(path: &Path, limit: u64) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    MultiGzDecoder::new(File::open(path)?)
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 > limit {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Demo is more than {limit} bytes when decompressed"),
+        ));
+    }
+
+    Ok(bytes)
+}
+
+#[derive(Debug, Error)]
+pub enum ReadHeaderError {
+    #[error("Couldn't read demo: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid demo header: {0}")]
+    Header(#[from] BitError),
+}
+
+/// Reads only the header of a demo file
+///
+/// # Errors
+/// If the file couldn't be read or doesn't start with a valid header
+pub fn read_header(path: &Path) -> Result<Header, ReadHeaderError> {
+    let mut bytes = Vec::new();
+    File::open(path)?.take(HEADER_LEN).read_to_end(&mut bytes)?;
+
+    let mut stream = BitReadStream::new(BitReadBuffer::new(&bytes, LittleEndian));
+    Ok(Header::read(&mut stream)?)
+}
+
+/// A demo on disk, as listed by `GET /mac/demos/v1`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DemoListing {
+    pub filename: String,
+    pub path: PathBuf,
+    pub map: Option<String>,
+    pub server: Option<String>,
+    /// In seconds. Only written at the end of the recording, so is 0 for
+    /// demos still being recorded.
+    pub duration: Option<f32>,
+    pub recorded_at: Option<DateTime<Utc>>,
+    /// If it was completely uploaded to the masterbase this session
+    pub uploaded: bool,
+    /// Why the demo's header couldn't be read
+    pub error: Option<String>,
+}
+
+/// Lists the demos in the given folders, most recently recorded first
+fn list_demos(dirs: &[PathBuf], uploaded: &HashSet<PathBuf>) -> Vec<DemoListing> {
+    let mut demos: Vec<DemoListing> = dirs
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "dem"))
+        .map(|path| {
+            let recorded_at = metadata(&path)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::<Utc>::from);
+            let header = read_header(&path);
+
+            DemoListing {
+                filename: path
+                    .file_name()
+                    .map(|f| f.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                map: header.as_ref().ok().map(|h| h.map.clone()),
+                server: header.as_ref().ok().map(|h| h.server.clone()),
+                duration: header.as_ref().ok().map(|h| h.duration),
+                recorded_at,
+                uploaded: uploaded.contains(&path),
+                error: header.err().map(|e| e.to_string()),
+                path,
+            }
+        })
+        .collect();
+
+    demos.sort_by(|a, b| b.recorded_at.cmp(&a.recorded_at));
+    demos
+}
+
+#[derive(Debug, Error)]
+pub enum DemoParseError {
+    #[error("Couldn't move to position {0} in demo: {1}")]
+    Position(usize, BitError),
+    #[error("Error reading demo header: {0}")]
+    Header(BitError),
+    #[error("Error reading demo packet: {0}")]
+    Packet(tf_demo_parser::ParseError),
+}
+
+/// Everything that could be parsed from the bytes of a demo so far
+#[derive(Debug)]
+pub struct ParsedChunk {
+    pub messages: Vec<DemoMessage>,
+    /// Where to continue parsing from once more bytes are available
+    pub offset: usize,
+    /// Set if parsing stopped because the demo is malformed, rather than
+    /// because it reached the end of the bytes available
+    pub error: Option<DemoParseError>,
+}
+
+/// Parses as many packets as possible from `bytes`, starting at the bit
+/// `offset`. The header is read first if it hasn't been already. Events are
+/// only extracted from packets which end after the bit `skip_until`. Doesn't
+/// touch anything other than its arguments, so it can be used to fuzz the
+/// demo parser.
+#[allow(clippy::cognitive_complexity)]
+pub fn parse_demo_chunk(
+    header: &mut Option<Header>,
+    handler: &mut DemoHandler<GameStateAnalyser>,
+    bytes: &[u8],
+    mut offset: usize,
+    skip_until: usize,
+    disabled: &[DemoEventKind],
+) -> ParsedChunk {
+    let mut messages = Vec::new();
+    let chunk = |messages, offset, error| ParsedChunk {
+        messages,
+        offset,
+        error,
+    };
+
+    let buffer = BitReadBuffer::new(bytes, LittleEndian);
+    let mut stream = BitReadStream::new(buffer);
+    if let Err(e) = stream.set_pos(offset) {
+        return chunk(messages, offset, Some(DemoParseError::Position(offset, e)));
+    }
+
+    // Parse header if there isn't one already
+    if header.is_none() {
+        match Header::read(&mut stream) {
+            Ok(h) => {
+                handler.handle_header(&h);
+                *header = Some(h);
+                offset = stream.pos();
+            }
+            Err(BitError::NotEnoughData {
+                requested,
+                bits_left,
+            }) => {
+                tracing::warn!("Tried to read header but there were not enough bits. Requested: {}, Remaining: {}", requested, bits_left);
+                return chunk(messages, offset, None);
+            }
+            Err(e) => {
+                return chunk(messages, offset, Some(DemoParseError::Header(e)));
             }
         }
+    }
 
-        // Parse packets
-        let mut packets: RawPacketStream = RawPacketStream::new(stream);
-        loop {
-            match packets.next(&self.handler.state_handler) {
-                Ok(Some(packet)) => {
-                    out.append(&mut handle_packet(&packet, self.handler.borrow_output()));
-                    self.handler
-                        .handle_packet(packet)
-                        .expect("Couldn't handle packet");
-                    self.offset = packets.pos();
-                }
-                Ok(None) => {
-                    break;
-                }
-                Err(tf_demo_parser::ParseError::ReadError(BitError::NotEnoughData {
-                    requested,
-                    bits_left,
-                })) => {
-                    tracing::warn!("Tried to read packet but there were not enough bits. Requested: {}, Remaining: {}", requested, bits_left);
-                    break;
+    // Parse packets
+    let mut packets: RawPacketStream = RawPacketStream::new(stream);
+    loop {
+        match packets.next(&handler.state_handler) {
+            Ok(Some(packet)) => {
+                if packets.pos() > skip_until {
+                    messages.append(&mut handle_packet(
+                        &packet,
+                        handler.borrow_output(),
+                        disabled,
+                    ));
                 }
-                Err(e) => {
-                    tracing::error!("Error reading demo packet: {}", e);
-                    return out;
+                if let Err(e) = handler.handle_packet(packet) {
+                    return chunk(messages, offset, Some(DemoParseError::Packet(e)));
                 }
+                offset = packets.pos();
+            }
+            Ok(None) => {
+                break;
+            }
+            Err(tf_demo_parser::ParseError::ReadError(BitError::NotEnoughData {
+                requested,
+                bits_left,
+            })) => {
+                tracing::warn!("Tried to read packet but there were not enough bits. Requested: {}, Remaining: {}", requested, bits_left);
+                break;
+            }
+            Err(e) => {
+                return chunk(messages, offset, Some(DemoParseError::Packet(e)));
             }
         }
+    }
+
+    chunk(messages, offset, None)
+}
 
-        out
+/// Which group of events a game event belongs to, if it's one we extract
+const fn demo_event_kind(event: &GameEvent) -> Option<DemoEventKind> {
+    match event {
+        GameEvent::VoteStarted(_) | GameEvent::VoteOptions(_) | GameEvent::VoteCast(_) => {
+            Some(DemoEventKind::Votes)
+        }
+        GameEvent::MvmBeginWave(_) | GameEvent::MvmPickupCurrency(_) => {
+            Some(DemoEventKind::MannVsMachine)
+        }
+        GameEvent::TeamPlayRoundStart(_) | GameEvent::TeamPlayRoundWin(_) => {
+            Some(DemoEventKind::Rounds)
+        }
+        GameEvent::PlayerConnect(_)
+        | GameEvent::PlayerConnectClient(_)
+        | GameEvent::PlayerDisconnect(_) => Some(DemoEventKind::Connections),
+        _ => None,
     }
 }
 
+/// Finds the `SteamID` of the player with the given userid, going by their
+/// network id if the event has one and otherwise by the players the demo
+/// knows about. Bots have neither.
+fn resolve_userid(state: &GameState, userid: u16, network_id: &str) -> Option<SteamID> {
+    SteamID::from_steam3(network_id).ok().or_else(|| {
+        state.players.iter().find_map(|p| {
+            p.info.as_ref().and_then(|i| {
+                if i.user_id == userid {
+                    SteamID::from_steam3(&i.steam_id).ok()
+                } else {
+                    None
+                }
+            })
+        })
+    })
+}
+
 #[allow(clippy::cognitive_complexity)]
-fn handle_packet(packet: &Packet, state: &GameState) -> Vec<DemoMessage> {
+#[allow(clippy::too_many_lines)]
+fn handle_packet(
+    packet: &Packet,
+    state: &GameState,
+    disabled: &[DemoEventKind],
+) -> Vec<DemoMessage> {
     let mut out = Vec::new();
 
     if let Packet::Message(MessagePacket {
@@ -726,6 +1766,11 @@ fn handle_packet(packet: &Packet, state: &GameState) -> Vec<DemoMessage> {
                 continue;
             };
 
+            // Events without a kind can't be disabled
+            if demo_event_kind(event).is_some_and(|kind| disabled.contains(&kind)) {
+                continue;
+            }
+
             match event {
                 // This event exists as per the Source Demo spec, but has never been extracted by tf-demo-parser
                 // We should not rely on it ever occuring, but ideally it should. This may 'just work (tm)' in
@@ -762,6 +1807,75 @@ fn handle_packet(packet: &Packet, state: &GameState) -> Vec<DemoMessage> {
                         }),
                     ),
                 }),
+                GameEvent::MvmBeginWave(MvmBeginWaveEvent {
+                    wave_index,
+                    max_waves,
+                    ..
+                }) => out.push(DemoMessage {
+                    tick: tick.0,
+                    event: DemoEvent::MvmWaveStarted {
+                        wave: u32::from(*wave_index) + 1,
+                        max_waves: u32::from(*max_waves),
+                    },
+                }),
+                GameEvent::MvmPickupCurrency(MvmPickupCurrencyEvent { currency, .. }) => {
+                    out.push(DemoMessage {
+                        tick: tick.0,
+                        event: DemoEvent::MvmCurrencyCollected(u32::from(*currency)),
+                    });
+                }
+                GameEvent::TeamPlayRoundStart(TeamPlayRoundStartEvent { full_reset }) => {
+                    out.push(DemoMessage {
+                        tick: tick.0,
+                        event: DemoEvent::RoundStart {
+                            full_reset: *full_reset,
+                        },
+                    });
+                }
+                GameEvent::TeamPlayRoundWin(TeamPlayRoundWinEvent { team, .. }) => {
+                    // Anything other than red or blu winning is a stalemate
+                    let winner = Team::try_from(u32::from(*team))
+                        .ok()
+                        .filter(|t| matches!(t, Team::Red | Team::Blu));
+                    out.push(DemoMessage {
+                        tick: tick.0,
+                        event: DemoEvent::RoundEnd { winner },
+                    });
+                }
+                // Servers send `player_connect_client` to clients, but handle
+                // both in case a demo has the other.
+                GameEvent::PlayerConnect(PlayerConnectEvent {
+                    name,
+                    user_id,
+                    network_id,
+                    ..
+                })
+                | GameEvent::PlayerConnectClient(PlayerConnectClientEvent {
+                    name,
+                    user_id,
+                    network_id,
+                    ..
+                }) => out.push(DemoMessage {
+                    tick: tick.0,
+                    event: DemoEvent::PlayerConnected {
+                        steamid: resolve_userid(state, *user_id, &network_id.to_string()),
+                        name: name.to_string(),
+                        userid: *user_id,
+                    },
+                }),
+                GameEvent::PlayerDisconnect(PlayerDisconnectEvent {
+                    user_id,
+                    reason,
+                    network_id,
+                    ..
+                }) => out.push(DemoMessage {
+                    tick: tick.0,
+                    event: DemoEvent::PlayerDisconnected {
+                        steamid: resolve_userid(state, *user_id, &network_id.to_string()),
+                        userid: *user_id,
+                        reason: reason.to_string(),
+                    },
+                }),
                 // GameEvent::VoteEnded(e) => {
                 //     tracing::info!("Vote ended: {:?}", e);
                 // }
@@ -774,12 +1888,6 @@ fn handle_packet(packet: &Packet, state: &GameState) -> Vec<DemoMessage> {
                 // GameEvent::VoteChanged(e) => {
                 //     tracing::info!("Vote changed: {:?}", e);
                 // }
-                // GameEvent::PlayerConnect(e) => {
-                //     tracing::info!("Player connect: {:?}", e);
-                // }
-                // GameEvent::PlayerConnectClient(e) => {
-                //     tracing::info!("Player connect client: {:?}", e);
-                // }
                 // GameEvent::PlayerInfo(e) => {
                 //     tracing::info!("Player info: {:?}", e);
                 // }
@@ -908,9 +2016,277 @@ where
                 let initiator = event.initiator;
                 tracing::info!("{issue} - called by {initiator}");
             }
-            DemoEvent::LatestTick => {}
+            DemoEvent::MvmWaveStarted { .. }
+            | DemoEvent::MvmCurrencyCollected(_)
+            | DemoEvent::RoundStart { .. }
+            | DemoEvent::RoundEnd { .. }
+            | DemoEvent::PlayerConnected { .. }
+            | DemoEvent::PlayerDisconnected { .. }
+            | DemoEvent::LatestTick => {}
         }
 
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    };
+
+    use tf_demo_parser::demo::parser::{gamestateanalyser::GameStateAnalyser, DemoHandler};
+
+    use super::{
+        decompress_demo, is_compressed_demo, parse_demo_chunk, read_late_bytes, DemoManager,
+        DemoManagerSession, DemoParseError, DemoProgress, DemoWatcher, OpenDemo,
+        SessionMissingReason, DEMO_LATE_BYTES_LEN, DEMO_LATE_BYTES_OFFSET, HEADER_LEN,
+    };
+    use crate::{
+        confidence::{AutoConfidence, AUTO_FLAG_SCORE},
+        masterbase::ReportReason,
+        player_records::Verdict,
+        settings::{PreexistingDemoMode, ReportPolicy, Settings},
+    };
+
+    fn garbage() -> Vec<u8> {
+        (0..=u8::MAX)
+            .cycle()
+            .take(4096)
+            .map(|b| b.wrapping_mul(31))
+            .collect()
+    }
+
+    #[test]
+    pub fn corrupt_demo_does_not_panic() {
+        // Garbage after a header sized chunk of bytes
+        let mut demo = OpenDemo::new(PathBuf::from("corrupt.dem"), 0);
+        let bytes = garbage();
+        demo.append_bytes(&bytes, &[]);
+        demo.append_bytes(&bytes, &[]);
+
+        // An offset past the end of the demo, e.g. after it was shortened
+        let mut handler = DemoHandler::with_analyser(GameStateAnalyser::new());
+        let chunk = parse_demo_chunk(&mut None, &mut handler, &[0; 16], 1000, 0, &[]);
+        assert!(chunk.messages.is_empty());
+        assert_eq!(chunk.offset, 1000);
+        assert!(matches!(chunk.error, Some(DemoParseError::Position(..))));
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn late_bytes_position() {
+        let path = std::env::temp_dir().join("mac_late_bytes.dem");
+        let read = |bytes: &[u8]| {
+            std::fs::write(&path, bytes).expect("Couldn't write demo");
+            read_late_bytes(&path, DEMO_LATE_BYTES_OFFSET, DEMO_LATE_BYTES_LEN)
+                .expect("Couldn't read demo")
+        };
+
+        // Not written yet
+        let mut header = vec![0; HEADER_LEN as usize];
+        assert_eq!(read(&header), None);
+        assert_eq!(read(&header[..0x100]), None);
+
+        // Written at the end of the recording, just before the end of the header
+        let late: Vec<u8> = (1..=16).collect();
+        let start = DEMO_LATE_BYTES_OFFSET as usize;
+        header[start..start + DEMO_LATE_BYTES_LEN].copy_from_slice(&late);
+        header.extend_from_slice(&[0xff; 64]);
+        assert_eq!(read(&header), Some(late));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    pub fn compressed_demo_size_limit() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("mac_limit_{}.dem.gz", std::process::id()));
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&[0; 4096]).expect("Couldn't compress");
+        std::fs::write(&path, encoder.finish().expect("Couldn't compress"))
+            .expect("Couldn't write demo");
+
+        assert_eq!(
+            decompress_demo(&path, 4096).expect("Under the limit").len(),
+            4096
+        );
+        assert!(decompress_demo(&path, 4095).is_err());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    pub fn compressed_demo_extension() {
+        assert!(is_compressed_demo(Path::new(
+            "demos/2024-01-01_pl_upward.dem.gz"
+        )));
+        assert!(!is_compressed_demo(Path::new("demos/pl_upward.dem")));
+        assert!(!is_compressed_demo(Path::new("demos/logs.tar.gz")));
+    }
+
+    #[test]
+    pub fn replay_file() {
+        let missing = std::env::temp_dir().join("mac_replay_missing.dem");
+        assert!(DemoManager::replay_file(&missing, &[]).is_err());
+
+        // The whole file is available at once, so the header and packets are
+        // read in one go
+        let path = std::env::temp_dir().join("mac_replay_corrupt.dem");
+        std::fs::write(&path, garbage()).expect("Couldn't write demo");
+        let replayed = DemoManager::replay_file(&path, &[]);
+        std::fs::remove_file(&path).ok();
+        assert!(replayed.is_ok());
+    }
+
+    #[test]
+    pub fn demo_in_progress_at_launch() {
+        let launched = SystemTime::now();
+        let before = launched - Duration::from_secs(60);
+        let after = launched + Duration::from_secs(60);
+        let offset = |mode, created| DemoWatcher::initial_offset(mode, created, launched, 5000);
+
+        // Demos already being recorded are handled according to the mode
+        assert_eq!(offset(PreexistingDemoMode::Skip, Some(before)), None);
+        assert_eq!(
+            offset(PreexistingDemoMode::ResumeFromOffset, Some(before)),
+            Some(5000)
+        );
+        assert_eq!(
+            offset(PreexistingDemoMode::ReadFromStart, Some(before)),
+            Some(0)
+        );
+
+        // Demos started after launch are always read from the start
+        assert_eq!(offset(PreexistingDemoMode::Skip, Some(after)), Some(0));
+        assert_eq!(
+            offset(PreexistingDemoMode::ResumeFromOffset, Some(after)),
+            Some(0)
+        );
+        assert_eq!(offset(PreexistingDemoMode::Skip, None), Some(0));
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn resumed_demo_has_no_gap() {
+        let dir = std::env::temp_dir().join(format!("mac_resume_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Couldn't create demo dir");
+        let path = dir.join("resumed.dem");
+        let demo = garbage();
+        std::fs::write(&path, &demo).expect("Couldn't write demo");
+
+        let mut watcher =
+            DemoWatcher::new(&dir, PreexistingDemoMode::Skip).expect("Couldn't watch demos");
+        watcher.current_demo = Some(path.clone());
+
+        // Everything before the saved position is uploaded again too
+        let resumed = watcher.resumed_bytes(3000).expect("Demo has bytes");
+        assert_eq!(resumed.resumed_from, Some(3000));
+        assert_eq!(resumed.bytes, demo);
+
+        // Then it carries on from the end
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .expect("Couldn't open demo");
+        std::io::Write::write_all(&mut file, &[0xff; 64]).expect("Couldn't write demo");
+        let next = watcher.next_bytes().expect("More bytes were written");
+        assert_eq!(next.resumed_from, None);
+        assert_eq!(next.bytes, [0xff; 64]);
+
+        // Nothing past the header was read before, so it's read like a new demo
+        std::fs::write(&path, &demo[..HEADER_LEN as usize]).expect("Couldn't write demo");
+        let fresh = watcher.resumed_bytes(HEADER_LEN).expect("Demo has bytes");
+        assert_eq!(fresh.resumed_from, None);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    pub fn demo_without_header_is_not_uploaded() {
+        // Picked up part way through, so the header was never seen
+        let mut demo = OpenDemo::new(PathBuf::from("midway.dem"), 0);
+        demo.append_bytes(&[0xff; 2048], &[]);
+        assert!(demo.failed);
+        assert!(demo.header.is_none());
+
+        // Uploads waiting for a session are let go once it's disabled
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("Couldn't build runtime");
+        rt.block_on(async {
+            let session = DemoManagerSession::new(SessionMissingReason::Uninit);
+            let mut waiting = session.clone();
+            let upload = tokio::spawn(async move {
+                matches!(*waiting.get().await, Err(SessionMissingReason::Disabled))
+            });
+
+            session.disable().await;
+            let disabled = tokio::time::timeout(Duration::from_secs(1), upload)
+                .await
+                .expect("Upload kept waiting for a session")
+                .expect("Upload panicked");
+            assert!(disabled);
+        });
+    }
+
+    #[test]
+    pub fn demo_progress_matches() {
+        let created = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let path = Path::new("demos/pl_upward.dem");
+        let progress = DemoProgress {
+            path: path.to_path_buf(),
+            offset: 5000,
+            created: Some(1_700_000_000),
+        };
+
+        assert!(progress.matches(path, Some(created), 5000));
+        assert!(progress.matches(path, Some(created), 8000));
+
+        // A different demo, or the same one recorded over
+        assert!(!progress.matches(Path::new("demos/pl_badwater.dem"), Some(created), 8000));
+        assert!(!progress.matches(path, Some(created + Duration::from_secs(1)), 8000));
+        assert!(!progress.matches(path, Some(created), 4000));
+        assert!(!progress.matches(path, None, 8000));
+    }
+
+    #[test]
+    pub fn auto_reports_only_flagged_unmuted_players() {
+        let mut settings = Settings::default();
+        let flagged = AutoConfidence {
+            score: AUTO_FLAG_SCORE,
+            signals: Vec::new(),
+        };
+        let unflagged = AutoConfidence {
+            score: AUTO_FLAG_SCORE - 1,
+            signals: Vec::new(),
+        };
+
+        settings.set_report_policy(ReportPolicy::IncludeAutomatic);
+        assert_eq!(
+            DemoManager::auto_report_reason(&settings, Verdict::Player, false, Some(&flagged)),
+            Some(ReportReason::Cheater)
+        );
+        assert_eq!(
+            DemoManager::auto_report_reason(&settings, Verdict::Player, true, Some(&flagged)),
+            None
+        );
+        assert_eq!(
+            DemoManager::auto_report_reason(&settings, Verdict::Player, false, Some(&unflagged)),
+            None
+        );
+        assert_eq!(
+            DemoManager::auto_report_reason(&settings, Verdict::Trusted, false, Some(&flagged)),
+            None
+        );
+
+        settings.set_report_policy(ReportPolicy::ManualOnly);
+        assert_eq!(
+            DemoManager::auto_report_reason(&settings, Verdict::Player, false, Some(&flagged)),
+            None
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, path::PathBuf, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::Duration,
+};
 
 use chrono::DateTime;
 use event_loop::Message;
@@ -6,25 +10,145 @@ use serde::{Deserialize, Serialize};
 use steamid_ng::SteamID;
 use tokio::sync::mpsc::Receiver;
 
-use crate::{player_records::Verdict, settings::FriendsAPIUsage, state::MACState};
+use crate::{
+    command_manager::KickReason,
+    player_records::Verdict,
+    settings::{
+        DemoEventKind, DisplayStyle, FriendsAPIUsage, NameSource, PlayerSort, PreexistingDemoMode,
+        ReportPolicy,
+    },
+    state::MACState,
+};
+
+/// How often a [`Refresh`] is emitted
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Why a [`Refresh`] was emitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshReason {
+    /// The regular refresh every [`REFRESH_INTERVAL`]
+    Timer,
+    /// Requested by the user
+    Manual,
+    /// The user has joined a different server
+    ServerChange,
+}
 
 #[derive(Debug, Clone, Copy)]
-pub struct Refresh;
+pub struct Refresh {
+    pub reason: RefreshReason,
+}
+
+impl Refresh {
+    #[must_use]
+    pub const fn timer() -> Self {
+        Self {
+            reason: RefreshReason::Timer,
+        }
+    }
+}
+
 impl Message<MACState> for Refresh {
     fn update_state(self, state: &mut MACState) {
-        state.players.refresh();
+        // Grace periods are counted in timer refreshes, so extra refreshes
+        // shouldn't bring forward players being disconnected or removed.
+        if self.reason != RefreshReason::Timer {
+            return;
+        }
+
+        // The grace periods are configured in seconds but players are only
+        // checked once per refresh, so convert them to a number of refreshes.
+        let cycles =
+            |secs: u64| u32::try_from(secs / REFRESH_INTERVAL.as_secs()).unwrap_or(u32::MAX);
+
+        state.players.refresh(
+            cycles(state.settings.disconnect_grace_period()),
+            cycles(state.settings.removal_grace_period()),
+        );
     }
 
     #[allow(unused_variables)]
     fn preprocess(&mut self, state: &MACState) {}
 }
 
+/// Empties the requested in-memory caches so their data will be fetched again.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct ClearCaches {
+    #[serde(default)]
+    pub steam_info: bool,
+    #[serde(default)]
+    pub friends: bool,
+    /// Also delete the steam info cache stored on disk
+    #[serde(default)]
+    pub delete_steam_cache: bool,
+}
+impl Message<MACState> for ClearCaches {
+    fn update_state(self, state: &mut MACState) {
+        if self.steam_info {
+            tracing::info!("Clearing steam info cache.");
+            state.players.clear_steam_info(self.delete_steam_cache);
+        }
+        if self.friends {
+            tracing::info!("Clearing friends cache.");
+            state.players.clear_friend_info();
+        }
+    }
+}
+
+/// Runs party detection again straight away, rather than waiting for the
+/// connected players or friends info to change.
+#[derive(Debug, Clone, Copy)]
+pub struct RecomputeParties;
+impl Message<MACState> for RecomputeParties {
+    fn update_state(self, state: &mut MACState) {
+        state.players.recompute_parties();
+    }
+}
+
+/// Pins or unpins a player to the top of the player list for this session.
+#[derive(Debug, Clone, Copy)]
+pub enum PinPlayer {
+    Pin(SteamID),
+    Unpin(SteamID),
+}
+impl Message<MACState> for PinPlayer {
+    fn update_state(self, state: &mut MACState) {
+        match self {
+            Self::Pin(steamid) => state.players.set_pinned(steamid, true),
+            Self::Unpin(steamid) => state.players.set_pinned(steamid, false),
+        }
+    }
+}
+
+/// Removes the records of the given players entirely, forgetting their
+/// verdicts, previous names and custom data. Players without a record are
+/// ignored.
+#[derive(Debug, Clone)]
+pub struct DeleteRecords(pub Vec<SteamID>);
+impl Message<MACState> for DeleteRecords {
+    fn update_state(self, state: &mut MACState) {
+        let mut deleted = 0;
+        for steamid in self.0 {
+            if state.players.records.remove(&steamid).is_some() {
+                deleted += 1;
+            }
+        }
+
+        if deleted > 0 {
+            tracing::info!("Deleted {deleted} player records.");
+            state.players.records.save_ok();
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct UserUpdate {
     #[serde(rename = "localVerdict")]
     pub local_verdict: Option<Verdict>,
     #[serde(rename = "customData")]
     pub custom_data: Option<serde_json::Value>,
+    pub muted: Option<bool>,
 }
 
 #[derive(Debug, Clone)]
@@ -41,6 +165,10 @@ impl Message<MACState> for UserUpdates {
                 record.set_custom_data(custom_data);
             }
 
+            if let Some(muted) = v.muted {
+                record.set_muted(muted);
+            }
+
             if let Some(verdict) = v.local_verdict {
                 record.set_verdict(verdict);
                 if let Some(name) = name {
@@ -92,7 +220,78 @@ pub struct InternalPreferences {
     pub masterbase_host: Option<String>,
     pub rcon_port: Option<u16>,
     pub dumb_autokick: Option<bool>,
+    pub autokick_reason: Option<KickReason>,
+    pub autokick_vote_interval_secs: Option<u64>,
     pub tos_agreement_date: Option<String>,
+    pub cache_avatars: Option<bool>,
+    pub name_source: Option<NameSource>,
+    pub disconnect_grace_period: Option<u64>,
+    pub removal_grace_period: Option<u64>,
+    pub prefetch_record_profiles: Option<bool>,
+    pub report_policy: Option<ReportPolicy>,
+    pub player_sort: Option<PlayerSort>,
+    pub max_history_len: Option<usize>,
+    pub autoexec_commands: Option<Vec<String>>,
+    pub steam_api_calls_per_minute: Option<u32>,
+    pub preexisting_demo_mode: Option<PreexistingDemoMode>,
+    pub recent_ban_days: Option<u32>,
+    pub mark_recently_banned: Option<bool>,
+    pub display_styles: Option<BTreeMap<String, DisplayStyle>>,
+    pub disabled_demo_events: Option<Vec<DemoEventKind>>,
+    pub wait_for_tf2: Option<bool>,
+    pub masterbase_session_retries: Option<u32>,
+    pub masterbase_retry_delay: Option<u64>,
+    pub rcon_command_interval_ms: Option<u64>,
+    pub prioritize_rcon_commands: Option<bool>,
+    pub steam_cache_ttl_minutes: Option<u64>,
+    pub replay_console_log: Option<bool>,
+    pub trusted_friends: Option<Vec<SteamID>>,
+}
+
+/// The config file was changed on disk and should be loaded again
+#[derive(Debug, Clone, Copy)]
+pub struct SettingsReloaded;
+
+impl Message<MACState> for SettingsReloaded {
+    fn update_state(self, state: &mut MACState) {
+        let settings = match state.settings.reload() {
+            Ok(settings) => settings,
+            Err(e) => {
+                tracing::error!("Failed to reload settings, keeping the current ones: {e}");
+                return;
+            }
+        };
+
+        // Saving the settings from here also shows up as a change, but there's
+        // nothing new to load
+        if serde_yaml::to_string(&settings).ok() == serde_yaml::to_string(&state.settings).ok() {
+            return;
+        }
+
+        if settings.webui_port() != state.settings.webui_port()
+            || settings.webui_bind_address() != state.settings.webui_bind_address()
+            || settings.webui_token() != state.settings.webui_token()
+        {
+            tracing::warn!("Web UI settings were changed, restart the client to apply them.");
+        }
+        if settings.rcon_port() != state.settings.rcon_port()
+            || settings.rcon_password() != state.settings.rcon_password()
+        {
+            tracing::info!("RCON settings were changed, reconnecting.");
+        }
+
+        state.players.set_name_source(settings.name_source());
+        state.players.set_player_sort(settings.player_sort());
+        state
+            .players
+            .set_max_history_len(settings.max_history_len());
+        state
+            .steam_api_limiter
+            .set_limit(settings.steam_api_calls_per_minute());
+        state.settings = settings;
+
+        tracing::info!("Reloaded settings from the config file.");
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -129,6 +328,87 @@ impl Message<MACState> for Preferences {
             if let Some(autokick) = internal.dumb_autokick {
                 state.settings.set_autokick_bots(autokick);
             }
+            if let Some(reason) = internal.autokick_reason {
+                state.settings.set_autokick_reason(reason);
+            }
+            if let Some(secs) = internal.autokick_vote_interval_secs {
+                state.settings.set_autokick_vote_interval_secs(secs);
+            }
+            if let Some(cache_avatars) = internal.cache_avatars {
+                state.settings.set_cache_avatars(cache_avatars);
+            }
+            if let Some(name_source) = internal.name_source {
+                state.settings.set_name_source(name_source);
+                state.players.set_name_source(name_source);
+            }
+            if let Some(secs) = internal.disconnect_grace_period {
+                state.settings.set_disconnect_grace_period(secs);
+            }
+            if let Some(secs) = internal.removal_grace_period {
+                state.settings.set_removal_grace_period(secs);
+            }
+            if let Some(prefetch) = internal.prefetch_record_profiles {
+                state.settings.set_prefetch_record_profiles(prefetch);
+            }
+            if let Some(policy) = internal.report_policy {
+                state.settings.set_report_policy(policy);
+            }
+            if let Some(sort) = internal.player_sort {
+                state.settings.set_player_sort(sort);
+                state.players.set_player_sort(sort);
+            }
+            if let Some(len) = internal.max_history_len {
+                state.settings.set_max_history_len(len);
+                state.players.set_max_history_len(len);
+            }
+            if let Some(commands) = internal.autoexec_commands {
+                state.settings.set_autoexec_commands(commands);
+            }
+            if let Some(calls) = internal.steam_api_calls_per_minute {
+                state.settings.set_steam_api_calls_per_minute(calls);
+                state.steam_api_limiter.set_limit(calls);
+            }
+            if let Some(mode) = internal.preexisting_demo_mode {
+                state.settings.set_preexisting_demo_mode(mode);
+            }
+            if let Some(days) = internal.recent_ban_days {
+                state.settings.set_recent_ban_days(days);
+            }
+            if let Some(mark) = internal.mark_recently_banned {
+                state.settings.set_mark_recently_banned(mark);
+            }
+            if let Some(kinds) = internal.disabled_demo_events {
+                state.settings.set_disabled_demo_events(kinds);
+            }
+            if let Some(wait) = internal.wait_for_tf2 {
+                state.settings.set_wait_for_tf2(wait);
+            }
+            if let Some(retries) = internal.masterbase_session_retries {
+                state.settings.set_masterbase_session_retries(retries);
+            }
+            if let Some(delay) = internal.masterbase_retry_delay {
+                state.settings.set_masterbase_retry_delay(delay);
+            }
+            if let Some(ms) = internal.rcon_command_interval_ms {
+                state.settings.set_rcon_command_interval_ms(ms);
+            }
+            if let Some(prioritize) = internal.prioritize_rcon_commands {
+                state.settings.set_prioritize_rcon_commands(prioritize);
+            }
+            if let Some(minutes) = internal.steam_cache_ttl_minutes {
+                state.settings.set_steam_cache_ttl_minutes(minutes);
+            }
+            if let Some(replay) = internal.replay_console_log {
+                state.settings.set_replay_console_log(replay);
+            }
+            if let Some(friends) = internal.trusted_friends {
+                state.settings.set_trusted_friends(friends);
+            }
+            if let Some(styles) = internal.display_styles {
+                if let Err(e) = state.settings.set_display_styles(styles) {
+                    tracing::error!("Failed to set display styles: {e}");
+                }
+            }
 
             if let Some(tos_agreement_date) = internal.tos_agreement_date {
                 if tos_agreement_date.is_empty() {
@@ -1,20 +1,71 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
+use keyvalues_parser::Vdf;
 use steamid_ng::SteamID;
 use steamlocate::SteamDir;
+use sysinfo::System;
 
 pub const TF2_GAME_ID: u32 = 440;
 
+/// Where Steam keeps its data when installed through Flatpak, relative to the
+/// home directory
+#[cfg(target_os = "linux")]
+const FLATPAK_STEAM_DIRS: &[&str] = &[
+    ".var/app/com.valvesoftware.Steam/.steam/steam",
+    ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+];
+
+/// Finds the Steam directory, falling back to a Flatpak install on Linux if
+/// there's no native one.
+///
+/// # Errors
+/// If neither could be found
+fn locate_steam_dir() -> Result<PathBuf> {
+    let steam_dir = match SteamDir::locate() {
+        Ok(steam) => steam.path().to_path_buf(),
+        #[cfg(target_os = "linux")]
+        Err(e) => locate_flatpak_steam_dir().ok_or_else(|| {
+            anyhow!("Could not locate Steam directory ({e}), or a Flatpak install of Steam")
+        })?,
+        #[cfg(not(target_os = "linux"))]
+        Err(e) => return Err(anyhow!("Could not locate Steam directory: {e}")),
+    };
+
+    tracing::info!("Using Steam directory {}", steam_dir.display());
+    Ok(steam_dir)
+}
+
+#[cfg(target_os = "linux")]
+fn locate_flatpak_steam_dir() -> Option<PathBuf> {
+    let home = directories_next::BaseDirs::new()?.home_dir().to_path_buf();
+    FLATPAK_STEAM_DIRS
+        .iter()
+        .map(|dir| home.join(dir))
+        .find(|dir| dir.join("config").is_dir())
+}
+
+/// The names the Steam client process goes by on each platform
+const STEAM_PROCESS_NAMES: &[&str] = &["steam", "steam.exe", "steam_osx"];
+
+/// Whether the Steam client is currently running. Steam rewrites its config
+/// files when it exits, so they shouldn't be edited while it's open.
+#[must_use]
+pub fn is_steam_running() -> bool {
+    let mut system = System::new();
+    system.refresh_processes();
+    system
+        .processes()
+        .values()
+        .any(|p| STEAM_PROCESS_NAMES.contains(&p.name().to_lowercase().as_str()))
+}
+
 /// # Errors
 /// - If the Steam directory couldn't be found
 /// - If the `config/loginusers.vdf` could not be found in the Steam directory
 pub fn locate_steam_logged_in_users() -> Result<PathBuf> {
     tracing::debug!("Fetching Steam loginusers.vdf");
-    let mut base_folder: PathBuf = SteamDir::locate()
-        .context("Could not locate steam directory")?
-        .path()
-        .into();
+    let mut base_folder = locate_steam_dir()?;
     base_folder.push::<PathBuf>("config/loginusers.vdf".into());
     if base_folder.as_path().exists() {
         Ok(base_folder)
@@ -31,8 +82,7 @@ pub fn locate_steam_launch_configs(steam_user: SteamID) -> Result<PathBuf> {
     let local_config_path = format!("userdata/{a_id}/config/localconfig.vdf");
     tracing::debug!("Fetching Steam {}", local_config_path);
 
-    let steam = SteamDir::locate().context("Could not locate steam directory")?;
-    let mut base_folder: PathBuf = steam.path().into();
+    let mut base_folder = locate_steam_dir()?;
     base_folder.push(local_config_path);
     if base_folder.as_path().exists() {
         Ok(base_folder)
@@ -42,16 +92,79 @@ pub fn locate_steam_launch_configs(steam_user: SteamID) -> Result<PathBuf> {
 }
 
 /// Attempts to open the TF2 directory or locate it if it's not in the expected
-/// place
+/// place. Every library folder Steam has been configured with is searched, in
+/// case TF2 is installed on another drive.
 ///
 /// # Errors
 /// - If the Steam directory could not be found
 /// - If the user's TF2 installation could not be found through Steam
 pub fn locate_tf2_folder() -> Result<PathBuf> {
+    let steam_dir = locate_steam_dir().context("Failed to locate Steam directory")?;
+
+    match find_tf2_in_libraries(&steam_dir) {
+        Ok(tf2_dir) => return Ok(tf2_dir),
+        Err(e) => tracing::debug!("{e:?}"),
+    }
+
     let (app, library) = SteamDir::locate()
-        .context("Failed to locate Steam directory")?
+        .context("Failed to locate TF2 installation.")?
         .find_app(TF2_GAME_ID)
         .context("Failed to locate TF2 installation.")?
         .ok_or_else(|| anyhow!("Failed to locate TF2 installation."))?;
     Ok(library.resolve_app_dir(&app))
 }
+
+/// Searches each library in `steamapps/libraryfolders.vdf` for a TF2
+/// installation, returning the first one found.
+fn find_tf2_in_libraries(steam_dir: &Path) -> Result<PathBuf> {
+    for library in library_folders(steam_dir)? {
+        let steamapps = library.join("steamapps");
+        let tf2_dir = steamapps.join("common/Team Fortress 2");
+        let manifest = steamapps.join(format!("appmanifest_{TF2_GAME_ID}.acf"));
+        tracing::debug!("Looking for TF2 in {}", tf2_dir.display());
+
+        if tf2_dir.is_dir() && manifest.is_file() {
+            return Ok(tf2_dir);
+        }
+    }
+
+    Err(anyhow!("TF2 was not found in any Steam library folder."))
+}
+
+/// Reads the library folders from `steamapps/libraryfolders.vdf`, starting
+/// with the Steam directory itself. Libraries are objects with a `path` in
+/// newer versions of the file, or just the path in older ones.
+fn library_folders(steam_dir: &Path) -> Result<Vec<PathBuf>> {
+    let path = steam_dir.join("steamapps/libraryfolders.vdf");
+    let contents =
+        std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let contents = String::from_utf8_lossy(&contents);
+    let vdf =
+        Vdf::parse(&contents).map_err(|e| anyhow!("Failed to parse libraryfolders.vdf: {e}"))?;
+    let libraries = vdf
+        .value
+        .get_obj()
+        .ok_or_else(|| anyhow!("Failed to parse libraryfolders.vdf"))?;
+
+    let mut folders = vec![steam_dir.to_path_buf()];
+    // Libraries are numbered, any other keys are unrelated
+    for (_, values) in libraries.iter().filter(|(k, _)| k.parse::<u32>().is_ok()) {
+        for value in values {
+            let folder = value.get_str().or_else(|| {
+                value
+                    .get_obj()
+                    .and_then(|library| library.get("path"))
+                    .and_then(|path| path.first())
+                    .and_then(|path| path.get_str())
+            });
+
+            if let Some(folder) = folder.map(PathBuf::from) {
+                if !folders.contains(&folder) {
+                    folders.push(folder);
+                }
+            }
+        }
+    }
+
+    Ok(folders)
+}
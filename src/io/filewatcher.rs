@@ -1,36 +1,32 @@
-use std::{path::PathBuf, time::Duration};
+use std::{io::SeekFrom, path::PathBuf, time::Duration};
 
-use anyhow::{anyhow, Context, Result};
-use clap_lex::SeekFrom;
+use anyhow::{Context, Result};
 use tokio::{
-    fs::{File, OpenOptions},
+    fs::{metadata, File},
     io::{AsyncReadExt, AsyncSeekExt},
     sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
 };
 
-struct OpenFile {
-    /// Size of the file (in bytes) when it was last read
-    pub last_size: u64,
-    /// The file being watched
-    pub file: File,
-}
-
 pub struct FileWatcher {
-    /// Used to reopen the file for the next bulk read
+    /// Reopened for each read, so it doesn't matter if the file is replaced
     file_path: PathBuf,
-    /// The file currently being watched
-    open_file: Option<OpenFile>,
+    /// How far into the file has been read
+    offset: u64,
+    /// Read whatever is already in the file when it's first opened, rather
+    /// than skipping to the end
+    from_start: bool,
     response_send: UnboundedSender<String>,
 }
 
 impl FileWatcher {
     #[must_use]
-    pub fn new(path: PathBuf) -> (UnboundedReceiver<String>, Self) {
+    pub fn new(path: PathBuf, from_start: bool) -> (UnboundedReceiver<String>, Self) {
         let (resp_tx, resp_rx) = unbounded_channel();
 
         let file_watcher = Self {
             file_path: path,
-            open_file: None,
+            offset: 0,
+            from_start,
 
             response_send: resp_tx,
         };
@@ -41,109 +37,123 @@ impl FileWatcher {
     /// Start the file watcher loop. This will block until the channel is
     /// closed, so usually it should be spawned in a separate `tokio::task`
     pub async fn file_watch_loop(&mut self) {
-        if let Err(e) = self.first_file_open().await {
-            tracing::error!("Failed to open file {:?}: {:?}", &self.file_path, e);
-            self.open_file = None;
-        }
+        self.first_file_open().await;
 
         loop {
-            match self.open_file {
-                Some(_) => {
-                    self.read_new_file_lines().await.ok();
-                }
-                None => {
-                    self.reopen_file().await.ok();
+            match self.read_new_file_lines().await {
+                Ok(lines) => {
+                    for l in lines {
+                        if self.response_send.send(l).is_err() {
+                            return;
+                        }
+                    }
                 }
+                Err(e) => tracing::trace!("Couldn't read {:?}: {e:?}", self.file_path),
             }
 
             tokio::time::sleep(Duration::from_millis(10)).await;
         }
     }
 
-    async fn first_file_open(&mut self) -> Result<()> {
-        let open_file = self.reopen_file().await?;
-        let meta = open_file.file.metadata().await?;
-        open_file.file.seek(SeekFrom::Start(meta.len())).await?;
-        open_file.last_size = meta.len();
-
-        Ok(())
-    }
-
-    async fn reopen_file(&mut self) -> tokio::io::Result<&mut OpenFile> {
-        let file = OpenOptions::new()
-            .read(true)
-            .write(false)
-            .open(&self.file_path)
-            .await?;
-
-        self.open_file = Some(OpenFile { last_size: 0, file });
-
-        Ok(self.open_file.as_mut().expect("Just check set it to some."))
-    }
-
-    /// Attempts to read the new contents of the observed file and updates the
-    /// internal state with any new lines that have been appended since last
-    /// call.
-    async fn read_new_file_lines(&mut self) -> Result<()> {
-        if self.open_file.is_none() {
-            return Err(anyhow!(
-                "read_new_file_lines wasn't meant to be called when self.file is None"
-            ));
+    /// Skips whatever is already in the file, unless it should be read from
+    /// the start. A file that doesn't exist yet is read from the start once it
+    /// does.
+    async fn first_file_open(&mut self) {
+        if self.from_start {
+            return;
         }
-        let mut file = self.open_file.as_mut().expect("Just checked for Some");
-
-        let meta =
-            std::fs::metadata(&self.file_path).context("Failed to fetch metadata for log file.")?;
 
-        // No new data
-        if meta.len() == file.last_size || meta.len() == 0 {
-            return Ok(());
+        match metadata(&self.file_path).await {
+            Ok(meta) => self.offset = meta.len(),
+            Err(e) => tracing::error!("Failed to open file {:?}: {:?}", &self.file_path, e),
         }
+    }
 
-        // Reset if file has been remade (i.e. is shorter) and update state
-        if meta.len() < file.last_size {
-            tracing::warn!("File has shortened, the file may have been replaced. Reopening.");
-            file = self
-                .reopen_file()
-                .await
-                .context("Failed to reopen file after it was shortened.")?;
+    /// Reads any lines that have been appended since the last call. If the
+    /// file has shortened (e.g. TF2 was restarted with `-conclearlog`), it's
+    /// read from the start again.
+    async fn read_new_file_lines(&mut self) -> Result<Vec<String>> {
+        let meta = metadata(&self.file_path)
+            .await
+            .context("Failed to fetch metadata for log file.")?;
+
+        match meta.len().cmp(&self.offset) {
+            std::cmp::Ordering::Less => {
+                tracing::warn!(
+                    "File has shortened, the file may have been cleared. Reading from the start."
+                );
+                self.offset = 0;
+            }
+            std::cmp::Ordering::Equal => return Ok(Vec::new()),
+            std::cmp::Ordering::Greater => {}
         }
 
-        // Get new file contents
+        let mut file = File::open(&self.file_path)
+            .await
+            .context("Failed to open file.")?;
+        file.seek(SeekFrom::Start(self.offset))
+            .await
+            .context("Failed to seek file.")?;
         let mut buff: Vec<u8> = Vec::new();
         let read_size = file
-            .file
             .read_to_end(&mut buff)
             .await
             .context("Failed to read file.")?;
+        self.offset += read_size as u64;
+
+        Ok(String::from_utf8_lossy(&buff)
+            .lines()
+            .filter(|x| !x.trim().is_empty())
+            .map(Into::into)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FileWatcher;
 
-        file.last_size += read_size as u64;
+    #[tokio::test]
+    pub async fn reads_after_truncation() {
+        let path =
+            std::env::temp_dir().join(format!("mac_console_truncated_{}.log", std::process::id()));
+        std::fs::write(&path, "before launch\n").expect("Couldn't write log");
 
-        // If we expected there to be new data but we didn't read anything, reopen the
-        // file and try again.
-        if read_size == 0 {
-            tracing::warn!("Expected to read bytes but didn't get any, the file may have been replaced. Reopening.");
-            file = self
-                .reopen_file()
+        let (_rx, mut watcher) = FileWatcher::new(path.clone(), false);
+        watcher.first_file_open().await;
+        assert!(watcher
+            .read_new_file_lines()
+            .await
+            .expect("Couldn't read log")
+            .is_empty());
+
+        std::fs::write(&path, "before launch\nfirst\nsecond\n").expect("Couldn't write log");
+        assert_eq!(
+            watcher
+                .read_new_file_lines()
                 .await
-                .context("Failed to reopen file after not receiving any data.")?;
-            buff.clear();
-            file.last_size = file
-                .file
-                .read_to_end(&mut buff)
+                .expect("Couldn't read log"),
+            ["first", "second"]
+        );
+
+        // The game was restarted and cleared the log
+        std::fs::write(&path, "third\n").expect("Couldn't write log");
+        assert_eq!(
+            watcher
+                .read_new_file_lines()
                 .await
-                .context("Failed to read file.")? as u64;
-        }
-
-        // Send newly read lines over channel
-        let data_str = String::from_utf8_lossy(&buff);
-        data_str
-            .lines()
-            .filter(|x| !x.trim().is_empty())
-            .for_each(|l| {
-                self.response_send.send(l.into()).expect("Main loop ded?");
-            });
+                .expect("Couldn't read log"),
+            ["third"]
+        );
+        std::fs::write(&path, "third\nfourth\n").expect("Couldn't write log");
+        assert_eq!(
+            watcher
+                .read_new_file_lines()
+                .await
+                .expect("Couldn't read log"),
+            ["fourth"]
+        );
 
-        Ok(())
+        std::fs::remove_file(&path).ok();
     }
 }
@@ -11,18 +11,17 @@ use crate::player::Team;
 
 #[derive(Debug, Error, Clone)]
 pub enum Error {
-    /// Occurs when `m_someArray[X]` has some X greater than 33 (since these are
-    /// static 34 element arrays)
+    /// Occurs when `m_someArray[X]` has some X greater than
+    /// `MAX_SLOT_INDEX`, which no real server should ever report
     #[error("index provided from output of g15 command was invalid")]
     IndexOutOfBounds,
 }
 
-/// July 25, 2023 TF2 update updated the maximum supported number of players in
-/// a single server instance to 100, from 32. This is unlikely to ever change
-/// again, so rather than dynamically handling `g15_dumpplayer` logs that
-/// can contain any number of array entries, we simply hard-code the current
-/// maximum amount of players you will EVER find in a game.
-const MAX_POSSIBLE_PLAYERS: u8 = 100;
+/// The backing storage for players grows to fit the highest slot index found
+/// in the `g15_dumpplayer` output, since community servers can raise the
+/// player limit past the official 100. This is only a sanity cap to stop a
+/// garbage index from allocating an enormous amount of memory.
+const MAX_SLOT_INDEX: usize = 1024;
 
 /// A `RegMatch` struct contains a Regex and the corresponding function to add
 /// the result to the `G15Player` vec if the regex results in a match.
@@ -252,8 +251,7 @@ impl Parser {
     /// Parse a `g15_dumpplayer` string via Regex search.
     /// We only extract useful data here, so drop most data.
     pub fn parse_g15(&self, g15_log: &str) -> Vec<G15Player> {
-        let mut players: Vec<G15Player> =
-            vec![G15Player::new(); (MAX_POSSIBLE_PLAYERS + 2) as usize];
+        let mut players: Vec<G15Player> = Vec::new();
         let lines = g15_log.split('\n');
 
         for line in lines {
@@ -262,6 +260,15 @@ impl Parser {
                 // current RegMatch struct
                 let pat_caps = pat.0.captures(line.trim());
                 if let Some(pat_caps) = pat_caps {
+                    // Every pattern captures the slot index first, make sure there's room for it
+                    if let Some(idx) = pat_caps
+                        .get(1)
+                        .and_then(|m| m.as_str().parse::<usize>().ok())
+                        .filter(|&idx| idx <= MAX_SLOT_INDEX && idx >= players.len())
+                    {
+                        players.resize(idx + 1, G15Player::new());
+                    }
+
                     // Call the corresponding function to store the capture groups, as we have found
                     // a match
                     match pat.1(&pat_caps, &mut players) {
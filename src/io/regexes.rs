@@ -109,7 +109,21 @@ impl PlayerKill {
 /// Matches:
 ///    0: Player
 ///    1: Message
-pub const REGEX_CHAT: &str = r"^(?:\*DEAD\*)?(?:\(TEAM\))?\s?(.*)\s:\s\s(.*)$";
+pub const REGEX_CHAT: &str = r"^(\*DEAD\*|\*SPEC\*)?(\(TEAM\)|\(PARTY\))?\s?(.*)\s:\s\s(.*)$";
+
+/// Who a chat message was sent to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ChatScope {
+    /// Everyone on the server
+    All,
+    /// Only the sender's team
+    Team,
+    /// All chat from a dead player or spectator, only seen by others who are
+    /// also dead
+    Dead,
+    /// Only the sender's party
+    Party,
+}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ChatMessage {
@@ -118,16 +132,29 @@ pub struct ChatMessage {
     pub steamid: Option<SteamID>,
     pub message: String,
     pub timestamp: DateTime<Utc>,
+    pub scope: ChatScope,
+    /// Whether the sender was dead or spectating
+    pub dead: bool,
 }
 
 impl ChatMessage {
     #[must_use]
     pub fn parse(caps: &Captures) -> Self {
+        let dead = caps.get(1).is_some();
+        let scope = match caps.get(2).map(|m| m.as_str()) {
+            Some("(TEAM)") => ChatScope::Team,
+            Some("(PARTY)") => ChatScope::Party,
+            _ if dead => ChatScope::Dead,
+            _ => ChatScope::All,
+        };
+
         Self {
-            player_name: caps[1].into(),
+            player_name: caps[3].into(),
             steamid: None,
-            message: caps[2].into(),
+            message: caps[4].into(),
             timestamp: Utc::now(),
+            scope,
+            dead,
         }
     }
 }
@@ -192,6 +219,25 @@ impl DemoStop {
     }
 }
 
+// The reason the local user was disconnected from the server, but only when
+// they were kicked or banned rather than leaving or the server shutting down
+pub const REGEX_SELFKICKED: &str =
+    r"^Disconnect: ((?i:kicked|banned|you have been (?:kicked|banned|voted off)).*?)\.?$";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SelfKicked {
+    pub reason: String,
+}
+
+impl SelfKicked {
+    #[must_use]
+    pub fn parse(caps: &Captures) -> Self {
+        Self {
+            reason: caps[1].into(),
+        }
+    }
+}
+
 // Converts a given string time (e.g. 57:48 or 1:14:46) as an integer number of
 // seconds
 #[allow(clippy::cast_possible_truncation)]
@@ -264,3 +310,71 @@ const INVIS_CHARS: &[char] = &[
     '\u{1d179}',
     '\u{1d17a}',
 ];
+
+#[cfg(test)]
+mod test {
+    use regex::Regex;
+
+    use super::{ChatMessage, ChatScope, SelfKicked, REGEX_CHAT, REGEX_SELFKICKED};
+
+    fn parse_chat(line: &str) -> ChatMessage {
+        let regex = Regex::new(REGEX_CHAT).expect("Compile static regex");
+        let caps = regex.captures(line).expect("Line should match");
+        ChatMessage::parse(&caps)
+    }
+
+    #[test]
+    pub fn chat_scopes() {
+        let cases = [
+            ("Lilith :  hello", ChatScope::All, false),
+            ("(TEAM) Lilith :  hello", ChatScope::Team, false),
+            ("*DEAD* Lilith :  hello", ChatScope::Dead, true),
+            ("*DEAD*(TEAM) Lilith :  hello", ChatScope::Team, true),
+            ("*SPEC* Lilith :  hello", ChatScope::Dead, true),
+            ("(PARTY) Lilith :  hello", ChatScope::Party, false),
+        ];
+
+        for (line, scope, dead) in cases {
+            let chat = parse_chat(line);
+            assert_eq!(chat.player_name, "Lilith", "{line}");
+            assert_eq!(chat.message, "hello", "{line}");
+            assert_eq!(chat.scope, scope, "{line}");
+            assert_eq!(chat.dead, dead, "{line}");
+        }
+    }
+
+    #[test]
+    pub fn chat_name_with_prefix_text() {
+        // Names can contain text that looks like a prefix, but only a real
+        // prefix at the start of the line counts
+        let chat = parse_chat("(TEAM) (TEAM) bot :  hi :  there");
+        assert_eq!(chat.scope, ChatScope::Team);
+        assert_eq!(chat.player_name, "(TEAM) bot :  hi");
+        assert_eq!(chat.message, "there");
+    }
+
+    #[test]
+    pub fn self_kicked() {
+        let regex = Regex::new(REGEX_SELFKICKED).expect("Compile static regex");
+        let reason = |line: &str| {
+            regex
+                .captures(line)
+                .map(|caps| SelfKicked::parse(&caps).reason)
+        };
+
+        assert_eq!(
+            reason("Disconnect: You have been voted off.").as_deref(),
+            Some("You have been voted off")
+        );
+        assert_eq!(
+            reason("Disconnect: Kicked by Console : Cheating.").as_deref(),
+            Some("Kicked by Console : Cheating")
+        );
+        assert_eq!(
+            reason("Disconnect: You have been banned from this server").as_deref(),
+            Some("You have been banned from this server")
+        );
+        assert_eq!(reason("Disconnect: Server shutting down"), None);
+        assert_eq!(reason("Disconnect: #TF_Idle_kicked"), None);
+    }
+}
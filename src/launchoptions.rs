@@ -1,12 +1,17 @@
-use std::{fs::File, io::Read, path::PathBuf};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::PathBuf,
+};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use atomic_write_file::AtomicWriteFile;
 use regex::Regex;
 use steamid_ng::SteamID;
 use substring::Substring;
 use tracing::Level;
 
-use crate::gamefinder::{locate_steam_launch_configs, TF2_GAME_ID};
+use crate::gamefinder::{is_steam_running, locate_steam_launch_configs, TF2_GAME_ID};
 
 /// `-condebug` enables the console.log file to be written to by the game.
 /// `-conclearlog` clears the console.log file on launch of TF2
@@ -22,6 +27,12 @@ pub const TF2_REQUIRED_OPTS: [&str; 4] = ["-condebug", "-conclearlog", "-usercon
 /// required options.
 pub struct LaunchOptions {
     launch_args_regex: Regex,
+    config_path: PathBuf,
+    /// The whole contents of `localconfig.vdf`
+    config_data: String,
+    /// Whether `config_data` had to be lossily converted, in which case it
+    /// can't be written back without mangling the file
+    lossy: bool,
     app_data: Option<String>,
     new_app_data: Option<String>,
 }
@@ -54,7 +65,14 @@ impl LaunchOptions {
             f.read_to_end(&mut data)
                 .context(format!("Failed to read {config_path:?}"))?;
         }
-        let binding = &String::from_utf8_lossy(&data);
+
+        Self::from_config(config_path, &data)
+    }
+
+    /// Finds the target app's block in the contents of `localconfig.vdf`
+    fn from_config(config_path: PathBuf, data: &[u8]) -> Result<Self, anyhow::Error> {
+        let lossy = std::str::from_utf8(data).is_err();
+        let binding = &String::from_utf8_lossy(data);
 
         let apps_regex =
             Regex::new(r#"\t{4}"[aA]pps"([\s\S]+)\t{5}}"#).expect("Apps regex construction");
@@ -88,6 +106,9 @@ impl LaunchOptions {
 
         Ok(Self {
             launch_args_regex: launch_options_regex,
+            config_path,
+            config_data: binding.to_string(),
+            lossy,
             app_data: matched_app_block,
             new_app_data: None,
         })
@@ -127,4 +148,193 @@ impl LaunchOptions {
 
         Ok(missing_args)
     }
+
+    /// Adds any missing required options to the end of the app's launch
+    /// options, keeping the ones already there. The change isn't written to
+    /// `localconfig.vdf` until [`LaunchOptions::write`] is called.
+    ///
+    /// Returns the new launch options, or `None` if nothing was missing.
+    ///
+    /// # Errors
+    /// - No app data is stored in this object (`self.app_data` is None).
+    pub fn add_missing_args(&mut self) -> Result<Option<String>, anyhow::Error> {
+        let missing = self.check_missing_args()?.join(" ");
+        if missing.is_empty() {
+            return Ok(None);
+        }
+
+        let app_data = self
+            .new_app_data
+            .as_ref()
+            .or(self.app_data.as_ref())
+            .context("No data currently stored.")?;
+
+        let (new_app_data, new_args) = match self.launch_args_regex.captures(app_data) {
+            Some(caps) => {
+                let current = caps.get(1).map_or("", |m| m.as_str());
+                let new_args = format!("{current} {missing}").trim().to_string();
+                let line = caps.get(0).expect("Whole match").as_str();
+                (
+                    app_data.replacen(
+                        line,
+                        &format!("\t\t\t\t\t\t\"LaunchOptions\"\t\t\"{new_args}\""),
+                        1,
+                    ),
+                    new_args,
+                )
+            }
+            // The app block runs up to (but not including) its closing brace
+            None => (
+                format!("{app_data}\n\t\t\t\t\t\t\"LaunchOptions\"\t\t\"{missing}\""),
+                missing,
+            ),
+        };
+
+        self.new_app_data = Some(new_app_data);
+        Ok(Some(new_args))
+    }
+
+    /// Writes the launch options added by [`LaunchOptions::add_missing_args`]
+    /// back to `localconfig.vdf`, after copying the original next to it as
+    /// `localconfig.vdf.bak`.
+    ///
+    /// Returns the path of the backup.
+    ///
+    /// # Errors
+    /// - Steam is running, since it would overwrite the file when it exits.
+    /// - There are no changes to write.
+    /// - `localconfig.vdf` isn't valid UTF-8.
+    /// - The backup or the new file could not be written.
+    pub fn write(&self) -> Result<PathBuf, anyhow::Error> {
+        if is_steam_running() {
+            return Err(anyhow!(
+                "Steam is running, close it before changing launch options."
+            ));
+        }
+
+        let new_config = self.new_config()?;
+
+        let mut backup_path = self.config_path.clone().into_os_string();
+        backup_path.push(".bak");
+        let backup_path = PathBuf::from(backup_path);
+        std::fs::copy(&self.config_path, &backup_path)
+            .with_context(|| format!("Failed to back up {:?}", self.config_path))?;
+
+        let mut file = AtomicWriteFile::open(&self.config_path)?;
+        file.write_all(new_config.as_bytes())
+            .with_context(|| format!("Failed to write {:?}", self.config_path))?;
+        file.commit()?;
+
+        Ok(backup_path)
+    }
+
+    /// The contents of `localconfig.vdf` with the changed app block swapped
+    /// in.
+    fn new_config(&self) -> Result<String, anyhow::Error> {
+        if self.lossy {
+            return Err(anyhow!(
+                "{:?} isn't valid UTF-8, not rewriting it.",
+                self.config_path
+            ));
+        }
+
+        let app_data = self
+            .app_data
+            .as_ref()
+            .context("No data currently stored.")?;
+        let new_app_data = self
+            .new_app_data
+            .as_ref()
+            .context("No launch options have been changed.")?;
+        Ok(self.config_data.replacen(app_data, new_app_data, 1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::LaunchOptions;
+    use crate::gamefinder::TF2_GAME_ID;
+
+    /// A cut down `localconfig.vdf` with another app before TF2, whose
+    /// launch options are given by `tf2_opts`
+    fn config(tf2_opts: Option<&str>) -> String {
+        let opts = tf2_opts
+            .map(|o| format!("\n\t\t\t\t\t\t\"LaunchOptions\"\t\t\"{o}\""))
+            .unwrap_or_default();
+        format!(
+            "\"UserLocalConfigStore\"\n{{\n\t\"Software\"\n\t{{\n\t\t\"Valve\"\n\t\t{{\n\t\t\t\"Steam\"\n\t\t\t{{\n\
+             \t\t\t\t\"apps\"\n\t\t\t\t{{\n\
+             \t\t\t\t\t\"10\"\n\t\t\t\t\t{{\n\t\t\t\t\t\t\"LaunchOptions\"\t\t\"-novid\"\n\t\t\t\t\t}}\n\
+             \t\t\t\t\t\"{TF2_GAME_ID}\"\n\t\t\t\t\t{{\n\t\t\t\t\t\t\"Playtime\"\t\t\"100\"{opts}\n\t\t\t\t\t}}\n\
+             \t\t\t\t}}\n\t\t\t}}\n\t\t}}\n\t}}\n}}\n"
+        )
+    }
+
+    fn launch_options(config: &str) -> LaunchOptions {
+        LaunchOptions::from_config(PathBuf::from("localconfig.vdf"), config.as_bytes())
+            .expect("Couldn't parse config")
+    }
+
+    #[test]
+    pub fn appends_to_existing_options() {
+        let original = config(Some("-novid -condebug"));
+        let mut opts = launch_options(&original);
+
+        let new_args = opts.add_missing_args().expect("Couldn't add args");
+        assert_eq!(
+            new_args.as_deref(),
+            Some("-novid -condebug -g15 -usercon -conclearlog")
+        );
+        assert!(opts.check_missing_args().expect("No app data").is_empty());
+
+        let new_config = opts.new_config().expect("Couldn't build config");
+        assert_eq!(
+            new_config,
+            config(Some("-novid -condebug -g15 -usercon -conclearlog"))
+        );
+    }
+
+    #[test]
+    pub fn inserts_missing_launch_options() {
+        let original = config(None);
+        let mut opts = launch_options(&original);
+
+        let new_args = opts.add_missing_args().expect("Couldn't add args");
+        assert_eq!(
+            new_args.as_deref(),
+            Some("-condebug -conclearlog -usercon -g15")
+        );
+
+        // Only TF2's block is changed, not the other app's launch options
+        let new_config = opts.new_config().expect("Couldn't build config");
+        assert_eq!(
+            new_config,
+            config(Some("-condebug -conclearlog -usercon -g15"))
+        );
+    }
+
+    #[test]
+    pub fn nothing_to_add() {
+        let original = config(Some("-condebug -conclearlog -usercon -g15"));
+        let mut opts = launch_options(&original);
+
+        assert_eq!(opts.add_missing_args().expect("Couldn't add args"), None);
+        assert!(opts.new_config().is_err());
+    }
+
+    #[test]
+    pub fn refuses_to_rewrite_invalid_utf8() {
+        let mut original = config(None).into_bytes();
+        original.splice(0..0, [0xff, 0xfe]);
+        let mut opts = LaunchOptions::from_config(PathBuf::from("localconfig.vdf"), &original)
+            .expect("Couldn't parse config");
+
+        assert!(opts
+            .add_missing_args()
+            .expect("Couldn't add args")
+            .is_some());
+        assert!(opts.new_config().is_err());
+    }
 }
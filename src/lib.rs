@@ -1,5 +1,8 @@
 pub mod args;
+pub mod audit;
+pub mod avatars;
 pub mod command_manager;
+pub mod confidence;
 pub mod console;
 pub mod demo;
 pub mod events;
@@ -14,7 +17,9 @@ pub mod player_records;
 pub mod server;
 pub mod settings;
 pub mod state;
+pub mod stats;
 pub mod steam_api;
+pub mod warnings;
 pub mod web;
 
 pub use clap;
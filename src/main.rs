@@ -5,20 +5,21 @@ use std::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use args::Args;
 use clap::Parser;
-use event_loop::{define_events, EventLoop};
+use event_loop::{define_events, EventLoop, EventLoopMetrics, QueuePolicy};
 use events::emit_on_timer;
 use launchoptions::LaunchOptions;
 use player::Players;
 use player_records::PlayerRecords;
 use reqwest::StatusCode;
 use server::Server;
-use settings::Settings;
+use settings::{ConfigWatcher, Settings};
 use state::MACState;
+use stats::SessionStats;
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{
     filter::Directive, fmt::writer::MakeWriterExt, layer::SubscriberExt, util::SubscriberInitExt,
@@ -27,7 +28,10 @@ use tracing_subscriber::{
 use web::{web_main, WebState};
 
 mod args;
+mod audit;
+mod avatars;
 mod command_manager;
+mod confidence;
 mod console;
 mod demo;
 mod events;
@@ -43,18 +47,30 @@ mod server;
 mod settings;
 mod sse_events;
 mod state;
+mod stats;
 mod steam_api;
+mod warnings;
 mod web;
 
-use command_manager::{Command, CommandManager, DumbAutoKick};
+use avatars::AvatarCache;
+use command_manager::{
+    AutoExec, Command, CommandManager, DumbAutoKick, KickResult, RconStateChanged,
+    RefreshOnServerChange, Tf2Launched,
+};
+use confidence::{ConfidenceScorer, RecentBans, RecentlyBanned, TrustFriends};
 use console::{ConsoleLog, ConsoleOutput, ConsoleParser, RawConsoleOutput};
-use demo::{DemoBytes, DemoManager, DemoMessage, DemoWatcher, PrintVotes};
-use events::{Preferences, Refresh, UserUpdates};
-use new_players::{ExtractNewPlayers, NewPlayers};
+use demo::{
+    CompressedDemo, DecompressedDemo, DemoBytes, DemoManager, DemoMessage, DemoWatcher, PrintVotes,
+};
+use events::{
+    ClearCaches, DeleteRecords, PinPlayer, Preferences, RecomputeParties, Refresh,
+    SettingsReloaded, UserUpdates, REFRESH_INTERVAL,
+};
+use new_players::{ExtractNewPlayers, ExtractPresenceChanges, NewPlayers, PresenceChanged};
 use sse_events::SseEventBroadcaster;
 use steam_api::{
     FriendLookupResult, LookupFriends, LookupProfiles, ProfileLookupBatchTick,
-    ProfileLookupRequest, ProfileLookupResult,
+    ProfileLookupRequest, ProfileLookupResult, SteamApiLimiter,
 };
 use web::{WebAPIHandler, WebRequest};
 
@@ -64,11 +80,15 @@ define_events!(
         Refresh,
 
         Command,
+        RconStateChanged,
+        KickResult,
+        Tf2Launched,
 
         RawConsoleOutput,
         ConsoleOutput,
 
         NewPlayers,
+        PresenceChanged,
 
         ProfileLookupBatchTick,
         ProfileLookupResult,
@@ -76,30 +96,53 @@ define_events!(
         ProfileLookupRequest,
 
         Preferences,
+        SettingsReloaded,
         UserUpdates,
+        RecentlyBanned,
+        DeleteRecords,
+        ClearCaches,
+        PinPlayer,
+        RecomputeParties,
 
         WebRequest,
 
         DemoBytes,
+        CompressedDemo,
+        DecompressedDemo,
         DemoMessage,
     },
     Handler {
         CommandManager,
         ConsoleParser,
         ExtractNewPlayers,
+        ExtractPresenceChanges,
 
         LookupProfiles,
         LookupFriends,
 
         WebAPIHandler,
         SseEventBroadcaster,
+        AvatarCache,
 
         DemoManager,
         PrintVotes,
         DumbAutoKick,
+        AutoExec,
+        RefreshOnServerChange,
+        ConfidenceScorer,
+        RecentBans,
+        TrustFriends,
     },
 );
 
+/// How long to wait for tasks in progress to finish when exiting
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often to check whether TF2 has launched while waiting for it
+const TF2_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How many messages to handle each cycle before leaving the rest in their
+/// sources, so a flood of console output can't grow the queue forever
+const MAX_MESSAGES_PER_CYCLE: usize = 4096;
+
 #[allow(clippy::too_many_lines)]
 fn main() {
     let _guard = init_tracing();
@@ -110,12 +153,44 @@ fn main() {
     settings.save_ok();
 
     let mut playerlist = PlayerRecords::load_or_create(&args);
+    if let Some(path) = &args.import_tf2bd {
+        match playerlist.import_tf2bd(path) {
+            Ok(n) => tracing::info!("Imported {n} players from {path:?}"),
+            Err(e) => tracing::error!("Failed to import TF2BD playerlist: {e}"),
+        }
+    }
     playerlist.save_ok();
 
-    let players = Players::new(playerlist, settings.steam_user());
+    if let Some(path) = &args.export_tf2bd {
+        match playerlist.export_tf2bd(path) {
+            Ok(n) => tracing::info!("Exported {n} players to {path:?}"),
+            Err(e) => {
+                tracing::error!("Failed to export TF2BD playerlist: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut players = Players::new(playerlist, settings.steam_user());
+    players.set_name_source(settings.name_source());
+    players.set_player_sort(settings.player_sort());
+    players.set_max_history_len(settings.max_history_len());
+    if let Some(path) = &args.friend_graph {
+        if let Err(e) = players.import_friend_graph(path) {
+            tracing::error!("Failed to import friends graph: {e}");
+        }
+    }
 
+    let session_stats = SessionStats::new();
     let mut state = MACState {
         server: Server::new(),
+        steam_api_limiter: SteamApiLimiter::new(
+            settings.steam_api_calls_per_minute(),
+            session_stats.clone(),
+        ),
+        session_stats,
+        event_loop_metrics: EventLoopMetrics::new(),
         settings,
         players,
     };
@@ -123,10 +198,11 @@ fn main() {
     // Steam user overrides usually imply the TF2 dir cannot be found
     // so don't check launch options.
     if !state.settings.is_steam_user_overridden() {
-        check_launch_options(&state.settings);
+        check_launch_options(&state.settings, &args);
     }
 
     let web_port = state.settings.webui_port();
+    let web_bind_address = state.settings.webui_bind_address();
 
     // The juicy part of the program
     tokio::runtime::Builder::new_multi_thread()
@@ -203,52 +279,104 @@ fn main() {
 
             // Demo watcher and manager
             let demo_path = state.settings.tf2_directory().join("tf");
-            let demo_watcher = DemoWatcher::new(&demo_path)
+            let demo_watcher = DemoWatcher::new(&demo_path, state.settings.preexisting_demo_mode())
                 .map_err(|e| {
                     tracing::error!("Could not initialise demo watcher: {e}");
                 })
                 .ok();
 
             // Web API
-            let (web_state, web_requests) = WebState::new(state.settings.web_ui_source());
+            let (web_state, web_requests) = WebState::new(
+                state.settings.web_ui_source(),
+                state.settings.webui_token(),
+            );
             tokio::task::spawn(async move {
-                web_main(web_state, web_port).await;
+                web_main(web_state, web_bind_address, web_port).await;
             });
 
             // Watch console log
             let log_file_path: PathBuf =
                 PathBuf::from(state.settings.tf2_directory()).join("tf/console.log");
-            let console_log = Box::new(ConsoleLog::new(log_file_path).await);
+            let console_log = Box::new(
+                ConsoleLog::new(log_file_path.clone(), state.settings.replay_console_log()).await,
+            );
+
+            // Hold off on RCON until TF2 is running, rather than failing to
+            // connect over and over. Everything else carries on as normal.
+            let started = SystemTime::now();
+            let waiting_for_tf2 = state.settings.wait_for_tf2()
+                && !tf2_running(state.settings.rcon_port(), &log_file_path, started).await;
+            let (tf2_launched_tx, tf2_launched_rx) = tokio::sync::mpsc::unbounded_channel();
+            if waiting_for_tf2 {
+                tracing::info!("Waiting for TF2 to launch.");
+                let rcon_port = state.settings.rcon_port();
+                let log_file_path = log_file_path.clone();
+                tokio::task::spawn(async move {
+                    loop {
+                        tokio::time::sleep(TF2_POLL_INTERVAL).await;
+                        if tf2_running(rcon_port, &log_file_path, started).await {
+                            tf2_launched_tx.send(Tf2Launched).ok();
+                            break;
+                        }
+                    }
+                });
+            }
 
             let mut event_loop: EventLoop<MACState, Message, Handler> = EventLoop::new()
+                .with_metrics(state.event_loop_metrics.clone())
+                .with_max_queue_len(MAX_MESSAGES_PER_CYCLE, QueuePolicy::PauseSources)
                 .add_source(console_log)
-                .add_source(emit_on_timer(Duration::from_secs(3), || Refresh).await)
+                .add_source(emit_on_timer(REFRESH_INTERVAL, Refresh::timer).await)
                 .add_source(emit_on_timer(Duration::from_millis(500), || ProfileLookupBatchTick).await)
                 .add_source(Box::new(web_requests))
-                .add_handler(DemoManager::new())
-                .add_handler(CommandManager::new())
+                .add_source(Box::new(tf2_launched_rx))
+                .add_handler(DemoManager::new().with_late_bytes(
+                    state.settings.demo_late_bytes_offset(),
+                    state.settings.demo_late_bytes_len(),
+                ))
+                .add_handler(CommandManager::new().waiting_for_tf2(waiting_for_tf2))
                 .add_handler(ConsoleParser::default())
                 .add_handler(ExtractNewPlayers)
+                .add_handler(ExtractPresenceChanges)
                 .add_handler(LookupProfiles::new())
                 .add_handler(LookupFriends::new())
-                .add_handler(DumbAutoKick)
+                // Only kick once everything else has seen the refresh
+                .add_handler_with_priority(DumbAutoKick::new(), -1)
+                .add_handler(AutoExec::new())
+                .add_handler(RefreshOnServerChange::new())
+                .add_handler(ConfidenceScorer)
+                .add_handler(RecentBans::new())
+                .add_handler(TrustFriends)
                 .add_handler(WebAPIHandler::new())
-                .add_handler(SseEventBroadcaster::new());
+                .add_handler(SseEventBroadcaster::new())
+                .add_handler(AvatarCache::new())
+                .add_handler(PrintVotes::new());
 
-            if args.print_votes {
-                event_loop = event_loop.add_handler(PrintVotes::new());
-            }
+            // Added regardless so it can be switched on later
+            event_loop.set_handler_enabled("PrintVotes", args.print_votes);
 
             if let Some(dw) = demo_watcher {
                 event_loop = event_loop.add_source(Box::new(dw));
             }
 
+            // Pick up changes made to the config file while running
+            if let Some(config_path) = state.settings.config_path() {
+                match ConfigWatcher::new(config_path) {
+                    Ok(cw) => event_loop = event_loop.add_source(Box::new(cw)),
+                    Err(e) => tracing::error!("Could not watch config file for changes: {e}"),
+                }
+            }
+
             loop {
                 if !running.load(Ordering::SeqCst) {
+                    // Let any uploads or lookups in progress finish up first
+                    event_loop.shutdown(&mut state, SHUTDOWN_TIMEOUT).await;
+
                     tracing::info!("Saving and exiting.");
                     state.players.records.save_ok();
                     state.settings.save_ok();
                     state.players.save_steam_info_ok();
+                    state.players.save_recent_players_ok();
                     std::process::exit(0);
                 }
 
@@ -259,8 +387,27 @@ fn main() {
         });
 }
 
+/// Whether TF2 looks to be running, either because RCON is accepting
+/// connections or because the console log has been written to since `since`.
+async fn tf2_running(rcon_port: u16, console_log: &Path, since: SystemTime) -> bool {
+    // Refused connections can take a while to come back on Windows
+    let rcon = tokio::time::timeout(
+        Duration::from_secs(3),
+        tokio::net::TcpStream::connect(("127.0.0.1", rcon_port)),
+    )
+    .await;
+    if matches!(rcon, Ok(Ok(_))) {
+        return true;
+    }
+
+    tokio::fs::metadata(console_log)
+        .await
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| modified > since)
+}
+
 #[allow(clippy::cognitive_complexity)]
-fn check_launch_options(settings: &Settings) {
+fn check_launch_options(settings: &Settings, args: &Args) {
     // Launch options and overrides
     let launch_opts = match LaunchOptions::new(
         settings
@@ -274,7 +421,7 @@ fn check_launch_options(settings: &Settings) {
         }
     };
 
-    if let Some(opts) = launch_opts {
+    if let Some(mut opts) = launch_opts {
         // Warn about missing launch options for TF2
         match opts.check_missing_args() {
             Ok(missing_opts) if !missing_opts.is_empty() => {
@@ -295,6 +442,35 @@ fn check_launch_options(settings: &Settings) {
                 );
             }
         }
+
+        if args.fix_launch_options {
+            fix_launch_options(&mut opts, args.dry_run);
+        }
+    }
+}
+
+fn fix_launch_options(opts: &mut LaunchOptions, dry_run: bool) {
+    let new_opts = match opts.add_missing_args() {
+        Ok(Some(new_opts)) => new_opts,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::error!("Couldn't fix launch options: {e:?}");
+            return;
+        }
+    };
+
+    if dry_run {
+        tracing::info!("Launch options would be set to \"{new_opts}\"");
+        return;
+    }
+
+    match opts.write() {
+        Ok(backup) => {
+            tracing::info!(
+                "Launch options set to \"{new_opts}\" (previous config saved to {backup:?})"
+            );
+        }
+        Err(e) => tracing::error!("Couldn't fix launch options: {e:?}"),
     }
 }
 
@@ -315,7 +491,7 @@ fn init_tracing() -> Option<WorkerGuard> {
             ),
     );
 
-    match std::fs::File::create("./macclient.log") {
+    match std::fs::File::create(settings::LOG_FILE_PATH) {
         Ok(latest_log) => {
             let (file_writer, guard) = tracing_appender::non_blocking(latest_log);
             subscriber
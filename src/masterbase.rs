@@ -1,10 +1,11 @@
 use std::{
     collections::HashMap,
     fmt::{Debug, Display, Write},
+    time::Duration,
 };
 
 use futures::SinkExt;
-use reqwest::{Client, RequestBuilder, Response};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use steamid_ng::SteamID;
 use thiserror::Error;
@@ -23,9 +24,11 @@ pub enum Error {
     WebSocket(#[from] tokio_tungstenite::tungstenite::error::Error),
     #[error("Request failed: {0}")]
     Failed(String),
+    #[error("Masterbase key was rejected")]
+    Unauthorized,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum ReportReason {
     Bot,
     Cheater,
@@ -43,9 +46,17 @@ impl TryFrom<Verdict> for ReportReason {
     }
 }
 
+/// How many times to try re-establishing a dropped session before giving up
+/// on the rest of the demo
+const RESUME_ATTEMPTS: u32 = 3;
+
 #[derive(Deserialize)]
 struct SessionID {
     session_id: u128,
+    /// Only provided by masterbase versions which allow a dropped upload to be
+    /// reconnected to the same session
+    #[serde(default)]
+    resume_token: Option<String>,
     #[serde(skip)]
     close: Option<Sender<()>>,
 }
@@ -82,6 +93,8 @@ pub type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
 pub struct DemoSession {
     session_id: SessionID,
     ws_client: WebSocket,
+    /// How many bytes the masterbase has accepted so far in this session
+    bytes_sent: u64,
 
     host: String,
     key: String,
@@ -94,6 +107,7 @@ impl DemoSession {
     ///   `fake_ip`, or `map`)
     /// * Network error
     /// * Invalid response
+    /// * The masterbase rejected the provided `key`
     ///
     /// # Panics
     /// This function spawns a task responsible for closing the session out when the
@@ -127,6 +141,9 @@ impl DemoSession {
         let url = reqwest::Url::parse_with_params(&endpoint, params)?;
         let response = reqwest::get(url).await?;
 
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(Error::Unauthorized);
+        }
         if !response.status().is_success() {
             return Err(Error::Failed(response.text().await?));
         }
@@ -137,14 +154,7 @@ impl DemoSession {
         session_id.close = Some(tx);
 
         // Open Websocket
-        let params: [(&str, &str); 2] =
-            [("api_key", &key), ("session_id", &session_id.to_string())];
-        let ws_endpoint = if http {
-            format!("ws://{host}/demos")
-        } else {
-            format!("wss://{host}/demos")
-        };
-        let url = reqwest::Url::parse_with_params(&ws_endpoint, params)?;
+        let url = demos_ws_url(&host, &key, &session_id, http, None)?;
 
         // Wait for the dropped `DemoSession` to tell it to close the session.
         // When it receives a message over the channel, it makes the appropriate
@@ -172,6 +182,7 @@ impl DemoSession {
         Ok(Self {
             session_id,
             ws_client,
+            bytes_sent: 0,
             host,
             key,
             http,
@@ -185,9 +196,90 @@ impl DemoSession {
         bytes: Vec<u8>,
     ) -> Result<(), tokio_tungstenite::tungstenite::Error> {
         tracing::debug!("Sending demo bytes to masterbase");
+        let len = bytes.len() as u64;
         self.ws_client
             .send(tokio_tungstenite::tungstenite::Message::Binary(bytes))
-            .await
+            .await?;
+        self.bytes_sent += len;
+        Ok(())
+    }
+
+    /// Sends the bytes, and if the connection has dropped, attempts to resume
+    /// the session and send them again. The bytes are handed off to be sent,
+    /// so `rebuild` is called to get them again for each retry, given where in
+    /// the demo they start. If the masterbase doesn't support resuming
+    /// sessions this behaves the same as [`Self::send_bytes`].
+    ///
+    /// # Errors
+    /// If the bytes could not be sent and the session could not be resumed
+    pub async fn send_bytes_or_resume(
+        &mut self,
+        bytes: Vec<u8>,
+        mut rebuild: impl FnMut(u64) -> std::io::Result<Vec<u8>> + Send,
+    ) -> Result<(), Error> {
+        let Err(mut error) = self.send_bytes(bytes).await.map_err(Error::from) else {
+            return Ok(());
+        };
+
+        if !self.can_resume() {
+            return Err(error);
+        }
+
+        for attempt in 1..=RESUME_ATTEMPTS {
+            tracing::warn!(
+                "Lost connection to masterbase ({error}), resuming session {} (attempt {attempt}/{RESUME_ATTEMPTS})",
+                self.session_id
+            );
+            tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+
+            if let Err(e) = self.resume().await {
+                error = e;
+                continue;
+            }
+            let bytes = rebuild(self.bytes_sent)
+                .map_err(|e| Error::Failed(format!("Couldn't read the demo to resend: {e}")))?;
+            match self.send_bytes(bytes).await {
+                Ok(()) => {
+                    tracing::info!("Resumed demo session {}", self.session_id);
+                    return Ok(());
+                }
+                Err(e) => error = e.into(),
+            }
+        }
+
+        Err(error)
+    }
+
+    /// Whether the masterbase provided a way to resume this session if the
+    /// connection drops
+    #[must_use]
+    pub const fn can_resume(&self) -> bool {
+        self.session_id.resume_token.is_some()
+    }
+
+    /// Reconnects to the same session, continuing from the last byte the
+    /// masterbase accepted.
+    ///
+    /// # Errors
+    /// * The session can't be resumed
+    /// * Network error
+    pub async fn resume(&mut self) -> Result<(), Error> {
+        let Some(token) = &self.session_id.resume_token else {
+            return Err(Error::Failed(
+                "The masterbase does not support resuming sessions".to_string(),
+            ));
+        };
+
+        let url = demos_ws_url(
+            &self.host,
+            &self.key,
+            &self.session_id,
+            self.http,
+            Some((token, self.bytes_sent)),
+        )?;
+        let (ws_client, _) = tokio_tungstenite::connect_async(url.as_str()).await?;
+        self.ws_client = ws_client;
+        Ok(())
     }
 
     /// # Errors
@@ -270,6 +362,32 @@ impl Debug for DemoSession {
     }
 }
 
+/// The websocket URL demo bytes are streamed to. Providing a resume token and
+/// offset reconnects to an existing session instead of starting to receive a
+/// new demo.
+fn demos_ws_url(
+    host: &str,
+    key: &str,
+    session_id: &SessionID,
+    http: bool,
+    resume: Option<(&str, u64)>,
+) -> Result<reqwest::Url, url::ParseError> {
+    let session_id = session_id.to_string();
+    let mut params: Vec<(&str, String)> =
+        vec![("api_key", key.to_owned()), ("session_id", session_id)];
+    if let Some((token, offset)) = resume {
+        params.push(("resume_token", token.to_owned()));
+        params.push(("offset", offset.to_string()));
+    }
+
+    let ws_endpoint = if http {
+        format!("ws://{host}/demos")
+    } else {
+        format!("wss://{host}/demos")
+    };
+    reqwest::Url::parse_with_params(&ws_endpoint, params)
+}
+
 /// Forcefully closes the active demo session if one was open.
 /// If `response.status().is_success()`, then a session was closed.
 /// If the response was not successful but response status is 403,
@@ -2,7 +2,11 @@ use event_loop::{try_get, Handled, Is, MessageHandler};
 use steamid_ng::SteamID;
 
 use super::console::ConsoleOutput;
-use crate::state::MACState;
+use crate::{
+    events::{Refresh, RefreshReason},
+    player::RefreshDelta,
+    state::MACState,
+};
 
 // Messages *********************
 
@@ -10,6 +14,11 @@ use crate::state::MACState;
 pub struct NewPlayers(pub Vec<SteamID>);
 impl<S> event_loop::Message<S> for NewPlayers {}
 
+/// Who joined or left the server since the previous refresh
+#[derive(Debug, Clone)]
+pub struct PresenceChanged(pub RefreshDelta);
+impl<S> event_loop::Message<S> for PresenceChanged {}
+
 // Handlers *********************
 
 #[allow(clippy::module_name_repetitions)]
@@ -38,7 +47,8 @@ where
             | ConsoleOutput::ServerIP(_)
             | ConsoleOutput::Map(_)
             | ConsoleOutput::PlayerCount(_)
-            | ConsoleOutput::DemoStop(_) => {}
+            | ConsoleOutput::DemoStop(_)
+            | ConsoleOutput::SelfKicked(_) => {}
             ConsoleOutput::G15(ps) => {
                 steamids.extend(ps.iter().filter_map(|p| p.steamid));
             }
@@ -52,3 +62,30 @@ where
         Handled::single(NewPlayers(steamids))
     }
 }
+
+/// Works out who joined or left on each timer refresh, before the players are
+/// refreshed again
+pub struct ExtractPresenceChanges;
+impl<IM, OM> MessageHandler<MACState, IM, OM> for ExtractPresenceChanges
+where
+    IM: Is<Refresh>,
+    OM: Is<PresenceChanged>,
+{
+    fn handle_message(
+        &mut self,
+        state: &MACState,
+        message: &IM,
+    ) -> Option<event_loop::Handled<OM>> {
+        let refresh = try_get::<Refresh>(message)?;
+        if refresh.reason != RefreshReason::Timer {
+            return None;
+        }
+
+        let delta = state.players.presence_delta();
+        if delta.is_empty() {
+            return None;
+        }
+
+        Handled::single(PresenceChanged(delta))
+    }
+}
@@ -1,10 +1,13 @@
 use std::collections::{HashMap, HashSet};
 
 use crate::player::FriendInfo;
+use chrono::{DateTime, Utc};
 use steamid_ng::SteamID;
 
 pub struct Parties {
     parties: Vec<HashSet<SteamID>>,
+    /// When the parties were last found
+    last_computed: Option<DateTime<Utc>>,
 }
 
 /// Groups accounts by those who are friends. Usually indicative of people who are
@@ -14,6 +17,7 @@ impl Parties {
     pub const fn new() -> Self {
         Self {
             parties: Vec::new(),
+            last_computed: None,
         }
     }
 
@@ -22,6 +26,21 @@ impl Parties {
         &self.parties
     }
 
+    #[must_use]
+    pub const fn last_computed(&self) -> Option<DateTime<Utc>> {
+        self.last_computed
+    }
+
+    /// Ids of all the parties the given account is a member of.
+    #[must_use]
+    pub fn parties_of(&self, steamid: SteamID) -> Vec<u32> {
+        self.parties
+            .iter()
+            .filter(|p| p.contains(&steamid))
+            .map(party_id)
+            .collect()
+    }
+
     /// Given a set of players and all of their friends, as well as a list to limit which accounts will be analysed,
     /// create a set of groups where all the members in a group are friends with each other.
     pub fn find_parties(&mut self, friends: &HashMap<SteamID, FriendInfo>, connected: &[SteamID]) {
@@ -94,9 +113,30 @@ impl Parties {
             // Finally add this set
             self.parties.push(new_p);
         }
+
+        // Keep the order independent of the order the friends were iterated in
+        self.parties.sort_by_cached_key(party_id);
+        self.last_computed = Some(Utc::now());
     }
 }
 
+/// A stable identifier for a party derived from its members, so the same group
+/// of players is always given the same id regardless of when or in what order
+/// the parties were found.
+#[must_use]
+pub fn party_id(party: &HashSet<SteamID>) -> u32 {
+    let mut members: Vec<u64> = party.iter().map(|&s| u64::from(s)).collect();
+    members.sort_unstable();
+
+    // FNV-1a
+    members
+        .iter()
+        .flat_map(|m| m.to_le_bytes())
+        .fold(0x811c_9dc5, |hash: u32, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        })
+}
+
 impl Default for Parties {
     fn default() -> Self {
         Self::new()
@@ -112,7 +152,7 @@ mod test {
     use crate::player::{Friend, FriendInfo};
     use steamid_ng::SteamID;
 
-    use super::Parties;
+    use super::{party_id, Parties};
 
     #[test]
     pub fn party_generation() {
@@ -188,4 +228,80 @@ mod test {
 
         assert!(parties.parties().len() == expected_parties.len());
     }
+
+    fn make_friends(friends: HashMap<SteamID, Vec<SteamID>>) -> HashMap<SteamID, FriendInfo> {
+        friends
+            .into_iter()
+            .map(|(s, fi)| {
+                (
+                    s,
+                    FriendInfo {
+                        public: None,
+                        friends: fi
+                            .into_iter()
+                            .map(|s| Friend {
+                                steamid: s,
+                                friend_since: 0,
+                            })
+                            .collect(),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn party_ids_are_stable() {
+        let s: Vec<_> = [0, 1, 2, 3, 4]
+            .iter()
+            .map(|&s| SteamID::from(76561198000000000 + s))
+            .collect();
+
+        let friends = make_friends(HashMap::from([
+            (s[0], vec![s[1], s[2]]),
+            (s[1], vec![s[0], s[2]]),
+            (s[2], vec![s[0], s[1]]),
+            (s[3], vec![s[4]]),
+            (s[4], vec![s[3]]),
+        ]));
+
+        let mut parties = Parties::new();
+        parties.find_parties(&friends, &s);
+        let ids: Vec<Vec<u32>> = s.iter().map(|&p| parties.parties_of(p)).collect();
+
+        // Same members found in a different order should give the same ids
+        let reversed: Vec<_> = s.iter().rev().copied().collect();
+        for _ in 0..10 {
+            let mut refreshed = Parties::new();
+            refreshed.find_parties(
+                &make_friends(HashMap::from([
+                    (s[4], vec![s[3]]),
+                    (s[3], vec![s[4]]),
+                    (s[2], vec![s[1], s[0]]),
+                    (s[1], vec![s[2], s[0]]),
+                    (s[0], vec![s[2], s[1]]),
+                ])),
+                &reversed,
+            );
+
+            let refreshed_ids: Vec<Vec<u32>> = s.iter().map(|&p| refreshed.parties_of(p)).collect();
+            assert_eq!(ids, refreshed_ids);
+            assert_eq!(
+                parties.parties().iter().map(party_id).collect::<Vec<_>>(),
+                refreshed.parties().iter().map(party_id).collect::<Vec<_>>()
+            );
+        }
+
+        // Different parties should have different ids
+        assert_eq!(ids[0].len(), 1);
+        assert_eq!(ids[3].len(), 1);
+        assert_eq!(ids[0], ids[1]);
+        assert_eq!(ids[3], ids[4]);
+        assert_ne!(ids[0], ids[3]);
+
+        // Changing the membership changes the id
+        let mut smaller = Parties::new();
+        smaller.find_parties(&friends, &s[1..]);
+        assert_ne!(smaller.parties_of(s[1]), ids[1]);
+    }
 }
@@ -1,6 +1,7 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
-    ops::{Deref, DerefMut},
+    fmt::Write,
+    ops::{Deref, DerefMut, RangeInclusive},
     path::{Path, PathBuf},
 };
 
@@ -9,20 +10,51 @@ use serde::{Deserialize, Serialize, Serializer};
 use steamid_ng::SteamID;
 
 use crate::{
+    confidence::AutoConfidence,
     io::{
         g15::{self, G15Player},
-        regexes::StatusLine,
+        regexes::{PlayerKill, StatusLine},
     },
     parties::Parties,
     player_records::{default_custom_data, PlayerRecord, PlayerRecords, Verdict},
-    settings::{ConfigFilesError, Settings},
+    settings::{ConfigFilesError, NameSource, PlayerSort, Settings},
 };
 
 pub mod tags {
     pub const FRIEND: &str = "Friend";
+    /// The player was on a server with the user in a previous session
+    pub const SEEN_BEFORE: &str = "SeenBefore";
+    /// The player's K/D is far above everyone else's on the server, see
+    /// [`super::Players::performance_outliers`]
+    pub const PERFORMANCE_OUTLIER: &str = "PerformanceOutlier";
 }
 
-// const MAX_HISTORY_LEN: usize = 100;
+/// How many players from previous sessions are remembered
+const MAX_RECENT_PLAYERS: usize = 500;
+/// How many standard deviations above the server's mean K/D a player has to be
+/// to be tagged as a [`tags::PERFORMANCE_OUTLIER`]
+const OUTLIER_STD_DEVS: f32 = 2.0;
+/// How many players from this session are kept in the history by default
+const DEFAULT_MAX_HISTORY_LEN: usize = 1000;
+/// SteamID64s of individual accounts in the public universe, the only ones a
+/// search resolves directly
+const INDIVIDUAL_STEAMID64S: RangeInclusive<u64> = 76_561_197_960_265_728..=76_561_202_255_233_023;
+
+/// The players who joined or left the server since the previous refresh. A
+/// player has left as soon as they're marked as disconnected, even though
+/// they're kept around until they're pruned.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RefreshDelta {
+    pub joined: Vec<SteamID>,
+    pub left: Vec<SteamID>,
+}
+
+impl RefreshDelta {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.joined.is_empty() && self.left.is_empty()
+    }
+}
 
 pub struct Players {
     pub game_info: HashMap<SteamID, GameInfo>,
@@ -33,10 +65,24 @@ pub struct Players {
     pub parties: Parties,
 
     pub connected: Vec<SteamID>,
+    /// When each connected player joined the server
+    pub connected_since: HashMap<SteamID, DateTime<Utc>>,
+    /// Kills and deaths of each connected player since they joined
+    pub session_kills: HashMap<SteamID, SessionKills>,
     pub history: VecDeque<SteamID>,
+    /// The connected players who weren't disconnected as of the last refresh
+    last_present: Vec<SteamID>,
 
     pub user: Option<SteamID>,
 
+    name_source: NameSource,
+    player_sort: PlayerSort,
+    /// How many players are kept in the history before the oldest are dropped
+    max_history_len: usize,
+    /// Players the user is keeping an eye on this session
+    pinned: HashSet<SteamID>,
+    /// Players seen in previous sessions, most recent first, loaded from disk
+    previous_session: Vec<SteamID>,
     parties_needs_update: bool,
 }
 
@@ -53,9 +99,17 @@ impl Players {
             parties: Parties::new(),
 
             connected: Vec::new(),
+            connected_since: HashMap::new(),
+            session_kills: HashMap::new(),
             history: VecDeque::new(),
+            last_present: Vec::new(),
             user,
 
+            name_source: NameSource::default(),
+            player_sort: PlayerSort::default(),
+            max_history_len: DEFAULT_MAX_HISTORY_LEN,
+            pinned: HashSet::new(),
+            previous_session: Vec::new(),
             parties_needs_update: false,
         };
 
@@ -70,9 +124,56 @@ impl Players {
             Err(e) => tracing::error!("Failed to load steam info cache: {e}"),
         }
 
+        match players.load_recent_players() {
+            Ok(()) => tracing::debug!(
+                "Loaded {} players from previous sessions.",
+                players.previous_session.len()
+            ),
+            Err(ConfigFilesError::IO(_, e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::error!("Failed to load recent players: {e}"),
+        }
+
         players
     }
 
+    /// Set which name should be preferred when a player has both an in-game
+    /// and Steam profile name
+    pub fn set_name_source(&mut self, source: NameSource) {
+        self.name_source = source;
+    }
+
+    /// Set how connected players are ordered when serialized
+    pub fn set_player_sort(&mut self, sort: PlayerSort) {
+        self.player_sort = sort;
+    }
+
+    /// Set how many players are kept in the history. Only the history is
+    /// shrunk, their records are left alone.
+    pub fn set_max_history_len(&mut self, len: usize) {
+        self.max_history_len = len;
+        self.shrink_history();
+    }
+
+    fn shrink_history(&mut self) {
+        let excess = self.history.len().saturating_sub(self.max_history_len);
+        self.history.drain(..excess);
+    }
+
+    /// Pin or unpin a player so they are shown at the top of the player list.
+    /// Pins only last until the application is closed.
+    pub fn set_pinned(&mut self, steamid: SteamID, pinned: bool) {
+        if pinned {
+            self.pinned.insert(steamid);
+        } else {
+            self.pinned.remove(&steamid);
+        }
+    }
+
+    #[must_use]
+    pub fn is_pinned(&self, steamid: SteamID) -> bool {
+        self.pinned.contains(&steamid)
+    }
+
     /// Retrieve the local verdict for a player
     #[must_use]
     pub fn verdict(&self, steamid: SteamID) -> Verdict {
@@ -81,6 +182,19 @@ impl Players {
             .map_or(Verdict::Player, PlayerRecord::verdict)
     }
 
+    /// Check if the user has muted alerts from a player
+    #[must_use]
+    pub fn is_muted(&self, steamid: SteamID) -> bool {
+        self.records.get(&steamid).is_some_and(PlayerRecord::muted)
+    }
+
+    /// Retrieve how many heuristics have flagged a player, see
+    /// [`crate::confidence::ConfidenceScorer`]
+    #[must_use]
+    pub fn auto_confidence(&self, steamid: SteamID) -> Option<AutoConfidence> {
+        AutoConfidence::from_custom_data(self.records.get(&steamid)?.custom_data())
+    }
+
     /// Check if a player has a particular tag set
     #[must_use]
     pub fn has_tag(&self, steamid: SteamID, tag: &str) -> bool {
@@ -123,6 +237,48 @@ impl Players {
         self.update_user_friend_tag(steamid);
     }
 
+    /// Loads a friends graph from a JSON file mapping accounts to their friends
+    /// and adds it as if each friends list had been fetched from the Steam API.
+    /// Useful for exercising party detection and friends-related features
+    /// without making any requests to Steam.
+    ///
+    /// ```no_run
+    /// # use client_backend::{player::Players, player_records::PlayerRecords};
+    /// // friends.json:
+    /// // {
+    /// //     "76561197960287930": ["76561197960265728", "76561197960265729"],
+    /// //     "76561197960265728": ["76561197960287930"]
+    /// // }
+    /// # let records = PlayerRecords::default();
+    /// let mut players = Players::new(records, None);
+    /// players.import_friend_graph("friends.json")?;
+    /// # Ok::<(), client_backend::settings::ConfigFilesError>(())
+    /// ```
+    ///
+    /// # Errors
+    /// If the file could not be read or is not a valid friends graph
+    pub fn import_friend_graph(&mut self, path: impl AsRef<Path>) -> Result<(), ConfigFilesError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
+        let graph: HashMap<SteamID, Vec<SteamID>> = serde_json::from_str(&contents)
+            .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))?;
+
+        tracing::info!("Importing friends lists of {} accounts.", graph.len());
+        for (steamid, friends) in graph {
+            let friends = friends
+                .into_iter()
+                .map(|steamid| Friend {
+                    steamid,
+                    friend_since: 0,
+                })
+                .collect();
+            self.update_friends_list(steamid, friends);
+        }
+
+        Ok(())
+    }
+
     /// Sets the friends list and friends list visibility, returning any old
     /// friends that have been removed
     fn set_friends(&mut self, steamid: SteamID, friends: Vec<Friend>) -> Vec<SteamID> {
@@ -202,6 +358,37 @@ impl Players {
         }
     }
 
+    /// Forgets all cached Steam profile info so it will be fetched again,
+    /// optionally deleting the cache on disk as well.
+    pub fn clear_steam_info(&mut self, delete_cache: bool) {
+        self.steam_info.clear();
+
+        if !delete_cache {
+            return;
+        }
+
+        match Self::locate_steam_info_cache_path() {
+            Ok(path) => match std::fs::remove_file(&path) {
+                Ok(()) => tracing::info!("Deleted steam info cache."),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => tracing::error!("Failed to delete steam info cache {path:?}: {e}"),
+            },
+            Err(e) => tracing::error!("Couldn't locate steam info cache: {e}"),
+        }
+    }
+
+    /// Forgets all known friends lists, along with anything derived from them.
+    pub fn clear_friend_info(&mut self) {
+        self.friend_info.clear();
+
+        self.tags.values_mut().for_each(|t| {
+            t.remove(tags::FRIEND);
+        });
+        self.tags.retain(|_, t| !t.is_empty());
+
+        self.parties_needs_update = true;
+    }
+
     /// Check if an account is friends with the user.
     /// Returns None if we don't have enough information to tell.
     #[must_use]
@@ -241,12 +428,22 @@ impl Players {
     /// Moves any old players from the server into history. Any console commands
     /// (status, `g15_dumpplayer`, etc) should be run before calling this
     /// function again to prevent removing all players from the player list.
-    pub fn refresh(&mut self) {
+    ///
+    /// Players who have been missing for more than `disconnected_cycles` calls
+    /// are marked as disconnected, and are removed after `prune_cycles` calls.
+    pub fn refresh(&mut self, disconnected_cycles: u32, prune_cycles: u32) {
+        // Anyone marked as disconnected below is picked up by the next delta
+        self.last_present = self.present().collect();
+
         // Get old players
         let unaccounted_players: Vec<SteamID> = self
             .connected
             .iter()
-            .filter(|&s| self.game_info.get(s).map_or(true, GameInfo::should_prune))
+            .filter(|&s| {
+                self.game_info
+                    .get(s)
+                    .map_or(true, |gi| gi.should_prune(prune_cycles))
+            })
             .copied()
             .collect();
 
@@ -255,41 +452,244 @@ impl Players {
         }
 
         self.connected.retain(|s| !unaccounted_players.contains(s));
+        self.connected_since
+            .retain(|s, _| !unaccounted_players.contains(s));
+        self.session_kills
+            .retain(|s, _| !unaccounted_players.contains(s));
 
         // Remove any of them from the history as they will be added more recently
         self.history
             .retain(|p| !unaccounted_players.iter().any(|up| up == p));
 
-        // Shrink to not go past max number of players
-        // let num_players = self.history.len() + unaccounted_players.len();
-        // for _ in MAX_HISTORY_LEN..num_players {
-        //     self.history.pop_front();
-        // }
-
         for p in unaccounted_players {
             self.history.push_back(p);
         }
 
+        // Shrink to not go past max number of players
+        self.shrink_history();
+
         // Mark all remaining players as unaccounted, they will be marked as accounted
         // again when they show up in status or another console command.
-        self.game_info.values_mut().for_each(GameInfo::next_cycle);
+        self.game_info
+            .values_mut()
+            .for_each(|gi| gi.next_cycle(disconnected_cycles));
 
         if self.parties_needs_update {
-            self.parties
-                .find_parties(&self.friend_info, &self.connected);
-            self.parties_needs_update = false;
+            self.recompute_parties();
+        }
+
+        self.tag_performance_outliers();
+    }
+
+    /// Tags the connected players whose K/D stands out, and untags the rest
+    fn tag_performance_outliers(&mut self) {
+        let outliers = self.performance_outliers(OUTLIER_STD_DEVS);
+        for s in self.connected.clone() {
+            if outliers.contains(&s) {
+                self.set_tag(s, tags::PERFORMANCE_OUTLIER.into());
+            } else {
+                self.clear_tag(s, tags::PERFORMANCE_OUTLIER);
+            }
+        }
+    }
+
+    /// Connected players who haven't been marked as disconnected
+    pub fn present(&self) -> impl Iterator<Item = SteamID> + '_ {
+        self.connected.iter().copied().filter(|s| {
+            self.game_info
+                .get(s)
+                .map_or(true, |gi| gi.state != PlayerState::Disconnected)
+        })
+    }
+
+    /// Who has joined or left the server since the last refresh
+    #[must_use]
+    pub fn presence_delta(&self) -> RefreshDelta {
+        let present: Vec<SteamID> = self.present().collect();
+        RefreshDelta {
+            joined: present
+                .iter()
+                .filter(|s| !self.last_present.contains(s))
+                .copied()
+                .collect(),
+            left: self
+                .last_present
+                .iter()
+                .filter(|s| !present.contains(s))
+                .copied()
+                .collect(),
         }
     }
 
+    /// Finds parties among the connected players using the friends info
+    /// currently available.
+    pub fn recompute_parties(&mut self) {
+        self.parties
+            .find_parties(&self.friend_info, &self.connected);
+        self.parties_needs_update = false;
+    }
+
+    /// Finds connected players whose K/D is more than `threshold` standard
+    /// deviations above the server mean. Players without a meaningful K/D are
+    /// ignored, and nobody is flagged if there are too few to compare against.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn performance_outliers(&self, threshold: f32) -> Vec<SteamID> {
+        // Nobody can be more than sqrt(n - 1) standard deviations above the
+        // mean of n players, so with fewer than 6 nobody could ever be more
+        // than `OUTLIER_STD_DEVS` above it
+        const MIN_PLAYERS: usize = 6;
+
+        let kds: Vec<(SteamID, f32)> = self
+            .connected
+            .iter()
+            .filter_map(|s| Some((*s, self.game_info.get(s)?.kd_ratio()?)))
+            .collect();
+        if kds.len() < MIN_PLAYERS {
+            return Vec::new();
+        }
+
+        let n = kds.len() as f32;
+        let mean = kds.iter().map(|(_, kd)| kd).sum::<f32>() / n;
+        let variance = kds.iter().map(|(_, kd)| (kd - mean).powi(2)).sum::<f32>() / n;
+        let std_dev = variance.sqrt();
+        if std_dev <= f32::EPSILON {
+            return Vec::new();
+        }
+
+        kds.into_iter()
+            .filter(|(_, kd)| (kd - mean) / std_dev > threshold)
+            .map(|(s, _)| s)
+            .collect()
+    }
+
+    /// Finds accounts that might belong to the same person as `steamid`, either
+    /// because they have gone by the same name (current or previous, ignoring
+    /// case) or because their friend lists overlap heavily.
+    ///
+    /// This is only a heuristic, common names and tight-knit friend groups
+    /// will both produce false positives, so the result should be treated as
+    /// a hint and never acted on automatically.
+    #[must_use]
+    pub fn possible_alts(&self, steamid: SteamID) -> Vec<SteamID> {
+        const MIN_SHARED_FRIENDS: usize = 3;
+
+        let names_of = |s: SteamID| -> HashSet<String> {
+            let current = self.current_name(self.game_info.get(&s), self.steam_info.get(&s));
+            let previous = self
+                .records
+                .get(&s)
+                .map(|r| r.previous_names())
+                .unwrap_or_default();
+
+            current
+                .into_iter()
+                .chain(previous.iter().map(String::as_str))
+                .map(|n| n.trim().to_lowercase())
+                .filter(|n| !n.is_empty())
+                .collect()
+        };
+        let friends_of = |s: SteamID| -> HashSet<SteamID> {
+            self.friend_info
+                .get(&s)
+                .map(|fi| fi.friends.iter().map(|f| f.steamid).collect())
+                .unwrap_or_default()
+        };
+
+        let names = names_of(steamid);
+        let friends = friends_of(steamid);
+
+        let candidates: HashSet<SteamID> = self
+            .records
+            .keys()
+            .chain(self.game_info.keys())
+            .chain(self.steam_info.keys())
+            .chain(self.friend_info.keys())
+            .copied()
+            .filter(|&s| s != steamid)
+            .collect();
+
+        let mut alts: Vec<SteamID> = candidates
+            .into_iter()
+            .filter(|&s| {
+                !names.is_disjoint(&names_of(s))
+                    || (friends.len() >= MIN_SHARED_FRIENDS
+                        && friends.intersection(&friends_of(s)).count() >= MIN_SHARED_FRIENDS)
+            })
+            .collect();
+        alts.sort_unstable_by_key(|&s| u64::from(s));
+        alts
+    }
+
+    /// Finds connected players and records whose name contains `query`,
+    /// ignoring case. Names are matched using [`Self::get_name`] as well as
+    /// each record's previous names. Exact matches are ranked first, then
+    /// names starting with the query, then any other matches, with current
+    /// names ranked above previous ones.
+    ///
+    /// A SteamID64 of an individual account is resolved directly to that
+    /// account, any other number is matched against names as usual.
+    #[must_use]
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SteamID> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+
+        let rank_name = |name: &str| -> Option<u8> {
+            let name = name.to_lowercase();
+            if name == query {
+                Some(0)
+            } else if name.starts_with(&query) {
+                Some(1)
+            } else if name.contains(&query) {
+                Some(2)
+            } else {
+                None
+            }
+        };
+        let rank = |s: SteamID| -> Option<u8> {
+            let current = self.get_name(s).and_then(rank_name);
+            let previous = self
+                .records
+                .get(&s)
+                .and_then(|r| r.previous_names().iter().filter_map(|n| rank_name(n)).min())
+                .map(|r| r + 3);
+            current.into_iter().chain(previous).min()
+        };
+
+        let direct = query
+            .parse::<u64>()
+            .ok()
+            .filter(|id| INDIVIDUAL_STEAMID64S.contains(id))
+            .map(SteamID::from);
+        let candidates: HashSet<SteamID> = self
+            .connected
+            .iter()
+            .chain(self.records.keys())
+            .copied()
+            .filter(|&s| Some(s) != direct)
+            .collect();
+
+        let mut matches: Vec<(u8, SteamID)> = candidates
+            .into_iter()
+            .filter_map(|s| rank(s).map(|r| (r, s)))
+            .collect();
+        matches.sort_by_key(|&(r, s)| (r, !self.connected.contains(&s), u64::from(s)));
+
+        direct
+            .into_iter()
+            .chain(matches.into_iter().map(|(_, s)| s))
+            .take(limit)
+            .collect()
+    }
+
     /// Gets a struct containing all the relevant data on a player in a
     /// serializable format
     pub fn get_serializable_player(&self, steamid: SteamID) -> Player {
         let game_info = self.game_info.get(&steamid);
         let steam_info = self.steam_info.get(&steamid);
-        let name = game_info.map_or_else(
-            || steam_info.map_or("", |si| &si.account_name),
-            |gi| &gi.name,
-        );
+        let name = self.current_name(game_info, steam_info).unwrap_or("");
         let tags: Vec<&str> = self
             .tags
             .get(&steamid)
@@ -315,8 +715,15 @@ impl Players {
             name,
             steamID64: steamid,
             localVerdict: local_verdict,
+            muted: record.as_ref().is_some_and(|r| r.muted()),
+            pinned: self.is_pinned(steamid),
+            timesSeen: record.as_ref().map_or(0, |r| r.times_seen()),
+            lastSeen: record.as_ref().and_then(|r| r.last_seen()),
+            connectedSince: self.connected_since.get(&steamid).copied(),
             steamInfo: steam_info,
             gameInfo: game_info,
+            kdRatio: game_info.and_then(GameInfo::kd_ratio),
+            sessionKills: self.session_kills.get(&steamid),
             customData: record
                 .as_ref()
                 .map_or_else(default_custom_data, |r| r.custom_data().clone()),
@@ -325,6 +732,7 @@ impl Players {
             previous_names,
             friends,
             friendsIsPublic: friend_info.and_then(|fi| fi.public),
+            parties: self.parties.parties_of(steamid),
         }
     }
 
@@ -334,15 +742,8 @@ impl Players {
                 continue;
             };
 
-            if let Some(r) = self.records.get_mut(&steamid) {
-                r.mark_seen();
-            }
-
-            // Add to connected players if they aren't already
-            if !self.connected.contains(&steamid) {
-                self.connected.push(steamid);
-                self.parties_needs_update = true;
-            }
+            self.records.mark_seen(steamid);
+            self.add_connected(steamid);
 
             // Update game info
             if let Some(game_info) = self.game_info.get_mut(&steamid) {
@@ -361,14 +762,14 @@ impl Players {
     pub fn handle_status_line(&mut self, status: StatusLine) {
         let steamid = status.steamid;
 
-        if let Some(r) = self.records.get_mut(&steamid) {
-            r.mark_seen();
-        }
+        self.records.mark_seen(steamid);
+        self.add_connected(steamid);
 
-        // Add to connected players if they aren't already
-        if !self.connected.contains(&steamid) {
-            self.connected.push(steamid);
-            self.parties_needs_update = true;
+        // Status tells us how long they've actually been connected, which is
+        // earlier than when we first saw them if they joined before we started
+        let since = Utc::now() - chrono::Duration::seconds(i64::from(status.time));
+        if let Some(connected_since) = self.connected_since.get_mut(&steamid) {
+            *connected_since = (*connected_since).min(since);
         }
 
         if let Some(game_info) = self.game_info.get_mut(&steamid) {
@@ -386,12 +787,28 @@ impl Players {
         }
     }
 
+    /// The current name of a player from either the game or their Steam
+    /// profile, whichever is preferred by the configured [`NameSource`].
+    fn current_name<'a>(
+        &self,
+        game_info: Option<&'a GameInfo>,
+        steam_info: Option<&'a SteamInfo>,
+    ) -> Option<&'a str> {
+        let game_name = game_info.map(|gi| gi.name.as_str());
+        let steam_name = steam_info.map(|si| si.account_name.as_str());
+
+        match self.name_source {
+            NameSource::Game => game_name.or(steam_name),
+            NameSource::Steam => steam_name.or(game_name),
+        }
+    }
+
     #[must_use]
     pub fn get_name(&self, steamid: SteamID) -> Option<&str> {
-        if let Some(gi) = self.game_info.get(&steamid) {
-            return Some(&gi.name);
-        } else if let Some(si) = self.steam_info.get(&steamid) {
-            return Some(&si.account_name);
+        if let Some(name) =
+            self.current_name(self.game_info.get(&steamid), self.steam_info.get(&steamid))
+        {
+            return Some(name);
         } else if let Some(last_name) = self
             .records
             .get(&steamid)
@@ -419,6 +836,188 @@ impl Players {
             .collect()
     }
 
+    /// Counts a kill from the console towards the session stats of the killer
+    /// and victim. Suicides only count as a death. Players who aren't
+    /// connected are left out, as their stats would outlive their session.
+    pub fn handle_kill(&mut self, kill: &PlayerKill) {
+        if let Some(victim) = kill.victim_steamid.filter(|s| self.connected.contains(s)) {
+            self.session_kills.entry(victim).or_default().add_death();
+        }
+
+        if let Some(killer) = kill.killer_steamid.filter(|s| self.connected.contains(s)) {
+            if kill.victim_steamid != Some(killer) {
+                self.session_kills.entry(killer).or_default().add_kill();
+            }
+        }
+    }
+
+    /// Adds a player to the connected players if they aren't already
+    fn add_connected(&mut self, steamid: SteamID) {
+        if self.connected.contains(&steamid) {
+            return;
+        }
+
+        self.connected.push(steamid);
+        // The demo may have already told us when they joined
+        self.connected_since.entry(steamid).or_insert_with(Utc::now);
+        self.tag_if_seen_before(steamid);
+        self.parties_needs_update = true;
+    }
+
+    /// Connected players who joined within the given time, e.g. to spot a wave
+    /// of bots joining at once.
+    #[must_use]
+    pub fn recently_connected(&self, within: std::time::Duration) -> Vec<SteamID> {
+        let Ok(within) = chrono::Duration::from_std(within) else {
+            return self.connected.clone();
+        };
+        let cutoff = Utc::now() - within;
+
+        self.connected
+            .iter()
+            .filter(|s| self.connected_since.get(s).is_some_and(|&t| t >= cutoff))
+            .copied()
+            .collect()
+    }
+
+    fn tag_if_seen_before(&mut self, steamid: SteamID) {
+        if self.previous_session.contains(&steamid) && self.user != Some(steamid) {
+            self.set_tag(steamid, tags::SEEN_BEFORE.into());
+        }
+    }
+
+    /// The connected players, ordered by the chosen sort with pinned players
+    /// first.
+    #[must_use]
+    pub fn sorted_players(&self) -> Vec<Player> {
+        let mut players: Vec<Player> = self
+            .connected
+            .iter()
+            .map(|&s| self.get_serializable_player(s))
+            .collect();
+
+        // Sort by name and SteamID last so the order is always stable
+        let by_name = |a: &Player, b: &Player| {
+            a.name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then_with(|| u64::from(a.steamID64).cmp(&u64::from(b.steamID64)))
+        };
+        match self.player_sort {
+            PlayerSort::Joined => {}
+            PlayerSort::TeamThenName => players.sort_by(|a, b| {
+                let team = |p: &Player| p.gameInfo.map_or(u32::MAX, |gi| gi.team as u32);
+                team(a).cmp(&team(b)).then_with(|| by_name(a, b))
+            }),
+            PlayerSort::Verdict => players.sort_by(|a, b| {
+                let rank = |p: &Player| match p.localVerdict {
+                    Verdict::Cheater => 0,
+                    Verdict::Bot => 1,
+                    Verdict::Suspicious => 2,
+                    Verdict::Player => 3,
+                    Verdict::Trusted => 4,
+                };
+                rank(a).cmp(&rank(b)).then_with(|| by_name(a, b))
+            }),
+        }
+        // Stable sort, so pinned players keep the order chosen above
+        players.sort_by_key(|p| !p.pinned);
+
+        players
+    }
+
+    /// The current scoreboard as a plain text table, for sharing in chat or bug
+    /// reports.
+    #[must_use]
+    pub fn scoreboard_text(&self) -> String {
+        const NAME_WIDTH: usize = 24;
+
+        let mut out = format!(
+            "{:<NAME_WIDTH$}  {:<10}  {:>5}  {:>6}  {:>4}  Verdict\n",
+            "Name", "Team", "Kills", "Deaths", "Ping"
+        );
+
+        for player in self.sorted_players() {
+            // Truncate long names so the columns stay aligned
+            let name = if player.name.chars().count() > NAME_WIDTH {
+                let mut name: String = player.name.chars().take(NAME_WIDTH - 3).collect();
+                name.push_str("...");
+                name
+            } else {
+                player.name.to_string()
+            };
+
+            let (team, kills, deaths, ping) = player.gameInfo.map_or_else(
+                || ("-".to_string(), 0, 0, 0),
+                |gi| (format!("{:?}", gi.team), gi.kills, gi.deaths, gi.ping),
+            );
+
+            // Writing to a String can't fail
+            let _ = writeln!(
+                out,
+                "{name:<NAME_WIDTH$}  {team:<10}  {kills:>5}  {deaths:>6}  {ping:>4}  {}",
+                player.localVerdict
+            );
+        }
+
+        out
+    }
+
+    fn locate_recent_players_path() -> Result<PathBuf, ConfigFilesError> {
+        Settings::locate_config_directory().map(|p| p.join("recent_players.json"))
+    }
+
+    /// Loads the players seen in previous sessions, so they can be tagged if
+    /// they show up again.
+    ///
+    /// # Errors
+    /// If the file could not be read from disk or the data could not be deserialized
+    pub fn load_recent_players(&mut self) -> Result<(), ConfigFilesError> {
+        let path = Self::locate_recent_players_path()?;
+        let contents = std::fs::read(&path)
+            .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
+        self.previous_session = serde_json::from_slice(&contents)
+            .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))?;
+        Ok(())
+    }
+
+    /// Saves the most recently seen players so they can be recognised next
+    /// session. Only SteamIDs are kept, and only up to a limited number of
+    /// them, with players from this session taking priority.
+    ///
+    /// # Errors
+    /// If the data could not be serialized or the file could not be written back to disk
+    pub fn save_recent_players(&self) -> Result<(), ConfigFilesError> {
+        let path = Self::locate_recent_players_path()?;
+
+        let mut recent: Vec<SteamID> = Vec::new();
+        let this_session = self.connected.iter().chain(self.history.iter().rev());
+        let older = self.previous_session.iter();
+        for &s in this_session.chain(older) {
+            if recent.len() >= MAX_RECENT_PLAYERS {
+                break;
+            }
+            if self.user != Some(s) && !recent.contains(&s) {
+                recent.push(s);
+            }
+        }
+
+        let contents = serde_json::to_vec(&recent)
+            .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))?;
+        std::fs::write(&path, contents)
+            .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
+
+        Ok(())
+    }
+
+    pub fn save_recent_players_ok(&self) {
+        if let Err(e) = self.save_recent_players() {
+            tracing::error!("Failed to save recent players: {e}");
+        } else {
+            tracing::debug!("Saved recent players.");
+        }
+    }
+
     fn locate_steam_info_cache_path() -> Result<PathBuf, ConfigFilesError> {
         Settings::locate_config_directory().map(|p| p.join("steam_cache.bin"))
     }
@@ -470,12 +1069,7 @@ impl Serialize for Players {
     where
         S: Serializer,
     {
-        let players: Vec<Player> = self
-            .connected
-            .iter()
-            .map(|&s| self.get_serializable_player(s))
-            .collect();
-        players.serialize(serializer)
+        self.sorted_players().serialize(serializer)
     }
 }
 
@@ -536,9 +1130,13 @@ pub struct SteamInfo {
 }
 
 impl SteamInfo {
+    /// Whether the profile was fetched longer than `ttl` ago
     #[must_use]
-    pub fn expired(&self) -> bool {
-        Utc::now().signed_duration_since(self.fetched).num_hours() > 3
+    pub fn expired(&self, ttl: std::time::Duration) -> bool {
+        Utc::now()
+            .signed_duration_since(self.fetched)
+            .to_std()
+            .is_ok_and(|age| age > ttl)
     }
 }
 
@@ -665,18 +1263,29 @@ impl GameInfo {
         self.acknowledge();
     }
 
-    pub(crate) fn next_cycle(&mut self) {
-        const DISCONNECTED_THRESHOLD: u32 = 2;
+    /// The player's kill/death ratio, or `None` if they haven't been in enough
+    /// fights for it to be meaningful.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn kd_ratio(&self) -> Option<f32> {
+        const MIN_SAMPLE: u32 = 10;
+
+        if self.kills + self.deaths < MIN_SAMPLE {
+            return None;
+        }
 
-        self.last_seen += 1;
-        if self.last_seen > DISCONNECTED_THRESHOLD {
+        Some(self.kills as f32 / self.deaths.max(1) as f32)
+    }
+
+    pub(crate) fn next_cycle(&mut self, disconnected_threshold: u32) {
+        self.last_seen = self.last_seen.saturating_add(1);
+        if self.last_seen > disconnected_threshold {
             self.state = PlayerState::Disconnected;
         }
     }
 
-    pub(crate) const fn should_prune(&self) -> bool {
-        const CYCLE_LIMIT: u32 = 6;
-        self.last_seen > CYCLE_LIMIT
+    pub(crate) const fn should_prune(&self, cycle_limit: u32) -> bool {
+        self.last_seen > cycle_limit
     }
 
     fn acknowledge(&mut self) {
@@ -688,6 +1297,29 @@ impl GameInfo {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionKills {
+    pub kills: u32,
+    pub deaths: u32,
+    /// Kills since the player last died
+    pub streak: u32,
+    pub longest_streak: u32,
+}
+
+impl SessionKills {
+    fn add_kill(&mut self) {
+        self.kills += 1;
+        self.streak += 1;
+        self.longest_streak = self.longest_streak.max(self.streak);
+    }
+
+    fn add_death(&mut self) {
+        self.deaths += 1;
+        self.streak = 0;
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Friend {
     #[serde(rename = "steamID64", serialize_with = "serialize_steamid_as_string")]
@@ -754,12 +1386,342 @@ pub struct Player<'a> {
 
     pub steamInfo: Option<&'a SteamInfo>,
     pub gameInfo: Option<&'a GameInfo>,
+    pub kdRatio: Option<f32>,
+    pub sessionKills: Option<&'a SessionKills>,
     pub customData: serde_json::Value,
     pub localVerdict: Verdict,
+    pub muted: bool,
+    pub pinned: bool,
+    pub timesSeen: u32,
+    pub lastSeen: Option<DateTime<Utc>>,
+    pub connectedSince: Option<DateTime<Utc>>,
     pub convicted: bool,
     pub tags: Vec<&'a str>,
     pub previous_names: Vec<&'a str>,
 
     pub friends: Vec<&'a Friend>,
     pub friendsIsPublic: Option<bool>,
+    /// Stable ids of the parties this player is in
+    pub parties: Vec<u32>,
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unreadable_literal)]
+
+    use steamid_ng::SteamID;
+
+    use super::{tags, Friend, FriendInfo, GameInfo, Players, SessionKills, OUTLIER_STD_DEVS};
+    use crate::io::regexes::PlayerKill;
+    use crate::player_records::{PlayerRecord, PlayerRecords, Verdict};
+
+    fn join(players: &mut Players, steamid: SteamID) {
+        players.game_info.insert(steamid, GameInfo::default());
+        players.add_connected(steamid);
+    }
+
+    #[test]
+    pub fn presence_delta() {
+        let a = SteamID::from(76561197960265729);
+        let b = SteamID::from(76561197960265730);
+        let mut players = Players::new(PlayerRecords::default(), None);
+        let seen = |players: &mut Players, s| {
+            players
+                .game_info
+                .get_mut(&s)
+                .expect("Player has game info")
+                .acknowledge();
+        };
+
+        join(&mut players, a);
+        let delta = players.presence_delta();
+        assert_eq!(delta.joined, [a]);
+        assert!(delta.left.is_empty());
+        players.refresh(1, 10);
+
+        // Players are only reported as joining once
+        seen(&mut players, a);
+        join(&mut players, b);
+        let delta = players.presence_delta();
+        assert_eq!(delta.joined, [b]);
+        assert!(delta.left.is_empty());
+        players.refresh(1, 10);
+
+        // a stops showing up, and leaves as soon as they're disconnected
+        seen(&mut players, b);
+        players.refresh(1, 10);
+        let delta = players.presence_delta();
+        assert!(delta.joined.is_empty());
+        assert_eq!(delta.left, [a]);
+        assert!(players.connected.contains(&a));
+        seen(&mut players, b);
+        players.refresh(1, 10);
+        assert!(players.presence_delta().is_empty());
+
+        // and joins again when they come back
+        seen(&mut players, a);
+        seen(&mut players, b);
+        let delta = players.presence_delta();
+        assert_eq!(delta.joined, [a]);
+        assert!(delta.left.is_empty());
+    }
+
+    #[test]
+    pub fn session_kills_reset_on_rejoin() {
+        let a = SteamID::from(76561197960265729);
+        let b = SteamID::from(76561197960265730);
+        let mut players = Players::new(PlayerRecords::default(), None);
+        join(&mut players, a);
+        join(&mut players, b);
+
+        let kill = |killer: SteamID, victim: SteamID| PlayerKill {
+            killer_name: String::new(),
+            killer_steamid: Some(killer),
+            victim_name: String::new(),
+            victim_steamid: Some(victim),
+            weapon: "scattergun".into(),
+            crit: false,
+            timestamp: chrono::Utc::now(),
+        };
+        players.handle_kill(&kill(a, b));
+        players.handle_kill(&kill(a, b));
+        players.handle_kill(&kill(b, a));
+        players.handle_kill(&kill(a, b));
+        // Suicides are only a death
+        players.handle_kill(&kill(b, b));
+
+        assert_eq!(
+            players.session_kills[&a],
+            SessionKills {
+                kills: 3,
+                deaths: 1,
+                streak: 1,
+                longest_streak: 2,
+            }
+        );
+        assert_eq!(players.session_kills[&b].kills, 1);
+        assert_eq!(players.session_kills[&b].deaths, 4);
+
+        // a stops showing up while b is still around, so a is pruned
+        for _ in 0..2 {
+            players
+                .game_info
+                .get_mut(&b)
+                .expect("Player has game info")
+                .acknowledge();
+            players.refresh(0, 0);
+        }
+        assert!(!players.connected.contains(&a));
+        assert!(!players.session_kills.contains_key(&a));
+
+        // Kills from the console after leaving don't count for them
+        players.handle_kill(&kill(a, b));
+        assert!(!players.session_kills.contains_key(&a));
+        assert_eq!(players.session_kills[&b].deaths, 5);
+
+        // and they start from nothing when they join again
+        join(&mut players, a);
+        players.handle_kill(&kill(a, b));
+        assert_eq!(
+            players.session_kills[&a],
+            SessionKills {
+                kills: 1,
+                deaths: 0,
+                streak: 1,
+                longest_streak: 1,
+            }
+        );
+
+        // Not seen since the last refresh so they are pruned
+        players.refresh(0, 0);
+        players.refresh(0, 0);
+        assert!(players.session_kills.is_empty());
+    }
+
+    #[test]
+    pub fn presence_delta_pruned() {
+        let a = SteamID::from(76561197960265729);
+        let mut players = Players::new(PlayerRecords::default(), None);
+
+        join(&mut players, a);
+        assert_eq!(players.presence_delta().joined, [a]);
+        players.refresh(10, 0);
+
+        // Pruned before they were marked as disconnected
+        players.refresh(10, 0);
+        let delta = players.presence_delta();
+        assert!(delta.joined.is_empty());
+        assert_eq!(delta.left, [a]);
+        assert!(!players.connected.contains(&a));
+    }
+
+    #[test]
+    pub fn performance_outliers_tagged() {
+        let ids: Vec<SteamID> = (0..6)
+            .map(|i| SteamID::from(76561197960265729 + i))
+            .collect();
+        let mut players = Players::new(PlayerRecords::default(), None);
+        let scores = [(30, 2), (5, 5), (4, 6), (6, 4), (3, 7), (7, 3)];
+        for (&id, (kills, deaths)) in ids.iter().zip(scores) {
+            join(&mut players, id);
+            let gi = players.game_info.get_mut(&id).expect("Just joined");
+            gi.kills = kills;
+            gi.deaths = deaths;
+        }
+
+        players.tag_performance_outliers();
+        assert_eq!(players.performance_outliers(OUTLIER_STD_DEVS), [ids[0]]);
+        assert!(players.has_tag(ids[0], tags::PERFORMANCE_OUTLIER));
+        assert!(!players.has_tag(ids[5], tags::PERFORMANCE_OUTLIER));
+
+        // Evening out clears the tag
+        let gi = players.game_info.get_mut(&ids[0]).expect("Just joined");
+        gi.kills = 6;
+        gi.deaths = 5;
+        players.tag_performance_outliers();
+        assert!(players.performance_outliers(OUTLIER_STD_DEVS).is_empty());
+        assert!(!players.has_tag(ids[0], tags::PERFORMANCE_OUTLIER));
+    }
+
+    #[test]
+    pub fn performance_outliers_minimum_players() {
+        let ids: Vec<SteamID> = (0..6)
+            .map(|i| SteamID::from(76561197960265729 + i))
+            .collect();
+        let mut players = Players::new(PlayerRecords::default(), None);
+        for (&id, kills) in ids.iter().zip([50, 4, 5, 6, 5, 5]) {
+            join(&mut players, id);
+            let gi = players.game_info.get_mut(&id).expect("Just joined");
+            gi.kills = kills;
+            gi.deaths = 5;
+        }
+
+        // Just enough players for one to stand out
+        assert_eq!(players.performance_outliers(OUTLIER_STD_DEVS), [ids[0]]);
+
+        // One fewer and nobody can
+        players.connected.retain(|&s| s != ids[5]);
+        assert!(players.performance_outliers(OUTLIER_STD_DEVS).is_empty());
+    }
+
+    #[test]
+    pub fn history_is_capped() {
+        let ids: Vec<SteamID> = (0..5)
+            .map(|i| SteamID::from(76561197960265729 + i))
+            .collect();
+        let mut players = Players::new(PlayerRecords::default(), None);
+        players.set_max_history_len(3);
+        let mut record = PlayerRecord::default();
+        record.set_verdict(Verdict::Cheater);
+        players.records.insert(ids[0], record);
+
+        for &id in &ids {
+            join(&mut players, id);
+            players.refresh(0, 0);
+            players.refresh(0, 0);
+        }
+
+        assert_eq!(players.history, &ids[2..]);
+        // Only the history is pruned, not the records
+        assert!(players.records.contains_key(&ids[0]));
+    }
+
+    fn friends_with(players: &mut Players, steamid: SteamID, friends: &[u64]) {
+        let friends = friends
+            .iter()
+            .map(|&f| Friend {
+                steamid: SteamID::from(f),
+                friend_since: 0,
+            })
+            .collect();
+        players.friend_info.insert(
+            steamid,
+            FriendInfo {
+                public: Some(true),
+                friends,
+            },
+        );
+    }
+
+    #[test]
+    pub fn search_ranks_matches() {
+        let a = SteamID::from(76561197960265729);
+        let b = SteamID::from(76561197960265730);
+        let c = SteamID::from(76561197960265731);
+        let mut players = Players::new(PlayerRecords::default(), None);
+
+        let mut record = PlayerRecord::default();
+        record.add_previous_name("xX_Sniper_Xx");
+        players.records.insert(a, record);
+        let mut record = PlayerRecord::default();
+        record.add_previous_name("sniper");
+        players.records.insert(b, record);
+        let mut record = PlayerRecord::default();
+        record.add_previous_name("Sniper Main");
+        record.add_previous_name("Medic");
+        players.records.insert(c, record);
+
+        assert_eq!(players.search("SNIPER", 10), [b, a, c]);
+        assert_eq!(players.search("sniper", 1), [b]);
+        assert!(players.search("pyro", 10).is_empty());
+        assert_eq!(players.search("76561197960265731", 10), [c]);
+    }
+
+    #[test]
+    pub fn search_numeric_names() {
+        let a = SteamID::from(76561197960265729);
+        let b = SteamID::from(76561197960265730);
+        let mut players = Players::new(PlayerRecords::default(), None);
+
+        let mut record = PlayerRecord::default();
+        record.add_previous_name("1337");
+        players.records.insert(a, record);
+        let mut record = PlayerRecord::default();
+        record.add_previous_name("xX_1337_Xx");
+        players.records.insert(b, record);
+
+        // Numbers which aren't SteamIDs are only matched against names
+        assert_eq!(players.search("1337", 10), [a, b]);
+        assert_eq!(players.search("1", 10), [a, b]);
+        assert!(players.search("76561197960265727", 10).is_empty());
+    }
+
+    #[test]
+    pub fn possible_alts_shared_name() {
+        let a = SteamID::from(76561197960265729);
+        let b = SteamID::from(76561197960265730);
+        let c = SteamID::from(76561197960265731);
+        let mut players = Players::new(PlayerRecords::default(), None);
+
+        let mut record = PlayerRecord::default();
+        record.add_previous_name("Cheater McGee");
+        players.records.insert(a, record);
+        let mut record = PlayerRecord::default();
+        record.add_previous_name("someone else");
+        record.add_previous_name("cheater mcgee ");
+        players.records.insert(b, record);
+        let mut record = PlayerRecord::default();
+        record.add_previous_name("Unrelated");
+        players.records.insert(c, record);
+
+        assert_eq!(players.possible_alts(a), [b]);
+        assert_eq!(players.possible_alts(b), [a]);
+        assert!(players.possible_alts(c).is_empty());
+    }
+
+    #[test]
+    pub fn possible_alts_friend_cluster() {
+        let a = SteamID::from(76561197960265729);
+        let b = SteamID::from(76561197960265730);
+        let c = SteamID::from(76561197960265731);
+        let mut players = Players::new(PlayerRecords::default(), None);
+
+        friends_with(&mut players, a, &[1, 2, 3, 4]);
+        friends_with(&mut players, b, &[2, 3, 4, 5]);
+        // Only two friends in common isn't enough
+        friends_with(&mut players, c, &[1, 2, 6, 7]);
+
+        assert_eq!(players.possible_alts(a), [b]);
+        assert!(players.possible_alts(c).is_empty());
+    }
 }
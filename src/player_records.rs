@@ -1,9 +1,9 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Display,
     io::{ErrorKind, Write},
     ops::{Deref, DerefMut},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use anyhow::Context;
@@ -18,6 +18,9 @@ use crate::{
     settings::{merge_json_objects, ConfigFilesError, Settings},
 };
 
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
 // PlayerList
 
 #[derive(Serialize, Deserialize)]
@@ -25,6 +28,13 @@ pub struct PlayerRecords {
     #[serde(skip)]
     path: PathBuf,
     pub records: HashMap<SteamID, PlayerRecord>,
+    /// Players who have already been counted as seen since the app started
+    #[serde(skip)]
+    seen_this_session: HashSet<SteamID>,
+    /// Database the records are saved to instead of the playerlist file
+    #[cfg(feature = "sqlite")]
+    #[serde(skip)]
+    db: Option<sqlite::RecordsDb>,
 }
 
 impl PlayerRecords {
@@ -45,7 +55,8 @@ impl PlayerRecords {
             tracing::error!("Could not find a suitable location for the playerlist: {} \nPlease specify a file path manually with --playerlist otherwise information may not be saved.", e); 
         }).unwrap_or_else(|()| PathBuf::from("playerlist.json"));
 
-        match Self::load_from(playerlist_path) {
+        #[allow(unused_mut)]
+        let mut playerlist = match Self::load_from(playerlist_path) {
             Ok(playerlist) => playerlist,
             Err(ConfigFilesError::Json(path, e)) => {
                 tracing::error!("{} could not be loaded: {:?}", path, e);
@@ -67,7 +78,49 @@ impl PlayerRecords {
                 );
                 panic!("Failed to load playerlist")
             }
+        };
+
+        #[cfg(feature = "sqlite")]
+        if let Err(e) = playerlist.use_database() {
+            tracing::error!("Could not load playerlist database: {:?}", e);
+            tracing::error!(
+                "Please resolve any issues or remove the file, otherwise data may be lost."
+            );
+            panic!("Failed to load playerlist")
+        }
+
+        playerlist
+    }
+
+    /// Switches to saving records in a database next to the playerlist file.
+    /// On the first run the records already loaded from the playerlist file
+    /// are migrated into it. This only happens once, so records deleted from
+    /// the database don't come back from the old file.
+    ///
+    /// # Errors
+    /// If the database could not be opened, read, or written.
+    #[cfg(feature = "sqlite")]
+    pub fn use_database(&mut self) -> Result<(), ConfigFilesError> {
+        let mut db = sqlite::RecordsDb::open(&self.path.with_extension("db"))?;
+        let records = db.load()?;
+
+        // Databases from before the marker was added count as migrated
+        if db.migrated()? || !records.is_empty() {
+            self.records = records;
+        } else {
+            if !self.records.is_empty() {
+                tracing::info!(
+                    "Migrating {} records from {:?} to the playerlist database.",
+                    self.records.len(),
+                    self.path
+                );
+            }
+            db.save(&self.records)?;
         }
+        db.set_migrated()?;
+
+        self.db = Some(db);
+        Ok(())
     }
 
     /// Attempt to load the `PlayerRecords` from the provided file
@@ -108,6 +161,12 @@ impl PlayerRecords {
     /// If it failed to serialize or write back to the file.
     pub fn save(&mut self) -> Result<(), ConfigFilesError> {
         self.prune();
+
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &mut self.db {
+            return db.save(&self.records);
+        }
+
         let contents = serde_json::to_string(self).context("Failed to serialize playerlist.")?;
 
         let err_map = |e| ConfigFilesError::IO(self.path.to_string_lossy().into(), e);
@@ -141,11 +200,215 @@ impl PlayerRecords {
         Settings::locate_config_directory().map(|dir| dir.join("playerlist.json"))
     }
 
+    /// Marks a player as having been seen now. The number of times they've been
+    /// seen is only incremented once per session.
+    pub fn mark_seen(&mut self, steamid: SteamID) {
+        let Some(record) = self.records.get_mut(&steamid) else {
+            return;
+        };
+
+        record.mark_seen();
+        if self.seen_this_session.insert(steamid) {
+            record.times_seen = record.times_seen.saturating_add(1);
+        }
+    }
+
     pub fn update_name(&mut self, steamid: SteamID, name: &str) {
         if let Some(record) = self.records.get_mut(&steamid) {
             record.add_previous_name(name);
         }
     }
+
+    /// Merges a TF2 Bot Detector playerlist into the records. Players that
+    /// already have a different verdict are left as they are.
+    ///
+    /// Returns the number of players that were imported.
+    ///
+    /// # Errors
+    /// If the file could not be read or parsed.
+    pub fn import_tf2bd(&mut self, path: &Path) -> Result<usize, ConfigFilesError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
+        let list: Tf2bdPlayerList = serde_json::from_str(&contents)
+            .map_err(|e| ConfigFilesError::Json(path.to_string_lossy().into(), e))?;
+
+        Ok(self.merge_tf2bd(list))
+    }
+
+    /// Merges the players of a parsed TF2 Bot Detector playerlist into the
+    /// records, returning how many were merged.
+    fn merge_tf2bd(&mut self, list: Tf2bdPlayerList) -> usize {
+        let mut imported = 0;
+        for player in list.players {
+            let Some(steamid) = player.steamid() else {
+                tracing::warn!(
+                    "Skipping TF2BD player with invalid steamid {}",
+                    player.steamid
+                );
+                continue;
+            };
+            let Some(verdict) = player.verdict() else {
+                continue;
+            };
+
+            let record = self.records.entry(steamid).or_default();
+            if record.verdict != Verdict::Player && record.verdict != verdict {
+                tracing::info!(
+                    "Not importing {} as {verdict} from TF2BD, already marked as {}",
+                    u64::from(steamid),
+                    record.verdict
+                );
+                continue;
+            }
+
+            record.set_verdict(verdict);
+            if let Some(last_seen) = player.last_seen {
+                let newer = last_seen
+                    .time
+                    .and_then(|t| DateTime::from_timestamp(t, 0))
+                    .filter(|&t| record.last_seen.map_or(true, |l| l < t));
+
+                // The name TF2BD last saw is only the current one if it saw
+                // them more recently than we did
+                if let Some(name) = last_seen.player_name.as_deref().filter(|n| !n.is_empty()) {
+                    if newer.is_some() {
+                        record.add_previous_name(name);
+                    } else if !record.previous_names.iter().any(|n| n == name) {
+                        record.previous_names.push(name.to_owned());
+                    }
+                }
+                if newer.is_some() {
+                    record.last_seen = newer;
+                }
+            }
+
+            imported += 1;
+        }
+
+        imported
+    }
+
+    /// Writes the records out as a TF2 Bot Detector playerlist, leaving out
+    /// any that wouldn't mean anything to it.
+    ///
+    /// Returns the number of players that were exported.
+    ///
+    /// # Errors
+    /// If the file could not be serialized or written.
+    pub fn export_tf2bd(&self, path: &Path) -> Result<usize, ConfigFilesError> {
+        let players: Vec<_> = self
+            .records
+            .iter()
+            .filter_map(|(&steamid, record)| Tf2bdPlayer::from_record(steamid, record))
+            .collect();
+        let exported = players.len();
+
+        let list = Tf2bdPlayerList {
+            schema: Some(TF2BD_PLAYERLIST_SCHEMA.to_owned()),
+            players,
+        };
+        let contents =
+            serde_json::to_string_pretty(&list).context("Failed to serialize TF2BD playerlist.")?;
+
+        let err_map = |e| ConfigFilesError::IO(path.to_string_lossy().into(), e);
+        let mut file = AtomicWriteFile::open(path).map_err(err_map)?;
+        write!(file, "{contents}").map_err(err_map)?;
+        file.commit().map_err(err_map)?;
+
+        Ok(exported)
+    }
+}
+
+const TF2BD_PLAYERLIST_SCHEMA: &str =
+    "https://raw.githubusercontent.com/PazerOP/tf2_bot_detector/master/schemas/v3/playerlist.schema.json";
+
+/// A playerlist in the format used by TF2 Bot Detector
+#[derive(Serialize, Deserialize)]
+struct Tf2bdPlayerList {
+    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
+    schema: Option<String>,
+    #[serde(default)]
+    players: Vec<Tf2bdPlayer>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tf2bdPlayer {
+    /// Either a SteamID3 string or a SteamID64 number
+    steamid: serde_json::Value,
+    #[serde(default)]
+    attributes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_seen: Option<Tf2bdLastSeen>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    proof: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tf2bdLastSeen {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    player_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<i64>,
+}
+
+impl Tf2bdPlayer {
+    fn from_record(steamid: SteamID, record: &PlayerRecord) -> Option<Self> {
+        let attributes: Vec<String> = match record.verdict {
+            Verdict::Cheater | Verdict::Bot => vec!["cheater".into()],
+            Verdict::Suspicious => vec!["suspicious".into()],
+            Verdict::Player | Verdict::Trusted => Vec::new(),
+        };
+
+        // TF2BD has nowhere to put custom data, so it's kept as proof
+        let has_custom_data = record
+            .custom_data
+            .as_object()
+            .map_or(!record.custom_data.is_null(), |m| !m.is_empty());
+        if attributes.is_empty() && !has_custom_data {
+            return None;
+        }
+        let proof = if has_custom_data {
+            vec![record.custom_data.to_string()]
+        } else {
+            Vec::new()
+        };
+
+        let player_name = record.previous_names.first().cloned();
+        let last_seen =
+            (player_name.is_some() || record.last_seen.is_some()).then(|| Tf2bdLastSeen {
+                player_name,
+                time: record.last_seen.map(|t| t.timestamp()),
+            });
+
+        Some(Self {
+            steamid: steamid.steam3().into(),
+            attributes,
+            last_seen,
+            proof,
+        })
+    }
+
+    fn steamid(&self) -> Option<SteamID> {
+        match &self.steamid {
+            serde_json::Value::Number(n) => n.as_u64().map(SteamID::from),
+            serde_json::Value::String(s) => SteamID::from_steam3(s)
+                .ok()
+                .or_else(|| s.parse::<u64>().ok().map(SteamID::from)),
+            _ => None,
+        }
+    }
+
+    /// The most severe verdict out of the player's attributes
+    fn verdict(&self) -> Option<Verdict> {
+        let has = |a: &str| self.attributes.iter().any(|b| b.eq_ignore_ascii_case(a));
+        if has("cheater") || has("exploiter") {
+            Some(Verdict::Cheater)
+        } else if has("suspicious") {
+            Some(Verdict::Suspicious)
+        } else {
+            None
+        }
+    }
 }
 
 impl Default for PlayerRecords {
@@ -157,6 +420,9 @@ impl Default for PlayerRecords {
         Self {
             path,
             records: HashMap::new(),
+            seen_this_session: HashSet::new(),
+            #[cfg(feature = "sqlite")]
+            db: None,
         }
     }
 }
@@ -178,13 +444,18 @@ impl DerefMut for PlayerRecords {
 // PlayerRecord
 
 /// A Record of a player stored in the persistent personal playerlist
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(default)]
 pub struct PlayerRecord {
     custom_data: serde_json::Value,
     verdict: Verdict,
     previous_names: Vec<String>,
     last_seen: Option<DateTime<Utc>>,
+    /// How many sessions the player has been seen in
+    times_seen: u32,
+    /// Player has been acknowledged by the user and shouldn't raise any alerts,
+    /// regardless of their verdict.
+    muted: bool,
     /// Time of last manual change made by the user.
     modified: DateTime<Utc>,
     created: DateTime<Utc>,
@@ -202,7 +473,7 @@ impl PlayerRecord {
                     .is_some_and(|m| m.values().all(value_is_empty))
         }
 
-        self.verdict == Verdict::Player && value_is_empty(&self.custom_data)
+        self.verdict == Verdict::Player && !self.muted && value_is_empty(&self.custom_data)
     }
 }
 
@@ -213,6 +484,8 @@ impl Default for PlayerRecord {
             verdict: Verdict::default(),
             previous_names: Vec::new(),
             last_seen: None,
+            times_seen: 0,
+            muted: false,
             modified: default_date(),
             created: default_date(),
         }
@@ -244,6 +517,15 @@ impl PlayerRecord {
         self
     }
     #[must_use]
+    pub const fn muted(&self) -> bool {
+        self.muted
+    }
+    pub fn set_muted(&mut self, muted: bool) -> &mut Self {
+        self.muted = muted;
+        self.modified = Utc::now();
+        self
+    }
+    #[must_use]
     pub fn previous_names(&self) -> &[String] {
         &self.previous_names
     }
@@ -270,6 +552,11 @@ impl PlayerRecord {
         self.last_seen
     }
 
+    #[must_use]
+    pub const fn times_seen(&self) -> u32 {
+        self.times_seen
+    }
+
     pub fn mark_seen(&mut self) {
         self.last_seen = Some(Utc::now());
     }
@@ -306,3 +593,97 @@ impl Default for Verdict {
         Self::Player
     }
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::DateTime;
+    use steamid_ng::SteamID;
+
+    use super::{PlayerRecords, Tf2bdPlayerList, Verdict};
+
+    #[test]
+    pub fn merge_tf2bd() {
+        let cheater = SteamID::from(76_561_198_000_000_001);
+        let trusted = SteamID::from(76_561_198_000_000_002);
+        let stale = SteamID::from(76_561_198_000_000_003);
+
+        let mut records = PlayerRecords::default();
+        records
+            .entry(trusted)
+            .or_default()
+            .set_verdict(Verdict::Trusted);
+        let record = records.entry(stale).or_default();
+        record.set_verdict(Verdict::Suspicious);
+        record.add_previous_name("Current");
+        record.last_seen = DateTime::from_timestamp(2_000_000_000, 0);
+
+        let list: Tf2bdPlayerList = serde_json::from_str(
+            r#"{
+                "players": [
+                    {
+                        "steamid": "[U:1:39734273]",
+                        "attributes": ["cheater"],
+                        "last_seen": { "player_name": "Newest", "time": 1700000000 }
+                    },
+                    { "steamid": 76561198000000002, "attributes": ["cheater"] },
+                    {
+                        "steamid": "76561198000000003",
+                        "attributes": ["suspicious"],
+                        "last_seen": { "player_name": "Older", "time": 1600000000 }
+                    },
+                    { "steamid": "not a steamid", "attributes": ["cheater"] },
+                    { "steamid": 76561198000000004, "attributes": ["racist"] }
+                ]
+            }"#,
+        )
+        .expect("Valid TF2BD playerlist");
+
+        // `[U:1:39734273]` is 76561198000000001
+        assert_eq!(records.merge_tf2bd(list), 2);
+
+        let record = &records[&cheater];
+        assert_eq!(record.verdict(), Verdict::Cheater);
+        assert_eq!(record.previous_names(), ["Newest"]);
+        assert_eq!(
+            record.last_seen(),
+            DateTime::from_timestamp(1_700_000_000, 0)
+        );
+
+        // Already marked as something else, so not touched
+        assert_eq!(records[&trusted].verdict(), Verdict::Trusted);
+
+        // Seen more recently here, so the imported name goes to the back
+        let record = &records[&stale];
+        assert_eq!(record.previous_names(), ["Current", "Older"]);
+        assert_eq!(
+            record.last_seen(),
+            DateTime::from_timestamp(2_000_000_000, 0)
+        );
+
+        assert!(!records.contains_key(&SteamID::from(76_561_198_000_000_004)));
+    }
+
+    #[test]
+    pub fn merge_tf2bd_newer_name() {
+        let steamid = SteamID::from(76_561_198_000_000_001);
+
+        let mut records = PlayerRecords::default();
+        let record = records.entry(steamid).or_default();
+        record.add_previous_name("Old").add_previous_name("Current");
+        record.last_seen = DateTime::from_timestamp(1_600_000_000, 0);
+
+        let list: Tf2bdPlayerList = serde_json::from_str(
+            r#"{
+                "players": [{
+                    "steamid": 76561198000000001,
+                    "attributes": ["cheater"],
+                    "last_seen": { "player_name": "Old", "time": 1700000000 }
+                }]
+            }"#,
+        )
+        .expect("Valid TF2BD playerlist");
+
+        assert_eq!(records.merge_tf2bd(list), 1);
+        assert_eq!(records[&steamid].previous_names(), ["Old", "Current"]);
+    }
+}
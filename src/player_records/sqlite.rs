@@ -0,0 +1,279 @@
+use std::{collections::HashMap, path::Path};
+
+use rusqlite::{params, types::Type, Connection};
+use steamid_ng::SteamID;
+
+use super::{PlayerRecord, Verdict};
+use crate::settings::ConfigFilesError;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS records (
+    steamid INTEGER PRIMARY KEY,
+    verdict TEXT NOT NULL,
+    custom_data TEXT NOT NULL,
+    last_seen TEXT,
+    times_seen INTEGER NOT NULL,
+    muted INTEGER NOT NULL,
+    modified TEXT NOT NULL,
+    created TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS previous_names (
+    steamid INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY (steamid, position)
+);
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+/// Set in `meta` once the playerlist file has been migrated into the database
+const MIGRATED_KEY: &str = "migrated";
+
+/// Player records stored in a SQLite database. Only records that have changed
+/// since they were last saved are written back.
+pub struct RecordsDb {
+    path: String,
+    conn: Connection,
+    /// The records as they were last read or written
+    saved: HashMap<SteamID, PlayerRecord>,
+}
+
+impl RecordsDb {
+    /// Opens the database at the provided path, creating it if it doesn't
+    /// exist yet.
+    ///
+    /// # Errors
+    /// If the database could not be opened or the tables could not be created.
+    pub fn open(path: &Path) -> Result<Self, ConfigFilesError> {
+        let path = path.to_string_lossy().to_string();
+        let conn =
+            Connection::open(&path).map_err(|e| ConfigFilesError::Sqlite(path.clone(), e))?;
+        Self::with_connection(path, conn)
+    }
+
+    fn with_connection(path: String, conn: Connection) -> Result<Self, ConfigFilesError> {
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| ConfigFilesError::Sqlite(path.clone(), e))?;
+
+        Ok(Self {
+            path,
+            conn,
+            saved: HashMap::new(),
+        })
+    }
+
+    /// Whether the playerlist file has already been migrated into the
+    /// database, so it isn't migrated again if the database is emptied.
+    ///
+    /// # Errors
+    /// If the database could not be read.
+    pub fn migrated(&self) -> Result<bool, ConfigFilesError> {
+        self.conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM meta WHERE key = ?1)",
+                [MIGRATED_KEY],
+                |row| row.get(0),
+            )
+            .map_err(|e| ConfigFilesError::Sqlite(self.path.clone(), e))
+    }
+
+    /// # Errors
+    /// If the database could not be written.
+    pub fn set_migrated(&self) -> Result<(), ConfigFilesError> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, '1')",
+                [MIGRATED_KEY],
+            )
+            .map(|_| ())
+            .map_err(|e| ConfigFilesError::Sqlite(self.path.clone(), e))
+    }
+
+    /// # Errors
+    /// If the records could not be read.
+    pub fn load(&mut self) -> Result<HashMap<SteamID, PlayerRecord>, ConfigFilesError> {
+        let records = self
+            .read_records()
+            .map_err(|e| ConfigFilesError::Sqlite(self.path.clone(), e))?;
+        self.saved.clone_from(&records);
+        Ok(records)
+    }
+
+    /// Writes any records that have changed since the last save, and removes
+    /// any that no longer exist.
+    ///
+    /// # Errors
+    /// If the records could not be written, in which case none of them are.
+    pub fn save(
+        &mut self,
+        records: &HashMap<SteamID, PlayerRecord>,
+    ) -> Result<(), ConfigFilesError> {
+        self.write_changes(records)
+            .map_err(|e| ConfigFilesError::Sqlite(self.path.clone(), e))?;
+        self.saved.clone_from(records);
+        Ok(())
+    }
+
+    fn read_records(&self) -> rusqlite::Result<HashMap<SteamID, PlayerRecord>> {
+        let mut records = HashMap::new();
+
+        let mut stmt = self.conn.prepare(
+            "SELECT steamid, verdict, custom_data, last_seen, times_seen, muted, modified, created
+            FROM records",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let verdict: String = row.get(1)?;
+            let verdict: Verdict = serde_json::from_value(serde_json::Value::String(verdict))
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(1, Type::Text, e.into()))?;
+
+            Ok((
+                from_sql_id(row.get(0)?),
+                PlayerRecord {
+                    custom_data: row.get(2)?,
+                    verdict,
+                    previous_names: Vec::new(),
+                    last_seen: row.get(3)?,
+                    times_seen: row.get(4)?,
+                    muted: row.get(5)?,
+                    modified: row.get(6)?,
+                    created: row.get(7)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (steamid, record) = row?;
+            records.insert(steamid, record);
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT steamid, name FROM previous_names ORDER BY steamid, position")?;
+        let rows = stmt.query_map([], |row| Ok((from_sql_id(row.get(0)?), row.get(1)?)))?;
+        for row in rows {
+            let (steamid, name) = row?;
+            if let Some(record) = records.get_mut(&steamid) {
+                record.previous_names.push(name);
+            }
+        }
+
+        Ok(records)
+    }
+
+    fn write_changes(&mut self, records: &HashMap<SteamID, PlayerRecord>) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut upsert = tx.prepare(
+                "INSERT INTO records
+                (steamid, verdict, custom_data, last_seen, times_seen, muted, modified, created)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                ON CONFLICT(steamid) DO UPDATE SET
+                verdict = excluded.verdict,
+                custom_data = excluded.custom_data,
+                last_seen = excluded.last_seen,
+                times_seen = excluded.times_seen,
+                muted = excluded.muted,
+                modified = excluded.modified,
+                created = excluded.created",
+            )?;
+            let mut delete = tx.prepare("DELETE FROM records WHERE steamid = ?1")?;
+            let mut clear_names = tx.prepare("DELETE FROM previous_names WHERE steamid = ?1")?;
+            let mut insert_name = tx.prepare(
+                "INSERT INTO previous_names (steamid, position, name) VALUES (?1, ?2, ?3)",
+            )?;
+
+            for (&steamid, record) in records {
+                if self.saved.get(&steamid) == Some(record) {
+                    continue;
+                }
+
+                let id = to_sql_id(steamid);
+                upsert.execute(params![
+                    id,
+                    record.verdict.to_string(),
+                    record.custom_data,
+                    record.last_seen,
+                    record.times_seen,
+                    record.muted,
+                    record.modified,
+                    record.created,
+                ])?;
+                clear_names.execute([id])?;
+                for (position, name) in record.previous_names.iter().enumerate() {
+                    insert_name.execute(params![id, position, name])?;
+                }
+            }
+
+            for &steamid in self.saved.keys().filter(|s| !records.contains_key(s)) {
+                let id = to_sql_id(steamid);
+                delete.execute([id])?;
+                clear_names.execute([id])?;
+            }
+        }
+        tx.commit()
+    }
+}
+
+// SQLite only has signed integers, but the conversion round trips exactly.
+#[allow(clippy::cast_possible_wrap)]
+fn to_sql_id(steamid: SteamID) -> i64 {
+    u64::from(steamid) as i64
+}
+
+#[allow(clippy::cast_sign_loss)]
+fn from_sql_id(id: i64) -> SteamID {
+    SteamID::from(id as u64)
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unreadable_literal)]
+
+    use std::collections::HashMap;
+
+    use rusqlite::Connection;
+    use steamid_ng::SteamID;
+
+    use super::RecordsDb;
+    use crate::player_records::{PlayerRecord, Verdict};
+
+    fn in_memory() -> RecordsDb {
+        let conn = Connection::open_in_memory().expect("Couldn't open database");
+        RecordsDb::with_connection(":memory:".into(), conn).expect("Couldn't create tables")
+    }
+
+    #[test]
+    pub fn save_load_delete() {
+        let a = SteamID::from(76561197960265729);
+        let b = SteamID::from(76561197960265730);
+        let mut db = in_memory();
+
+        let mut record = PlayerRecord::default();
+        record
+            .set_verdict(Verdict::Cheater)
+            .add_previous_name("first")
+            .add_previous_name("second");
+        let mut records = HashMap::new();
+        records.insert(a, record);
+        records.insert(b, PlayerRecord::default());
+        db.save(&records).expect("Couldn't save records");
+        assert_eq!(db.load().expect("Couldn't load records"), records);
+
+        records.remove(&a);
+        db.save(&records).expect("Couldn't save records");
+        let loaded = db.load().expect("Couldn't load records");
+        assert_eq!(loaded, records);
+        assert!(!loaded.contains_key(&a));
+    }
+
+    #[test]
+    pub fn migration_marker() {
+        let db = in_memory();
+        assert!(!db.migrated().expect("Couldn't read marker"));
+        db.set_migrated().expect("Couldn't set marker");
+        db.set_migrated().expect("Couldn't set marker");
+        assert!(db.migrated().expect("Couldn't read marker"));
+    }
+}
@@ -1,7 +1,11 @@
+use std::collections::HashMap;
+
 use serde::Serialize;
+use steamid_ng::SteamID;
 
 use crate::{
     console::ConsoleOutput,
+    demo::DemoEvent,
     io::regexes::{self, ChatMessage, PlayerKill},
 };
 
@@ -14,8 +18,11 @@ pub struct Server {
     max_players: Option<u32>,
     num_players: Option<u32>,
     gamemode: Option<Gamemode>,
+    mvm: Option<MvmState>,
     chat_history: Vec<ChatMessage>,
     kill_history: Vec<PlayerKill>,
+    /// Players connected according to the demo being recorded, by userid
+    demo_players: HashMap<u16, SteamID>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -26,10 +33,45 @@ pub struct Gamemode {
     pub vanilla: bool,
 }
 
+impl Gamemode {
+    /// Guesses the gamemode from the map's prefix (e.g. `mvm_` or `koth_`).
+    /// Whether the server is a matchmaking or vanilla server can't be
+    /// determined from the map, so they are assumed to be false.
+    #[must_use]
+    pub fn from_map(map: &str) -> Self {
+        let game_type = map
+            .split_once('_')
+            .map_or("unknown", |(prefix, _)| prefix)
+            .to_lowercase();
+
+        Self {
+            matchmaking: false,
+            game_type,
+            vanilla: false,
+        }
+    }
+
+    #[must_use]
+    pub fn is_mvm(&self) -> bool {
+        self.game_type == "mvm"
+    }
+}
+
+/// Progress through the current Mann vs. Machine mission. Everything is
+/// optional since it is only known once the relevant events have been seen.
+#[derive(Debug, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MvmState {
+    pub wave: Option<u32>,
+    pub max_waves: Option<u32>,
+    /// Total credits picked up by the team during this mission
+    pub currency_collected: u32,
+}
+
 #[allow(dead_code)]
 impl Server {
     #[must_use]
-    pub const fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             map: None,
             ip: None,
@@ -38,9 +80,11 @@ impl Server {
             num_players: None,
 
             gamemode: None,
+            mvm: None,
 
             chat_history: Vec::new(),
             kill_history: Vec::new(),
+            demo_players: HashMap::new(),
         }
     }
 
@@ -76,6 +120,11 @@ impl Server {
         self.gamemode.as_ref()
     }
 
+    #[must_use]
+    pub const fn mvm(&self) -> Option<&MvmState> {
+        self.mvm.as_ref()
+    }
+
     #[must_use]
     pub fn chat_history(&self) -> &[ChatMessage] {
         &self.chat_history
@@ -85,6 +134,12 @@ impl Server {
     pub fn kill_history(&self) -> &[PlayerKill] {
         &self.kill_history
     }
+
+    /// The player connected with the given userid, according to the demo
+    #[must_use]
+    pub fn demo_player(&self, userid: u16) -> Option<SteamID> {
+        self.demo_players.get(&userid).copied()
+    }
 }
 
 impl Default for Server {
@@ -102,7 +157,7 @@ impl Server {
     ///   server.
     pub fn handle_console_output(&mut self, response: ConsoleOutput) {
         use ConsoleOutput::{
-            Chat, DemoStop, Hostname, Kill, Map, PlayerCount, ServerIP, Status, G15,
+            Chat, DemoStop, Hostname, Kill, Map, PlayerCount, SelfKicked, ServerIP, Status, G15,
         };
         match response {
             Chat(chat) => self.handle_chat(chat),
@@ -114,13 +169,65 @@ impl Server {
                 self.ip = Some(ip);
             }
             Map(regexes::Map(map)) => {
+                if self.map.as_ref() != Some(&map) {
+                    let gamemode = Gamemode::from_map(&map);
+                    self.mvm = gamemode.is_mvm().then(MvmState::default);
+                    self.gamemode = Some(gamemode);
+                    self.demo_players.clear();
+                }
                 self.map = Some(map);
             }
             PlayerCount(playercount) => {
                 self.max_players = Some(playercount.max);
                 self.num_players = Some(playercount.players);
             }
-            G15(_) | Status(_) | DemoStop(_) => {}
+            G15(_) | Status(_) | DemoStop(_) | SelfKicked(_) => {}
+        }
+    }
+
+    /// Handles game events extracted from the demo being recorded
+    pub fn handle_demo_event(&mut self, event: &DemoEvent) {
+        match event {
+            &DemoEvent::MvmWaveStarted { wave, max_waves } => {
+                let mvm = self.mvm.get_or_insert_with(MvmState::default);
+                mvm.wave = Some(wave);
+                mvm.max_waves = Some(max_waves);
+            }
+            &DemoEvent::MvmCurrencyCollected(amount) => {
+                let mvm = self.mvm.get_or_insert_with(MvmState::default);
+                mvm.currency_collected = mvm.currency_collected.saturating_add(amount);
+            }
+            &DemoEvent::PlayerConnected {
+                steamid: Some(steamid),
+                userid,
+                ..
+            } => {
+                // The same userid showing up again is a reconnect (or the
+                // server resending its state), not a new player
+                match self.demo_players.insert(userid, steamid) {
+                    Some(previous) if previous == steamid => {
+                        tracing::debug!("Player {} reconnected.", u64::from(steamid));
+                    }
+                    Some(previous) => {
+                        tracing::debug!(
+                            "Userid {userid} was reused, {} replaced {}.",
+                            u64::from(steamid),
+                            u64::from(previous)
+                        );
+                    }
+                    None => {}
+                }
+            }
+            &DemoEvent::PlayerDisconnected { userid, .. } => {
+                self.demo_players.remove(&userid);
+            }
+            DemoEvent::VoteStarted(_)
+            | DemoEvent::VoteOptions(_)
+            | DemoEvent::PlayerConnected { steamid: None, .. }
+            | DemoEvent::VoteCast(..)
+            | DemoEvent::RoundStart { .. }
+            | DemoEvent::RoundEnd { .. }
+            | DemoEvent::LatestTick => {}
         }
     }
 
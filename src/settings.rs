@@ -1,21 +1,32 @@
 use std::{
+    collections::BTreeMap,
+    ffi::OsString,
     fmt::Display,
     io::{self, ErrorKind, Write},
+    net::{IpAddr, Ipv4Addr},
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{self, Receiver},
+    time::Duration,
 };
 
 use anyhow::{anyhow, Context, Result};
 use atomic_write_file::AtomicWriteFile;
 use chrono::{DateTime, NaiveDate, Utc};
 use directories_next::ProjectDirs;
+use event_loop::{Is, MessageSource};
 use include_dir::Dir;
 use keyvalues_parser::Vdf;
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use steamid_ng::SteamID;
 use thiserror::Error;
 
-use crate::{args::Args, gamefinder, player_records::Verdict, web::UISource};
+use crate::{
+    args::Args, command_manager::KickReason, events::SettingsReloaded, gamefinder,
+    player_records::Verdict, web::UISource,
+};
 
 // if feature "include-ui" is enabled, bundle the web UI files
 #[cfg(feature = "include-ui")]
@@ -47,9 +58,28 @@ pub enum ConfigFilesError {
     Json(String, serde_json::Error),
     #[error("Pot error with file {0}, {1:?}")]
     Pot(String, pot::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("Database error with file {0}, {1:?}")]
+    Sqlite(String, rusqlite::Error),
     #[error("{0:?}")]
     Other(#[from] anyhow::Error),
 }
+/// Where the app's own logs are written
+pub const LOG_FILE_PATH: &str = "./macclient.log";
+/// The shortest a Steam profile is cached for, so a bad value can't have us
+/// re-fetching every profile on every lookup tick
+const MIN_STEAM_CACHE_TTL_MINUTES: u64 = 5;
+/// Where the bytes that are only written once a demo recording has finished
+/// (e.g. its duration) start, within the demo header
+pub const DEMO_LATE_BYTES_OFFSET: u64 = 0x420;
+/// How many late bytes there are
+pub const DEMO_LATE_BYTES_LEN: usize = 16;
+/// The late bytes are only known to be written once something past the first
+/// 8 is, so any fewer can never be detected
+const MIN_DEMO_LATE_BYTES_LEN: usize = 9;
+/// Keeps a bad length from reading (and allocating) far past the header
+const MAX_DEMO_LATE_BYTES_LEN: usize = 0x400;
+
 #[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
 pub enum FriendsAPIUsage {
     None,
@@ -73,6 +103,160 @@ impl FriendsAPIUsage {
     }
 }
 
+/// Which players should be reported to the masterbase
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum ReportPolicy {
+    /// Only players the user has personally marked as a cheater or bot
+    ManualOnly,
+    /// Also players who have been flagged by enough heuristics, see
+    /// [`crate::confidence`]. They're reported as cheaters.
+    IncludeAutomatic,
+}
+
+impl Default for ReportPolicy {
+    fn default() -> Self {
+        Self::ManualOnly
+    }
+}
+
+impl Display for ReportPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// What to do with a demo that TF2 was already recording when the client was
+/// launched. Changes only take effect after a restart.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum PreexistingDemoMode {
+    /// Ignore the demo until a new one is recorded. Since part of the demo has
+    /// been missed it couldn't be uploaded completely anyway.
+    Skip,
+    /// Only read data written after the client was launched. The demo's
+    /// header will have been missed, and nothing after it can be parsed
+    /// without it, so this mode extracts no events and the demo isn't
+    /// uploaded.
+    ResumeFromOffset,
+    /// Read the demo from the beginning as if it had just been created.
+    ReadFromStart,
+}
+
+impl Default for PreexistingDemoMode {
+    fn default() -> Self {
+        Self::Skip
+    }
+}
+
+impl Display for PreexistingDemoMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// How the connected players are ordered when sent to the UI
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum PlayerSort {
+    /// The order players joined the server in
+    Joined,
+    /// Grouped by team, then alphabetically by name
+    TeamThenName,
+    /// The most suspicious players first, then alphabetically by name
+    Verdict,
+}
+
+impl Default for PlayerSort {
+    fn default() -> Self {
+        Self::TeamThenName
+    }
+}
+
+impl Display for PlayerSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Groups of game events that can be extracted from demos. Turning off the
+/// ones you don't need saves a little work for each packet, but the packets
+/// still have to be parsed either way, so the savings are small. Vote casts
+/// are the most expensive as the voter has to be looked up among the players,
+/// the others are just copied out of the packet. Use `minimal_demo_parsing`
+/// to skip parsing demos altogether.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum DemoEventKind {
+    /// Vote options and vote casts, used to follow votekicks
+    Votes,
+    /// Mann vs. Machine waves and currency pickups
+    MannVsMachine,
+    /// Rounds starting and being won
+    Rounds,
+    /// Players connecting to and disconnecting from the server
+    Connections,
+}
+
+impl Display for DemoEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Which name to prefer when a player has both an in-game name and a Steam
+/// profile name.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum NameSource {
+    /// The name the player is using in the current game, which may be spoofed
+    Game,
+    /// The persona name on the player's Steam profile
+    Steam,
+}
+
+impl Default for NameSource {
+    fn default() -> Self {
+        Self::Game
+    }
+}
+
+impl Display for NameSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// How a verdict or tag should be shown in the UI. Anything left unset falls
+/// back to the UI's own default. The backend only stores these.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct DisplayStyle {
+    pub label: Option<String>,
+    /// A hex color, e.g. `#f00` or `#ff0000aa`
+    pub color: Option<String>,
+}
+
+/// Checks that `color` is a hex color in the form `#rgb`, `#rgba`, `#rrggbb`
+/// or `#rrggbbaa`
+#[must_use]
+pub fn is_valid_color(color: &str) -> bool {
+    color.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
+/// Checks all the colors in a set of display styles, keyed by verdict or tag
+/// name
+///
+/// # Errors
+/// With the key and value of the first invalid color found
+pub fn validate_display_styles(styles: &BTreeMap<String, DisplayStyle>) -> Result<(), String> {
+    for (key, style) in styles {
+        if let Some(color) = &style.color {
+            if !is_valid_color(color) {
+                return Err(format!("Invalid color for {key}: {color:?}"));
+            }
+        }
+    }
+    Ok(())
+}
+
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(default)]
@@ -91,10 +275,77 @@ pub struct Settings {
     autolaunch_ui: bool,
     friends_api_usage: FriendsAPIUsage,
     webui_port: u16,
+    /// The address to host the web-ui and API on
+    webui_bind_address: IpAddr,
+    /// If set, requests to the API must provide this as a bearer token
+    webui_token: Option<String>,
     rcon_port: u16,
     external: serde_json::Value,
     autokick_bots: bool,
+    /// The reason given when calling a vote to kick a bot
+    autokick_reason: KickReason,
+    /// Seconds to wait after calling a kick vote before calling another, since
+    /// only one vote can run at a time. 0 calls a vote on every bot at once.
+    autokick_vote_interval_secs: u64,
     tos_agreement_date: Option<DateTime<Utc>>,
+    cache_avatars: bool,
+    name_source: NameSource,
+    /// Seconds a player can be missing from `status` before they are shown as
+    /// disconnected
+    disconnect_grace_period: u64,
+    /// Seconds a player can be missing from `status` before they are removed
+    /// from the server and moved into history. Should be longer than
+    /// `disconnect_grace_period`, otherwise players will be removed without
+    /// ever being shown as disconnected.
+    removal_grace_period: u64,
+    /// Look up the Steam profiles of everyone with a verdict on startup
+    prefetch_record_profiles: bool,
+    /// Where to record kicks and reports, defaults to the config directory.
+    /// Only set from the config file, so the API can't point it elsewhere.
+    audit_log_path: Option<PathBuf>,
+    report_policy: ReportPolicy,
+    player_sort: PlayerSort,
+    /// How many players from this session are kept in the history
+    max_history_len: usize,
+    /// Console commands to run whenever a new server is joined
+    autoexec_commands: Vec<String>,
+    /// The most Steam API calls to make per minute, or 0 for no limit
+    steam_api_calls_per_minute: u32,
+    preexisting_demo_mode: PreexistingDemoMode,
+    /// Bans at most this many days old are reported as recent, or 0 to disable
+    recent_ban_days: u32,
+    /// Whether players with recent bans are automatically marked as suspicious
+    mark_recently_banned: bool,
+    /// How each verdict or custom tag is labelled and colored in the UI
+    display_styles: BTreeMap<String, DisplayStyle>,
+    /// Demo events which won't be extracted, all are extracted by default
+    disabled_demo_events: Vec<DemoEventKind>,
+    /// If TF2 isn't running at startup, wait for it to launch before talking
+    /// to it over RCON
+    wait_for_tf2: bool,
+    /// Where the bytes only written at the end of a demo recording are, in
+    /// case the demo format changes before the client is updated
+    demo_late_bytes_offset: Option<u64>,
+    demo_late_bytes_len: Option<usize>,
+    /// How many more times to try opening a masterbase session for a demo
+    /// if the first attempt fails
+    masterbase_session_retries: u32,
+    /// Seconds to wait before the first retry, doubling after each one
+    masterbase_retry_delay: u64,
+    /// The shortest time in milliseconds between RCON commands, so TF2
+    /// doesn't drop any of their output
+    rcon_command_interval_ms: u64,
+    /// Let kicks and commands from the user go ahead of the regular refresh
+    /// commands when they're waiting to be sent
+    prioritize_rcon_commands: bool,
+    /// How many minutes a player's Steam profile is kept before it's fetched
+    /// again
+    steam_cache_ttl_minutes: u64,
+    /// Read console.log from the start when launching instead of only
+    /// picking up new lines
+    replay_console_log: bool,
+    /// Friends who are automatically marked as trusted when they join
+    trusted_friends: Vec<SteamID>,
 
     #[serde(skip)]
     override_tf2_dir: Option<PathBuf>,
@@ -105,10 +356,14 @@ pub struct Settings {
     #[serde(skip)]
     override_webui_port: Option<u16>,
     #[serde(skip)]
+    override_webui_bind_address: Option<IpAddr>,
+    #[serde(skip)]
     override_steam_user: Option<SteamID>,
     #[serde(skip)]
     override_rcon_port: Option<u16>,
     #[serde(skip)]
+    override_replay_console_log: Option<bool>,
+    #[serde(skip)]
     override_masterbase_api_key: Option<String>,
     #[serde(skip)]
     override_masterbase_host: Option<String>,
@@ -120,6 +375,8 @@ pub struct Settings {
     minimal_demo_parsing: bool,
     #[serde(skip)]
     masterbase_http: bool,
+    #[serde(skip)]
+    debug_endpoints: bool,
 }
 
 #[allow(dead_code)]
@@ -154,6 +411,7 @@ impl Settings {
                 let mut settings = Self::default();
                 settings.set_config_path(path.into());
                 settings.apply_args(args);
+                settings.apply_env();
                 settings
             }
             Err(e) => {
@@ -210,6 +468,57 @@ impl Settings {
     /// If the config file could not be located (usually because no valid home
     /// directory could be found)
     pub fn load_from(path: PathBuf, args: &Args) -> Result<Self, ConfigFilesError> {
+        let mut settings = Self::read_file(path)?;
+
+        tracing::debug!("Successfully loaded settings.");
+        settings.apply_args(args);
+        settings.apply_env();
+        Ok(settings)
+    }
+
+    /// Reads the config file again after it was changed on disk. Anything that
+    /// comes from the command line or was found at startup is kept from the
+    /// current settings.
+    ///
+    /// # Errors
+    /// If no config file is set, or it could not be read or parsed.
+    pub fn reload(&self) -> Result<Self, ConfigFilesError> {
+        let path = self
+            .config_path
+            .clone()
+            .ok_or_else(|| anyhow!("No config file set."))?;
+        let mut settings = Self::read_file(path)?;
+
+        settings.steam_user = self.steam_user;
+        settings.tf2_directory.clone_from(&self.tf2_directory);
+        settings.override_tf2_dir.clone_from(&self.override_tf2_dir);
+        settings
+            .override_rcon_password
+            .clone_from(&self.override_rcon_password);
+        settings
+            .override_steam_api_key
+            .clone_from(&self.override_steam_api_key);
+        settings.override_webui_port = self.override_webui_port;
+        settings.override_webui_bind_address = self.override_webui_bind_address;
+        settings.override_steam_user = self.override_steam_user;
+        settings.override_rcon_port = self.override_rcon_port;
+        settings.override_replay_console_log = self.override_replay_console_log;
+        settings
+            .override_masterbase_api_key
+            .clone_from(&self.override_masterbase_api_key);
+        settings
+            .override_masterbase_host
+            .clone_from(&self.override_masterbase_host);
+        settings.web_ui_source = self.web_ui_source.clone();
+        settings.minimal_demo_parsing = self.minimal_demo_parsing;
+        settings.masterbase_http = self.masterbase_http;
+        settings.debug_endpoints = self.debug_endpoints;
+
+        Ok(settings)
+    }
+
+    /// Reads and parses a config file without applying any overrides
+    fn read_file(path: PathBuf) -> Result<Self, ConfigFilesError> {
         // Read config.yaml file if it exists, otherwise try to create a default file.
         let contents = std::fs::read_to_string(&path)
             .map_err(|e| ConfigFilesError::IO(path.to_string_lossy().into(), e))?;
@@ -218,8 +527,40 @@ impl Settings {
 
         settings.config_path = Some(path);
 
-        tracing::debug!("Successfully loaded settings.");
-        settings.apply_args(args);
+        if settings.steam_cache_ttl_minutes < MIN_STEAM_CACHE_TTL_MINUTES {
+            tracing::warn!(
+                "steam_cache_ttl_minutes is too low, using {MIN_STEAM_CACHE_TTL_MINUTES} minutes instead."
+            );
+            settings.steam_cache_ttl_minutes = MIN_STEAM_CACHE_TTL_MINUTES;
+        }
+
+        if let Some(len) = settings.demo_late_bytes_len {
+            if !(MIN_DEMO_LATE_BYTES_LEN..=MAX_DEMO_LATE_BYTES_LEN).contains(&len) {
+                tracing::warn!(
+                    "demo_late_bytes_len must be between {MIN_DEMO_LATE_BYTES_LEN} and {MAX_DEMO_LATE_BYTES_LEN}, using {DEMO_LATE_BYTES_LEN} instead."
+                );
+                settings.demo_late_bytes_len = None;
+            }
+        }
+        if settings
+            .demo_late_bytes_offset()
+            .checked_add(settings.demo_late_bytes_len() as u64)
+            .is_none()
+        {
+            tracing::warn!(
+                "demo_late_bytes_offset is too large, using {DEMO_LATE_BYTES_OFFSET:#x} instead."
+            );
+            settings.demo_late_bytes_offset = None;
+        }
+
+        // Drop any colors that were edited into the file by hand and can't be used
+        for (key, style) in &mut settings.display_styles {
+            if style.color.as_deref().is_some_and(|c| !is_valid_color(c)) {
+                tracing::warn!("Ignoring invalid color for {key}: {:?}", style.color);
+                style.color = None;
+            }
+        }
+
         Ok(settings)
     }
 
@@ -274,7 +615,9 @@ impl Settings {
     }
 
     /// Pull all values from the args struct and set to our override values,
-    /// make sure to add tracing for any values overridden!
+    /// make sure to add tracing for any values overridden! These take
+    /// precedence over any environment variable overrides from
+    /// [`Self::apply_env`].
     ///
     /// # Panics
     /// Will panic if provided the --steam-user flag but not a valid Steam ID 64
@@ -289,6 +632,15 @@ impl Settings {
             );
             val
         });
+        // Override (and log if) the address used to host the middleware API (default 127.0.0.1)
+        self.override_webui_bind_address = args.bind.map(|val| {
+            tracing::info!(
+                "Overrode configured bind address {:?}->{:?}",
+                self.webui_bind_address,
+                val
+            );
+            val
+        });
         // Override (and log if) the RCON password. (default mac_rcon)
         self.override_rcon_password = args.rcon_pword.as_deref().map(|val| {
             tracing::info!(
@@ -318,6 +670,10 @@ impl Settings {
             );
             PathBuf::from(val.clone())
         });
+        if args.replay_console_log {
+            tracing::info!("Overrode configured console log replay to true");
+            self.override_replay_console_log = Some(true);
+        }
         // Override (and log if) the RCON port (default 27015)
         self.override_rcon_port = args.rcon_port.map(|val| {
             tracing::info!(
@@ -380,6 +736,93 @@ impl Settings {
 
         self.minimal_demo_parsing = args.minimal_demo_parsing;
         self.masterbase_http = args.masterbase_http;
+        self.debug_endpoints = args.debug_endpoints;
+    }
+
+    /// Pull override values from `MAC_*` environment variables, for when
+    /// editing the config file isn't convenient. Must be called after
+    /// [`Self::apply_args`], as anything overridden there is left alone so the
+    /// precedence is config < environment < args. Empty variables are ignored.
+    #[allow(clippy::cognitive_complexity)]
+    pub fn apply_env(&mut self) {
+        if self.override_webui_port.is_none() {
+            self.override_webui_port = parse_env_var("MAC_WEBUI_PORT").map(|val| {
+                tracing::info!(
+                    "Overrode configured port value {:?}->{:?} from MAC_WEBUI_PORT",
+                    self.webui_port,
+                    val
+                );
+                val
+            });
+        }
+        if self.override_webui_bind_address.is_none() {
+            self.override_webui_bind_address = parse_env_var("MAC_WEBUI_BIND_ADDRESS").map(|val| {
+                tracing::info!(
+                    "Overrode configured bind address {:?}->{:?} from MAC_WEBUI_BIND_ADDRESS",
+                    self.webui_bind_address,
+                    val
+                );
+                val
+            });
+        }
+        if self.override_rcon_password.is_none() {
+            self.override_rcon_password = env_var("MAC_RCON_PASSWORD").map(|val| {
+                tracing::info!(
+                    "Overrode configured rcon_password {:?}->{:?} from MAC_RCON_PASSWORD",
+                    self.rcon_password,
+                    val
+                );
+                val
+            });
+        }
+        if self.override_steam_api_key.is_none() {
+            self.override_steam_api_key = env_var("MAC_STEAM_API_KEY").map(|val| {
+                tracing::info!(
+                    "Overrode configured Steam API key {:?}->{:?} from MAC_STEAM_API_KEY",
+                    self.steam_api_key,
+                    val
+                );
+                val
+            });
+        }
+        if self.override_tf2_dir.is_none() {
+            self.override_tf2_dir = env_var("MAC_TF2_DIR").map(|val| {
+                tracing::info!(
+                    "Overrode configured TF2 directory {:?}->{:?} from MAC_TF2_DIR",
+                    self.tf2_directory,
+                    val
+                );
+                PathBuf::from(val)
+            });
+        }
+        if self.override_rcon_port.is_none() {
+            self.override_rcon_port = parse_env_var("MAC_RCON_PORT").map(|val| {
+                tracing::info!(
+                    "Overrode configured RCON port value {:?}->{:?} from MAC_RCON_PORT",
+                    self.rcon_port,
+                    val
+                );
+                val
+            });
+        }
+        if self.override_masterbase_api_key.is_none() {
+            self.override_masterbase_api_key = env_var("MAC_MASTERBASE_KEY").map(|val| {
+                tracing::info!(
+                    "Overrode configured Masterbase key {}->{val} from MAC_MASTERBASE_KEY",
+                    self.masterbase_key
+                );
+                val
+            });
+        }
+        if self.override_masterbase_host.is_none() {
+            self.override_masterbase_host = env_var("MAC_MASTERBASE_HOST").map(|val| {
+                tracing::info!(
+                    "Overrode configured Masterbase endpoint {}->{val} from MAC_MASTERBASE_HOST",
+                    self.masterbase_host
+                );
+                val
+            });
+        }
     }
 
     /// Attempt to save the settings back to the loaded configuration file
@@ -535,6 +978,17 @@ impl Settings {
         self.override_webui_port.unwrap_or(self.webui_port)
     }
 
+    #[must_use]
+    pub fn webui_token(&self) -> Option<&str> {
+        self.webui_token.as_deref().filter(|t| !t.is_empty())
+    }
+
+    #[must_use]
+    pub fn webui_bind_address(&self) -> IpAddr {
+        self.override_webui_bind_address
+            .unwrap_or(self.webui_bind_address)
+    }
+
     pub fn set_rcon_port(&mut self, port: u16) {
         self.rcon_port = port;
     }
@@ -577,6 +1031,10 @@ impl Settings {
     pub const fn use_masterbase_http(&self) -> bool {
         self.masterbase_http
     }
+    #[must_use]
+    pub const fn debug_endpoints(&self) -> bool {
+        self.debug_endpoints
+    }
 
     #[must_use]
     pub const fn autokick_bots(&self) -> bool {
@@ -586,11 +1044,328 @@ impl Settings {
         self.autokick_bots = kick;
     }
 
+    #[must_use]
+    pub const fn autokick_reason(&self) -> KickReason {
+        self.autokick_reason
+    }
+    pub fn set_autokick_reason(&mut self, reason: KickReason) {
+        self.autokick_reason = reason;
+    }
+
+    #[must_use]
+    pub const fn autokick_vote_interval_secs(&self) -> u64 {
+        self.autokick_vote_interval_secs
+    }
+    #[must_use]
+    pub const fn autokick_vote_interval(&self) -> Duration {
+        Duration::from_secs(self.autokick_vote_interval_secs)
+    }
+    pub fn set_autokick_vote_interval_secs(&mut self, secs: u64) {
+        self.autokick_vote_interval_secs = secs;
+    }
+
+    #[must_use]
+    pub const fn cache_avatars(&self) -> bool {
+        self.cache_avatars
+    }
+    pub fn set_cache_avatars(&mut self, cache: bool) {
+        self.cache_avatars = cache;
+    }
+
+    #[must_use]
+    pub const fn name_source(&self) -> NameSource {
+        self.name_source
+    }
+    pub fn set_name_source(&mut self, source: NameSource) {
+        self.name_source = source;
+    }
+
+    #[must_use]
+    pub const fn disconnect_grace_period(&self) -> u64 {
+        self.disconnect_grace_period
+    }
+    pub fn set_disconnect_grace_period(&mut self, secs: u64) {
+        self.disconnect_grace_period = secs;
+    }
+
+    #[must_use]
+    pub const fn removal_grace_period(&self) -> u64 {
+        self.removal_grace_period
+    }
+    pub fn set_removal_grace_period(&mut self, secs: u64) {
+        self.removal_grace_period = secs;
+    }
+
+    #[must_use]
+    pub const fn prefetch_record_profiles(&self) -> bool {
+        self.prefetch_record_profiles
+    }
+    pub fn set_prefetch_record_profiles(&mut self, prefetch: bool) {
+        self.prefetch_record_profiles = prefetch;
+    }
+
+    #[must_use]
+    pub fn audit_log_path(&self) -> Option<&Path> {
+        self.audit_log_path.as_deref()
+    }
+
+    #[must_use]
+    pub const fn report_policy(&self) -> ReportPolicy {
+        self.report_policy
+    }
+    pub fn set_report_policy(&mut self, policy: ReportPolicy) {
+        self.report_policy = policy;
+    }
+
+    #[must_use]
+    pub const fn player_sort(&self) -> PlayerSort {
+        self.player_sort
+    }
+    pub fn set_player_sort(&mut self, sort: PlayerSort) {
+        self.player_sort = sort;
+    }
+
+    #[must_use]
+    pub const fn max_history_len(&self) -> usize {
+        self.max_history_len
+    }
+    pub fn set_max_history_len(&mut self, len: usize) {
+        self.max_history_len = len;
+    }
+
+    #[must_use]
+    pub fn autoexec_commands(&self) -> &[String] {
+        &self.autoexec_commands
+    }
+    pub fn set_autoexec_commands(&mut self, commands: Vec<String>) {
+        self.autoexec_commands = commands;
+    }
+
+    #[must_use]
+    pub const fn steam_api_calls_per_minute(&self) -> u32 {
+        self.steam_api_calls_per_minute
+    }
+    pub fn set_steam_api_calls_per_minute(&mut self, calls: u32) {
+        self.steam_api_calls_per_minute = calls;
+    }
+
+    #[must_use]
+    pub const fn preexisting_demo_mode(&self) -> PreexistingDemoMode {
+        self.preexisting_demo_mode
+    }
+    pub fn set_preexisting_demo_mode(&mut self, mode: PreexistingDemoMode) {
+        self.preexisting_demo_mode = mode;
+    }
+
+    #[must_use]
+    pub const fn recent_ban_days(&self) -> u32 {
+        self.recent_ban_days
+    }
+    pub fn set_recent_ban_days(&mut self, days: u32) {
+        self.recent_ban_days = days;
+    }
+
+    #[must_use]
+    pub const fn mark_recently_banned(&self) -> bool {
+        self.mark_recently_banned
+    }
+    pub fn set_mark_recently_banned(&mut self, mark: bool) {
+        self.mark_recently_banned = mark;
+    }
+
+    #[must_use]
+    pub fn disabled_demo_events(&self) -> &[DemoEventKind] {
+        &self.disabled_demo_events
+    }
+    pub fn set_disabled_demo_events(&mut self, kinds: Vec<DemoEventKind>) {
+        self.disabled_demo_events = kinds;
+    }
+
+    #[must_use]
+    pub const fn wait_for_tf2(&self) -> bool {
+        self.wait_for_tf2
+    }
+    pub fn set_wait_for_tf2(&mut self, wait: bool) {
+        self.wait_for_tf2 = wait;
+    }
+
+    #[must_use]
+    pub const fn masterbase_session_retries(&self) -> u32 {
+        self.masterbase_session_retries
+    }
+    pub fn set_masterbase_session_retries(&mut self, retries: u32) {
+        self.masterbase_session_retries = retries;
+    }
+
+    #[must_use]
+    pub const fn masterbase_retry_delay(&self) -> u64 {
+        self.masterbase_retry_delay
+    }
+    pub fn set_masterbase_retry_delay(&mut self, delay: u64) {
+        self.masterbase_retry_delay = delay;
+    }
+
+    #[must_use]
+    pub const fn rcon_command_interval_ms(&self) -> u64 {
+        self.rcon_command_interval_ms
+    }
+    pub fn set_rcon_command_interval_ms(&mut self, ms: u64) {
+        self.rcon_command_interval_ms = ms;
+    }
+
+    #[must_use]
+    pub const fn steam_cache_ttl_minutes(&self) -> u64 {
+        self.steam_cache_ttl_minutes
+    }
+    #[must_use]
+    pub const fn steam_cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.steam_cache_ttl_minutes.saturating_mul(60))
+    }
+    pub fn set_steam_cache_ttl_minutes(&mut self, minutes: u64) {
+        self.steam_cache_ttl_minutes = minutes.max(MIN_STEAM_CACHE_TTL_MINUTES);
+    }
+
+    #[must_use]
+    pub fn replay_console_log(&self) -> bool {
+        self.override_replay_console_log
+            .unwrap_or(self.replay_console_log)
+    }
+    pub fn set_replay_console_log(&mut self, replay: bool) {
+        self.replay_console_log = replay;
+    }
+
+    #[must_use]
+    pub fn trusted_friends(&self) -> &[SteamID] {
+        &self.trusted_friends
+    }
+    pub fn set_trusted_friends(&mut self, friends: Vec<SteamID>) {
+        self.trusted_friends = friends;
+    }
+
+    #[must_use]
+    pub const fn prioritize_rcon_commands(&self) -> bool {
+        self.prioritize_rcon_commands
+    }
+    pub fn set_prioritize_rcon_commands(&mut self, prioritize: bool) {
+        self.prioritize_rcon_commands = prioritize;
+    }
+
+    #[must_use]
+    pub fn demo_late_bytes_offset(&self) -> u64 {
+        self.demo_late_bytes_offset
+            .unwrap_or(DEMO_LATE_BYTES_OFFSET)
+    }
+    #[must_use]
+    pub fn demo_late_bytes_len(&self) -> usize {
+        self.demo_late_bytes_len.unwrap_or(DEMO_LATE_BYTES_LEN)
+    }
+
+    #[must_use]
+    pub const fn display_styles(&self) -> &BTreeMap<String, DisplayStyle> {
+        &self.display_styles
+    }
+    /// # Errors
+    /// If any of the colors are invalid, in which case the styles are left
+    /// unchanged
+    pub fn set_display_styles(
+        &mut self,
+        styles: BTreeMap<String, DisplayStyle>,
+    ) -> Result<(), String> {
+        validate_display_styles(&styles)?;
+        self.display_styles = styles;
+        Ok(())
+    }
+
     #[must_use]
     pub const fn web_ui_source(&self) -> &UISource {
         &self.web_ui_source
     }
 
+    /// A snapshot of the settings actually in use, after any overrides have
+    /// been applied. Secrets are masked so this is safe to show to the user.
+    #[must_use]
+    pub fn effective(&self) -> EffectiveSettings {
+        let mut overridden = Vec::new();
+        let overrides = [
+            ("tf2Directory", self.override_tf2_dir.is_some()),
+            ("rconPassword", self.override_rcon_password.is_some()),
+            ("steamApiKey", self.override_steam_api_key.is_some()),
+            ("webuiPort", self.override_webui_port.is_some()),
+            (
+                "webuiBindAddress",
+                self.override_webui_bind_address.is_some(),
+            ),
+            ("steamUser", self.override_steam_user.is_some()),
+            ("rconPort", self.override_rcon_port.is_some()),
+            (
+                "replayConsoleLog",
+                self.override_replay_console_log.is_some(),
+            ),
+            ("masterbaseKey", self.override_masterbase_api_key.is_some()),
+            ("masterbaseHost", self.override_masterbase_host.is_some()),
+        ];
+        for (name, is_overridden) in overrides {
+            if is_overridden {
+                overridden.push(name);
+            }
+        }
+
+        EffectiveSettings {
+            config_path: self.config_path.clone(),
+            steam_user: self.steam_user().map(|s| format!("{}", u64::from(s))),
+            tf2_directory: self.tf2_directory().to_path_buf(),
+            rcon_password: mask_secret(self.rcon_password()),
+            rcon_port: self.rcon_port(),
+            steam_api_key: mask_secret(self.steam_api_key()),
+            masterbase_key: mask_secret(self.masterbase_key()),
+            masterbase_host: self.masterbase_host().to_owned(),
+            webui_port: self.webui_port(),
+            webui_bind_address: self.webui_bind_address(),
+            webui_token: self.webui_token().map(mask_secret),
+            web_ui_source: match &self.web_ui_source {
+                UISource::Bundled(_) => "bundled".into(),
+                UISource::Dynamic(dir) => dir.to_string_lossy().into(),
+                UISource::None => "none".into(),
+            },
+            autolaunch_ui: self.autolaunch_ui,
+            friends_api_usage: self.friends_api_usage,
+            autokick_bots: self.autokick_bots,
+            autokick_reason: self.autokick_reason,
+            autokick_vote_interval_secs: self.autokick_vote_interval_secs,
+            cache_avatars: self.cache_avatars,
+            name_source: self.name_source,
+            disconnect_grace_period: self.disconnect_grace_period,
+            removal_grace_period: self.removal_grace_period,
+            prefetch_record_profiles: self.prefetch_record_profiles,
+            audit_log_path: self.audit_log_path.clone(),
+            report_policy: self.report_policy,
+            player_sort: self.player_sort,
+            max_history_len: self.max_history_len,
+            autoexec_commands: self.autoexec_commands.clone(),
+            steam_api_calls_per_minute: self.steam_api_calls_per_minute,
+            preexisting_demo_mode: self.preexisting_demo_mode,
+            recent_ban_days: self.recent_ban_days,
+            mark_recently_banned: self.mark_recently_banned,
+            display_styles: self.display_styles.clone(),
+            disabled_demo_events: self.disabled_demo_events.clone(),
+            wait_for_tf2: self.wait_for_tf2,
+            demo_late_bytes_offset: self.demo_late_bytes_offset(),
+            demo_late_bytes_len: self.demo_late_bytes_len(),
+            masterbase_session_retries: self.masterbase_session_retries,
+            masterbase_retry_delay: self.masterbase_retry_delay,
+            rcon_command_interval_ms: self.rcon_command_interval_ms,
+            prioritize_rcon_commands: self.prioritize_rcon_commands,
+            steam_cache_ttl_minutes: self.steam_cache_ttl_minutes,
+            replay_console_log: self.replay_console_log(),
+            trusted_friends: self.trusted_friends.clone(),
+            upload_demos: self.upload_demos(),
+            minimal_demo_parsing: self.minimal_demo_parsing,
+            masterbase_http: self.masterbase_http,
+            debug_endpoints: self.debug_endpoints,
+            overridden,
+        }
+    }
+
     /// Attempts to find (and create) a directory to be used for configuration
     /// files
     ///
@@ -614,6 +1389,63 @@ impl Settings {
     }
 }
 
+/// The settings in use at runtime, see [`Settings::effective`]
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectiveSettings {
+    pub config_path: Option<PathBuf>,
+    pub steam_user: Option<String>,
+    pub tf2_directory: PathBuf,
+    pub rcon_password: String,
+    pub rcon_port: u16,
+    pub steam_api_key: String,
+    pub masterbase_key: String,
+    pub masterbase_host: String,
+    pub webui_port: u16,
+    pub webui_bind_address: IpAddr,
+    pub webui_token: Option<String>,
+    pub web_ui_source: String,
+    pub autolaunch_ui: bool,
+    pub friends_api_usage: FriendsAPIUsage,
+    pub autokick_bots: bool,
+    pub autokick_reason: KickReason,
+    pub autokick_vote_interval_secs: u64,
+    pub cache_avatars: bool,
+    pub name_source: NameSource,
+    pub disconnect_grace_period: u64,
+    pub removal_grace_period: u64,
+    pub prefetch_record_profiles: bool,
+    pub audit_log_path: Option<PathBuf>,
+    pub report_policy: ReportPolicy,
+    pub player_sort: PlayerSort,
+    pub max_history_len: usize,
+    pub autoexec_commands: Vec<String>,
+    pub steam_api_calls_per_minute: u32,
+    pub preexisting_demo_mode: PreexistingDemoMode,
+    pub recent_ban_days: u32,
+    pub mark_recently_banned: bool,
+    pub display_styles: BTreeMap<String, DisplayStyle>,
+    pub disabled_demo_events: Vec<DemoEventKind>,
+    pub wait_for_tf2: bool,
+    pub demo_late_bytes_offset: u64,
+    pub demo_late_bytes_len: usize,
+    pub masterbase_session_retries: u32,
+    pub masterbase_retry_delay: u64,
+    pub rcon_command_interval_ms: u64,
+    pub prioritize_rcon_commands: bool,
+    pub steam_cache_ttl_minutes: u64,
+    pub replay_console_log: bool,
+    pub trusted_friends: Vec<SteamID>,
+    pub upload_demos: bool,
+    pub minimal_demo_parsing: bool,
+    pub masterbase_http: bool,
+    pub debug_endpoints: bool,
+    /// Which of the settings have been overridden and differ from the
+    /// configuration file
+    pub overridden: Vec<&'static str>,
+}
+
 impl Default for Settings {
     fn default() -> Self {
         let config_path = Self::locate_config_file_path()
@@ -630,6 +1462,8 @@ impl Default for Settings {
             masterbase_host: "megaanticheat.com".into(),
             friends_api_usage: FriendsAPIUsage::CheatersOnly,
             webui_port: 3621,
+            webui_bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            webui_token: None,
             autolaunch_ui: false,
             rcon_port: 27015,
             tos_agreement_date: None,
@@ -637,14 +1471,45 @@ impl Default for Settings {
             override_rcon_password: None,
             override_steam_api_key: None,
             override_webui_port: None,
+            override_webui_bind_address: None,
             override_steam_user: None,
             override_rcon_port: None,
+            override_replay_console_log: None,
             override_masterbase_api_key: None,
             override_masterbase_host: None,
             external: serde_json::Value::Object(Map::new()),
             minimal_demo_parsing: false,
             masterbase_http: false,
+            debug_endpoints: false,
             autokick_bots: false,
+            autokick_reason: KickReason::Cheating,
+            autokick_vote_interval_secs: 30,
+            cache_avatars: false,
+            name_source: NameSource::default(),
+            disconnect_grace_period: 6,
+            removal_grace_period: 18,
+            prefetch_record_profiles: false,
+            audit_log_path: None,
+            report_policy: ReportPolicy::default(),
+            player_sort: PlayerSort::default(),
+            max_history_len: 1000,
+            autoexec_commands: Vec::new(),
+            steam_api_calls_per_minute: 0,
+            preexisting_demo_mode: PreexistingDemoMode::default(),
+            recent_ban_days: 14,
+            mark_recently_banned: false,
+            display_styles: BTreeMap::new(),
+            disabled_demo_events: Vec::new(),
+            wait_for_tf2: false,
+            demo_late_bytes_offset: None,
+            demo_late_bytes_len: None,
+            masterbase_session_retries: 3,
+            masterbase_retry_delay: 1,
+            rcon_command_interval_ms: 100,
+            prioritize_rcon_commands: true,
+            steam_cache_ttl_minutes: 180,
+            replay_console_log: false,
+            trusted_friends: Vec::new(),
             web_ui_source: UISource::default(),
         }
     }
@@ -652,6 +1517,20 @@ impl Default for Settings {
 
 // Useful
 
+/// Hides all but the last few characters of a secret value.
+#[must_use]
+pub fn mask_secret(secret: &str) -> String {
+    const VISIBLE: usize = 4;
+
+    let len = secret.chars().count();
+    if len <= VISIBLE * 2 {
+        return "*".repeat(len);
+    }
+
+    let visible: String = secret.chars().skip(len - VISIBLE).collect();
+    format!("{}{visible}", "*".repeat(len - VISIBLE))
+}
+
 /// Combines the second provided Json Object into the first. If the given
 /// [Value]s are not `Value::Object`s, this will do nothing.
 pub fn merge_json_objects(a: &mut Value, b: Value) {
@@ -676,3 +1555,156 @@ pub fn merge_json_objects(a: &mut Value, b: Value) {
 
     *a = b;
 }
+
+/// Reads an environment variable, treating it as unset if it's empty
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .filter(|val| !val.trim().is_empty())
+}
+
+/// Reads and parses an environment variable, ignoring it if it's invalid
+fn parse_env_var<T: FromStr>(name: &str) -> Option<T>
+where
+    T::Err: Display,
+{
+    let val = env_var(name)?;
+    val.trim()
+        .parse()
+        .map_err(|e| tracing::error!("Ignoring invalid value {val:?} for {name}: {e}"))
+        .ok()
+}
+
+/// Watches the config file so it can be reloaded when it's edited while the
+/// client is running.
+pub struct ConfigWatcher {
+    recv: Receiver<Event>,
+    file_name: OsString,
+    _watcher: RecommendedWatcher,
+}
+
+impl ConfigWatcher {
+    /// # Errors
+    /// If the [`notify::Watcher`] could not be started.
+    pub fn new(config_path: &Path) -> Result<Self, notify::Error> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher: RecommendedWatcher = Watcher::new(
+            move |res: Result<Event, notify::Error>| match res {
+                Ok(event) => {
+                    let _ = tx.send(event);
+                }
+                Err(err) => {
+                    tracing::error!("Error while watching config file: {}", err);
+                }
+            },
+            Config::default(),
+        )?;
+
+        // Saving replaces the file instead of writing to it, so watch the
+        // directory it's in.
+        let dir = config_path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            recv: rx,
+            file_name: config_path.file_name().unwrap_or_default().to_owned(),
+            _watcher: watcher,
+        })
+    }
+}
+
+impl<M: Is<SettingsReloaded>> MessageSource<M> for ConfigWatcher {
+    fn next_message(&mut self) -> Option<M> {
+        // A single save can show up as several events, only reload once for them
+        let mut changed = false;
+        while let Ok(event) = self.recv.try_recv() {
+            changed |= matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name() == Some(self.file_name.as_os_str()));
+        }
+
+        changed.then(|| SettingsReloaded.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{
+        is_valid_color, validate_display_styles, DisplayStyle, Settings, DEMO_LATE_BYTES_LEN,
+        DEMO_LATE_BYTES_OFFSET,
+    };
+
+    #[test]
+    pub fn valid_colors() {
+        for color in ["#f00", "#F00a", "#ff00", "#ff0000", "#ff0000AA"] {
+            assert!(is_valid_color(color), "{color}");
+        }
+        for color in [
+            "",
+            "#",
+            "f00",
+            "#ff000",
+            "#ff00000",
+            "#gg0000",
+            "red",
+            "#ff0000aa00",
+        ] {
+            assert!(!is_valid_color(color), "{color}");
+        }
+    }
+
+    #[test]
+    pub fn invalid_display_style() {
+        let mut styles = BTreeMap::new();
+        styles.insert(
+            "Cheater".to_string(),
+            DisplayStyle {
+                label: Some("Hacker".to_string()),
+                color: Some("#f00".to_string()),
+            },
+        );
+        styles.insert("Bot".to_string(), DisplayStyle::default());
+        assert!(validate_display_styles(&styles).is_ok());
+
+        styles.insert(
+            "Suspicious".to_string(),
+            DisplayStyle {
+                label: None,
+                color: Some("orange".to_string()),
+            },
+        );
+        assert!(validate_display_styles(&styles).is_err());
+    }
+
+    #[test]
+    pub fn invalid_demo_late_bytes() {
+        let path = std::env::temp_dir().join(format!("mac_settings_{}.yaml", std::process::id()));
+        let read = |contents: &str| {
+            std::fs::write(&path, contents).expect("Couldn't write config");
+            Settings::read_file(path.clone()).expect("Couldn't read config")
+        };
+
+        let settings = read("demo_late_bytes_offset: 1024\ndemo_late_bytes_len: 32\n");
+        assert_eq!(settings.demo_late_bytes_offset(), 0x400);
+        assert_eq!(settings.demo_late_bytes_len(), 32);
+
+        // Too short to ever tell if they've been written
+        let settings = read("demo_late_bytes_len: 8\n");
+        assert_eq!(settings.demo_late_bytes_len(), DEMO_LATE_BYTES_LEN);
+
+        let settings = read("demo_late_bytes_len: 1000000000\n");
+        assert_eq!(settings.demo_late_bytes_len(), DEMO_LATE_BYTES_LEN);
+
+        let settings = read(&format!("demo_late_bytes_offset: {}\n", u64::MAX));
+        assert_eq!(settings.demo_late_bytes_offset(), DEMO_LATE_BYTES_OFFSET);
+
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -1,14 +1,22 @@
 use crate::{
+    command_manager::RconStateChanged,
+    confidence::RecentlyBanned,
     console::ConsoleOutput,
     demo::{DemoEvent, DemoMessage},
-    io::regexes::{ChatMessage, DemoStop, PlayerKill},
+    events::Refresh,
+    io::regexes::{ChatMessage, DemoStop, PlayerKill, SelfKicked},
+    new_players::PresenceChanged,
+    player::{serialize_steamid_as_string, RefreshDelta, Team},
     state::MACState,
     web::broadcast_event,
 };
 use chrono::{DateTime, Utc};
 use event_loop::{try_get, Handled, Is, MessageHandler};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 use steamid_ng::SteamID;
 use tf_demo_parser::demo::gameevent_gen::{VoteCastEvent, VoteOptionsEvent, VoteStartedEvent};
 use uuid::Uuid;
@@ -37,6 +45,116 @@ impl SerializableConsoleOutput for DemoStop {
     }
 }
 
+impl SerializableConsoleOutput for SelfKicked {
+    fn get_type(&self) -> String {
+        "SelfKicked".to_string()
+    }
+}
+
+/// Sent when a new Mann vs. Machine wave starts
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WaveChanged {
+    pub wave: u32,
+    pub max_waves: u32,
+}
+
+impl SerializableConsoleOutput for WaveChanged {
+    fn get_type(&self) -> String {
+        "WaveChanged".to_string()
+    }
+}
+
+/// Sent when a round starts or ends, with the demo tick it happened on so the
+/// UI can map rounds to positions in the demo
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoundChanged {
+    pub tick: u32,
+    pub started: bool,
+    /// The winning team when a round ends, or `None` for a stalemate
+    pub winner: Option<Team>,
+}
+
+impl SerializableConsoleOutput for RoundChanged {
+    fn get_type(&self) -> String {
+        "RoundChanged".to_string()
+    }
+}
+
+impl SerializableConsoleOutput for RconStateChanged {
+    fn get_type(&self) -> String {
+        "RconStateChanged".to_string()
+    }
+}
+
+impl SerializableConsoleOutput for RecentlyBanned {
+    fn get_type(&self) -> String {
+        "RecentlyBanned".to_string()
+    }
+}
+
+/// Sent when a player is first seen on the server
+#[derive(Serialize)]
+pub struct PlayerJoined {
+    #[serde(rename = "steamID64", serialize_with = "serialize_steamid_as_string")]
+    pub steamid: SteamID,
+    pub name: Option<String>,
+}
+
+impl SerializableConsoleOutput for PlayerJoined {
+    fn get_type(&self) -> String {
+        "PlayerJoined".to_string()
+    }
+}
+
+/// Sent when a player is marked as disconnected after they stop showing up in
+/// the server's status
+#[derive(Serialize)]
+pub struct PlayerLeft {
+    #[serde(rename = "steamID64", serialize_with = "serialize_steamid_as_string")]
+    pub steamid: SteamID,
+    pub name: Option<String>,
+}
+
+impl SerializableConsoleOutput for PlayerLeft {
+    fn get_type(&self) -> String {
+        "PlayerLeft".to_string()
+    }
+}
+
+/// A summary of the server, sent whenever it changes and periodically as a
+/// heartbeat so the UI can tell the client is still connected
+#[derive(Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    pub map: Option<String>,
+    pub hostname: Option<String>,
+    pub num_players: Option<u32>,
+    pub max_players: Option<u32>,
+}
+
+impl ServerStatus {
+    #[must_use]
+    pub fn from_state(state: &MACState) -> Self {
+        Self {
+            map: state.server.map().map(ToString::to_string),
+            hostname: state.server.hostname().map(ToString::to_string),
+            num_players: state.server.num_players(),
+            max_players: state.server.max_players(),
+        }
+    }
+}
+
+impl SerializableConsoleOutput for ServerStatus {
+    fn get_type(&self) -> String {
+        "ServerStatus".to_string()
+    }
+}
+
+/// How often to send the [`ServerStatus`] when it hasn't changed
+const SERVER_STATUS_HEARTBEAT: Duration = Duration::from_secs(30);
+
 /// Wraps the type (that is wrapped by `ConsoleOutput`) with use json data, such as the event type,
 /// timestamp and even a uuid.  
 #[derive(Serialize, Deserialize)]
@@ -135,6 +253,8 @@ where
 pub struct SseEventBroadcaster {
     /// Used when handling certain `DemoMessage` messages (I.e. `VoteStarted`)
     votes: HashMap<u32, Vec<String>>,
+    /// The last [`ServerStatus`] sent, and when
+    last_status: Option<(ServerStatus, Instant)>,
 }
 
 impl SseEventBroadcaster {
@@ -142,6 +262,7 @@ impl SseEventBroadcaster {
     pub fn new() -> Self {
         Self {
             votes: HashMap::new(),
+            last_status: None,
         }
     }
 }
@@ -159,7 +280,12 @@ impl Default for SseEventBroadcaster {
 /// See `broadcast_event` in `crate::web` for more info
 impl<IM, OM> MessageHandler<MACState, IM, OM> for SseEventBroadcaster
 where
-    IM: Is<DemoMessage> + Is<ConsoleOutput>,
+    IM: Is<DemoMessage>
+        + Is<ConsoleOutput>
+        + Is<RconStateChanged>
+        + Is<Refresh>
+        + Is<PresenceChanged>
+        + Is<RecentlyBanned>,
 {
     /// `IM` can be a message that is either a `DemoMessage` or a `ConsoleOutput` message
     /// These are then dispatched to the appropriate discrete message handler, which take the
@@ -168,10 +294,27 @@ where
     /// If a `String` was returned, we broadcast that message to all subscribers.
     #[allow(clippy::cognitive_complexity)]
     fn handle_message(&mut self, state: &MACState, message: &IM) -> Option<Handled<OM>> {
+        if let Some(PresenceChanged(delta)) = try_get(message) {
+            return Handled::multiple(Self::handle_presence(state, delta).into_iter().map(
+                |json| {
+                    Handled::future(async move {
+                        broadcast_event(json).await;
+                        None
+                    })
+                },
+            ));
+        }
+
         let event_json = if let Some(demo_msg) = try_get::<DemoMessage>(message) {
             self.handle_demo_message(state, demo_msg)
         } else if let Some(con_msg) = try_get::<ConsoleOutput>(message) {
-            self.handle_console_message(con_msg)
+            self.handle_console_message(state, con_msg)
+        } else if let Some(&changed) = try_get::<RconStateChanged>(message) {
+            serde_json::to_string(&SerializableEvent::make_from(changed)).ok()
+        } else if let Some(banned) = try_get::<RecentlyBanned>(message) {
+            serde_json::to_string(&SerializableEvent::make_from(banned.clone())).ok()
+        } else if try_get::<Refresh>(message).is_some() {
+            self.handle_refresh(state)
         } else {
             None
         };
@@ -189,14 +332,18 @@ impl SseEventBroadcaster {
     /// Handling the console message requires injecting certain variables into the `ConsoleOutput` wrapped values,
     /// as not all of them are populated when instantiated. We use the current `MACState` to pull out the relevant
     /// values, then insert in the necessary place on a case-by-case basis.
+    /// Events caused by players the user has muted are not broadcast.
     /// Allow unused self (non-static method that can be static) as self may be used in future when we add more
     /// `ConsoleOutput` types to handle.
     #[allow(clippy::unused_self)]
-    fn handle_console_message(&self, message: &ConsoleOutput) -> Option<String> {
+    fn handle_console_message(&self, state: &MACState, message: &ConsoleOutput) -> Option<String> {
         let cloned_co = message.clone();
+        let is_muted = |s: Option<SteamID>| s.is_some_and(|s| state.players.is_muted(s));
 
         // We also set the steam_id fields in the events here before we serialise
         match cloned_co {
+            ConsoleOutput::Chat(m) if is_muted(m.steamid) => None,
+            ConsoleOutput::Kill(m) if is_muted(m.killer_steamid) => None,
             ConsoleOutput::Chat(m) => {
                 let event = SerializableEvent::make_from(m);
                 Some(serde_json::to_string(&event).expect("Serialisation failure"))
@@ -209,10 +356,57 @@ impl SseEventBroadcaster {
                 let event = SerializableEvent::make_from(m);
                 Some(serde_json::to_string(&event).expect("Serialisation failure"))
             }
+            ConsoleOutput::SelfKicked(m) => {
+                let event = SerializableEvent::make_from(m);
+                Some(serde_json::to_string(&event).expect("Serialisation failure"))
+            }
             _ => None,
         }
     }
 
+    /// A [`PlayerJoined`] or [`PlayerLeft`] for each player in the delta
+    fn handle_presence(state: &MACState, delta: &RefreshDelta) -> Vec<String> {
+        let name = |s| state.players.get_name(s).map(ToString::to_string);
+        let joined = delta.joined.iter().map(|&steamid| {
+            serde_json::to_string(&SerializableEvent::make_from(PlayerJoined {
+                steamid,
+                name: name(steamid),
+            }))
+        });
+        let left = delta.left.iter().map(|&steamid| {
+            serde_json::to_string(&SerializableEvent::make_from(PlayerLeft {
+                steamid,
+                name: name(steamid),
+            }))
+        });
+
+        joined
+            .chain(left)
+            .filter_map(|event| {
+                event
+                    .map_err(|e| tracing::error!("Failed to serialize presence event: {e}"))
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Sends the [`ServerStatus`] if it has changed, or if it hasn't been sent
+    /// for a while.
+    fn handle_refresh(&mut self, state: &MACState) -> Option<String> {
+        let status = ServerStatus::from_state(state);
+        let now = Instant::now();
+        if self.last_status.as_ref().is_some_and(|(last, at)| {
+            *last == status && now.duration_since(*at) < SERVER_STATUS_HEARTBEAT
+        }) {
+            return None;
+        }
+
+        let json = serde_json::to_string(&SerializableEvent::make_from(status.clone()))
+            .expect("Serialisation failure");
+        self.last_status = Some((status, now));
+        Some(json)
+    }
+
     /// Handling `DemoMessages` often requires inspecting or modifying the list of votes that have been
     /// cast. I.e. a `VoteCast` event wont contain information about what the vote options were. So we
     /// have to keep this context ourselves.
@@ -237,6 +431,7 @@ impl SseEventBroadcaster {
                 let res = VoteRelatedEvent::make_from(options);
                 Some(serde_json::to_string(&res).expect("Serialisation failure"))
             }
+            DemoEvent::VoteCast(_, Some(steamid)) if state.players.is_muted(steamid) => None,
             DemoEvent::VoteCast(event, steamid) => {
                 let name = steamid
                     .as_ref()
@@ -260,7 +455,36 @@ impl SseEventBroadcaster {
                 let res = VoteRelatedEvent::make_from(event);
                 Some(serde_json::to_string(&res).expect("Serialisation failure"))
             }
-            DemoEvent::LatestTick => None,
+            DemoEvent::MvmWaveStarted { wave, max_waves } => {
+                // The state hasn't been updated yet, so this is the previous wave
+                if state.server.mvm().and_then(|m| m.wave) == Some(wave) {
+                    return None;
+                }
+
+                let event = SerializableEvent::make_from(WaveChanged { wave, max_waves });
+                Some(serde_json::to_string(&event).expect("Serialisation failure"))
+            }
+            DemoEvent::RoundStart { .. } => {
+                let event = SerializableEvent::make_from(RoundChanged {
+                    tick: message.tick,
+                    started: true,
+                    winner: None,
+                });
+                Some(serde_json::to_string(&event).expect("Serialisation failure"))
+            }
+            DemoEvent::RoundEnd { winner } => {
+                let event = SerializableEvent::make_from(RoundChanged {
+                    tick: message.tick,
+                    started: false,
+                    winner,
+                });
+                Some(serde_json::to_string(&event).expect("Serialisation failure"))
+            }
+            // Joins and leaves are already broadcast when the players are refreshed
+            DemoEvent::MvmCurrencyCollected(_)
+            | DemoEvent::PlayerConnected { .. }
+            | DemoEvent::PlayerDisconnected { .. }
+            | DemoEvent::LatestTick => None,
         }
     }
 }
@@ -1,24 +1,45 @@
-use crate::{console::ConsoleOutput, player::Players, server::Server, settings::Settings};
+use event_loop::EventLoopMetrics;
+
+use crate::{
+    console::ConsoleOutput, player::Players, server::Server, settings::Settings,
+    stats::SessionStats, steam_api::SteamApiLimiter,
+};
 
 #[allow(clippy::module_name_repetitions)]
 pub struct MACState {
     pub server: Server,
     pub settings: Settings,
     pub players: Players,
+    pub steam_api_limiter: SteamApiLimiter,
+    pub session_stats: SessionStats,
+    pub event_loop_metrics: EventLoopMetrics,
 }
 
 impl MACState {
     pub fn handle_console_output(&mut self, output: ConsoleOutput) {
         use ConsoleOutput::{
-            Chat, DemoStop, Hostname, Kill, Map, PlayerCount, ServerIP, Status, G15,
+            Chat, DemoStop, Hostname, Kill, Map, PlayerCount, SelfKicked, ServerIP, Status, G15,
         };
+        let connected = self.players.connected.len();
         match output {
             Status(inner) => self.players.handle_status_line(inner),
             G15(inner) => self.players.handle_g15(inner),
             DemoStop(_) => {}
-            Chat(_) | Kill(_) | Hostname(_) | ServerIP(_) | Map(_) | PlayerCount(_) => {
+            SelfKicked(kicked) => {
+                tracing::warn!("You were removed from the server: {}", kicked.reason);
+            }
+            Kill(ref kill) => {
+                self.players.handle_kill(kill);
+                self.server.handle_console_output(output);
+            }
+            Chat(_) | Hostname(_) | ServerIP(_) | Map(_) | PlayerCount(_) => {
                 self.server.handle_console_output(output);
             }
         }
+
+        // Anyone who just joined is at the end
+        for &steamid in self.players.connected.iter().skip(connected) {
+            self.session_stats.add_player_seen(steamid);
+        }
     }
 }
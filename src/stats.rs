@@ -0,0 +1,137 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serde::Serialize;
+use steamid_ng::SteamID;
+
+/// Counts things that have happened this session, or since the counters were
+/// last reset. Cloning gives another handle to the same counters so they can
+/// be incremented from handlers and async tasks.
+#[derive(Debug, Clone, Default)]
+pub struct SessionStats {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    /// Kept as a set so players who leave and rejoin are only counted once
+    players_seen: Mutex<HashSet<SteamID>>,
+    kicks: AtomicU64,
+    reports: AtomicU64,
+    steam_api_calls: AtomicU64,
+    /// Unlike the others, these are never reset so they cover the whole time
+    /// the client has been running
+    total_steam_api_calls: AtomicU64,
+    rcon_connections: AtomicU64,
+}
+
+/// The values of the [`SessionStats`] at a point in time
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionStatsSnapshot {
+    pub players_seen: u64,
+    pub kicks: u64,
+    pub reports: u64,
+    pub steam_api_calls: u64,
+}
+
+impl SessionStats {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Panics
+    /// If the players seen lock was poisoned
+    pub fn add_player_seen(&self, steamid: SteamID) {
+        self.counters
+            .players_seen
+            .lock()
+            .expect("Players seen lock poisoned")
+            .insert(steamid);
+    }
+
+    /// How many different players have been seen, forgetting them if `reset`
+    ///
+    /// # Panics
+    /// If the players seen lock was poisoned
+    #[must_use]
+    fn players_seen(&self, reset: bool) -> u64 {
+        let mut seen = self
+            .counters
+            .players_seen
+            .lock()
+            .expect("Players seen lock poisoned");
+        let count = seen.len() as u64;
+        if reset {
+            seen.clear();
+        }
+        count
+    }
+
+    pub fn add_kick(&self) {
+        self.counters.kicks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_report(&self) {
+        self.counters.reports.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_steam_api_call(&self) {
+        self.counters
+            .steam_api_calls
+            .fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .total_steam_api_calls
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_rcon_connection(&self) {
+        self.counters
+            .rcon_connections
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Steam API calls made since the client started
+    #[must_use]
+    pub fn total_steam_api_calls(&self) -> u64 {
+        self.counters.total_steam_api_calls.load(Ordering::Relaxed)
+    }
+
+    /// How many times RCON has connected after losing the connection since the
+    /// client started
+    #[must_use]
+    pub fn rcon_reconnects(&self) -> u64 {
+        self.counters
+            .rcon_connections
+            .load(Ordering::Relaxed)
+            .saturating_sub(1)
+    }
+
+    #[must_use]
+    pub fn snapshot(&self) -> SessionStatsSnapshot {
+        let c = &self.counters;
+        SessionStatsSnapshot {
+            players_seen: self.players_seen(false),
+            kicks: c.kicks.load(Ordering::Relaxed),
+            reports: c.reports.load(Ordering::Relaxed),
+            steam_api_calls: c.steam_api_calls.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Sets all the counters back to 0, returning what they were before
+    pub fn reset(&self) -> SessionStatsSnapshot {
+        let c = &self.counters;
+        SessionStatsSnapshot {
+            players_seen: self.players_seen(true),
+            kicks: c.kicks.swap(0, Ordering::Relaxed),
+            reports: c.reports.swap(0, Ordering::Relaxed),
+            steam_api_calls: c.steam_api_calls.swap(0, Ordering::Relaxed),
+        }
+    }
+}
@@ -1,27 +1,136 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use chrono::Utc;
 use event_loop::{try_get, Handled, Is, Message, MessageHandler};
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::Serialize;
 use steamid_ng::SteamID;
-use tappet::{
-    response_types::{
-        GetFriendListResponseBase, GetPlayerBansResponseBase, GetPlayerSummariesResponseBase,
-        PlayerBans, PlayerSummary,
-    },
-    Executor, SteamAPI,
+use tappet::response_types::{
+    GetFriendListResponseBase, GetPlayerBansResponseBase, GetPlayerSummariesResponseBase,
+    PlayerBans, PlayerSummary,
 };
 use thiserror::Error;
+use tokio::sync::mpsc::UnboundedSender;
 
 use super::new_players::NewPlayers;
 use crate::{
-    events::{InternalPreferences, Preferences, UserUpdates},
+    events::{ClearCaches, InternalPreferences, Preferences, UserUpdates},
     player::{Friend, SteamInfo},
     player_records::{PlayerRecord, Verdict},
     settings::FriendsAPIUsage,
     state::MACState,
+    stats::SessionStats,
+    web::{WebError, WebRequest, WebResponse},
 };
 
 const BATCH_SIZE: usize = 20; // adjust as needed
+const STEAM_API_HOST: &str = "https://api.steampowered.com";
+/// How many times to retry a Steam API call that was rate limited
+const RATE_LIMIT_RETRIES: u32 = 3;
+/// Delay before retrying a rate limited call if Steam didn't say how long to
+/// wait, doubled for each following attempt
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(2);
+/// The longest we'll wait before retrying a rate limited call
+const MAX_RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(60);
+/// How many times to retry looking up a profile that individually failed
+const MAX_PROFILE_RETRIES: u32 = 3;
+/// Delay before the first retry, doubled for each following attempt
+const PROFILE_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+/// How long to leave a profile alone after giving up on looking it up
+const PROFILE_GIVE_UP_COOLDOWN: Duration = Duration::from_secs(30 * 60);
+/// The most records that will have their profiles prefetched on startup
+const MAX_PREFETCH: usize = 1000;
+/// How long to wait between sending batches of prefetched profiles
+const PREFETCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The window which the Steam API call limit applies to
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Limits how many Steam API calls are made per minute across all lookups.
+/// Calls over the limit wait for the next window instead of being dropped.
+#[derive(Debug, Clone)]
+pub struct SteamApiLimiter {
+    window: Arc<Mutex<LimiterWindow>>,
+    stats: SessionStats,
+}
+
+#[derive(Debug)]
+struct LimiterWindow {
+    /// Calls allowed per window, or 0 for no limit
+    limit: u32,
+    start: Instant,
+    used: u32,
+}
+
+impl LimiterWindow {
+    fn roll_over(&mut self, now: Instant) {
+        if now.duration_since(self.start) >= RATE_LIMIT_WINDOW {
+            self.start = now;
+            self.used = 0;
+        }
+    }
+}
+
+impl SteamApiLimiter {
+    #[must_use]
+    pub fn new(limit: u32, stats: SessionStats) -> Self {
+        Self {
+            window: Arc::new(Mutex::new(LimiterWindow {
+                limit,
+                start: Instant::now(),
+                used: 0,
+            })),
+            stats,
+        }
+    }
+
+    /// # Panics
+    /// If the lock was poisoned
+    pub fn set_limit(&self, limit: u32) {
+        self.window.lock().expect("Limiter lock poisoned").limit = limit;
+    }
+
+    /// How many calls can still be made in the current window, or `None` if
+    /// there is no limit.
+    ///
+    /// # Panics
+    /// If the lock was poisoned
+    #[must_use]
+    pub fn remaining(&self) -> Option<u32> {
+        let mut window = self.window.lock().expect("Limiter lock poisoned");
+        window.roll_over(Instant::now());
+        (window.limit != 0).then(|| window.limit.saturating_sub(window.used))
+    }
+
+    /// Waits until a call can be made within the limit, and uses it.
+    ///
+    /// # Panics
+    /// If the lock was poisoned
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut window = self.window.lock().expect("Limiter lock poisoned");
+                let now = Instant::now();
+                window.roll_over(now);
+
+                if window.limit == 0 || window.used < window.limit {
+                    window.used += 1;
+                    self.stats.add_steam_api_call();
+                    return;
+                }
+
+                (window.start + RATE_LIMIT_WINDOW).saturating_duration_since(now)
+            };
+
+            tracing::debug!("Steam API call limit reached, waiting {wait:?}.");
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
 
 #[derive(Debug, Error)]
 pub enum SteamAPIError {
@@ -32,7 +141,54 @@ pub enum SteamAPIError {
     #[error(transparent)]
     Serde(#[from] serde_json::Error),
     #[error(transparent)]
-    Tappet(#[from] tappet::errors::SteamAPIError),
+    Request(#[from] reqwest::Error),
+    #[error("Steam API responded with {status}")]
+    Status {
+        status: StatusCode,
+        /// How long Steam asked us to wait before trying again
+        retry_after: Option<Duration>,
+    },
+}
+
+/// A minimal Steam Web API client. The requests are made directly rather
+/// than through `tappet` so the response status and headers are available,
+/// which is how rate limiting is detected.
+#[derive(Clone)]
+pub struct SteamAPI {
+    client: reqwest::Client,
+    key: String,
+}
+
+impl SteamAPI {
+    #[must_use]
+    pub fn new(key: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            key: key.into(),
+        }
+    }
+
+    /// Calls a Steam Web API method, e.g. `ISteamUser/GetPlayerBans/v1`, and
+    /// returns the body of the response.
+    async fn get(&self, method: &str, params: &[(&str, &str)]) -> Result<String, SteamAPIError> {
+        let resp = self
+            .client
+            .get(format!("{STEAM_API_HOST}/{method}/"))
+            .query(&[("key", self.key.as_str())])
+            .query(params)
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(SteamAPIError::Status {
+                status,
+                retry_after: parse_retry_after(resp.headers()),
+            });
+        }
+
+        Ok(resp.text().await?)
+    }
 }
 
 // Messages *************************
@@ -43,8 +199,10 @@ impl<S> event_loop::Message<S> for ProfileLookupBatchTick {}
 
 type ProfileResult = Result<Vec<(SteamID, Result<SteamInfo, SteamAPIError>)>, SteamAPIError>;
 
+/// The second field is the id of the web request which asked for the
+/// lookup, if any, so the results can be sent back to it.
 #[derive(Debug)]
-pub struct ProfileLookupResult(pub ProfileResult);
+pub struct ProfileLookupResult(pub ProfileResult, pub Option<u64>);
 impl Message<MACState> for ProfileLookupResult {
     fn update_state(self, state: &mut MACState) {
         let results = match &self.0 {
@@ -103,17 +261,165 @@ impl<S> Message<S> for ProfileLookupRequest {}
 
 // Handlers *************************
 
+struct ProfileRetry {
+    attempts: u32,
+    /// `None` while the retry is queued or in progress
+    next_attempt: Option<Instant>,
+}
+
 pub struct LookupProfiles {
     batch_buffer: VecDeque<SteamID>,
     in_progress: Vec<SteamID>,
+    retries: HashMap<SteamID, ProfileRetry>,
+    /// Profiles which failed every retry, and when they can be looked up again
+    given_up: HashMap<SteamID, Instant>,
+    /// Records waiting to be prefetched, only looked up when nothing else is
+    prefetch_buffer: VecDeque<SteamID>,
+    prefetch_queued: bool,
+    next_prefetch: Instant,
 }
 
 impl LookupProfiles {
     #[must_use]
-    pub const fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             batch_buffer: VecDeque::new(),
             in_progress: Vec::new(),
+            retries: HashMap::new(),
+            given_up: HashMap::new(),
+            prefetch_buffer: VecDeque::new(),
+            prefetch_queued: false,
+            next_prefetch: Instant::now(),
+        }
+    }
+
+    /// Queues up every account the user has given a verdict to (up to a limit)
+    /// so their profiles are available without them having to join a server.
+    fn queue_prefetch(&mut self, state: &MACState) {
+        self.prefetch_queued = true;
+
+        self.prefetch_buffer.extend(
+            state
+                .players
+                .records
+                .iter()
+                .filter(|(_, r)| r.verdict() != Verdict::Player)
+                .map(|(&s, _)| s)
+                .filter(|s| {
+                    !state
+                        .players
+                        .steam_info
+                        .get(s)
+                        .is_some_and(|si| !si.expired(state.settings.steam_cache_ttl()))
+                })
+                .take(MAX_PREFETCH),
+        );
+
+        tracing::info!(
+            "Prefetching profiles of {} players.",
+            self.prefetch_buffer.len()
+        );
+    }
+
+    /// Moves the next batch of prefetched profiles into the batch buffer, as
+    /// long as there isn't anything more important waiting and we haven't sent
+    /// one too recently.
+    fn queue_due_prefetch(&mut self) {
+        let now = Instant::now();
+        if !self.batch_buffer.is_empty() || self.next_prefetch > now {
+            return;
+        }
+
+        let len = BATCH_SIZE.min(self.prefetch_buffer.len());
+        if len == 0 {
+            return;
+        }
+
+        self.batch_buffer.extend(self.prefetch_buffer.drain(0..len));
+        self.next_prefetch = now + PREFETCH_INTERVAL;
+    }
+
+    /// Schedules another attempt at any profiles that individually failed to be
+    /// looked up, backing off after each failure until giving up.
+    fn handle_lookup_result(&mut self, result: &ProfileLookupResult) {
+        let Ok(results) = &result.0 else {
+            // The result doesn't say which batch failed, so free up everything
+            // being looked up. At worst some profiles get requested twice.
+            self.in_progress.clear();
+            return;
+        };
+
+        for (steamid, result) in results {
+            self.in_progress.retain(|s| s != steamid);
+
+            if result.is_ok() {
+                self.retries.remove(steamid);
+                self.given_up.remove(steamid);
+                continue;
+            }
+
+            let retry = self.retries.entry(*steamid).or_insert(ProfileRetry {
+                attempts: 0,
+                next_attempt: None,
+            });
+            retry.attempts += 1;
+
+            if retry.attempts > MAX_PROFILE_RETRIES {
+                tracing::warn!(
+                    "Giving up on looking up profile {} after {MAX_PROFILE_RETRIES} retries.",
+                    u64::from(*steamid)
+                );
+                self.retries.remove(steamid);
+                self.given_up
+                    .insert(*steamid, Instant::now() + PROFILE_GIVE_UP_COOLDOWN);
+                continue;
+            }
+
+            let backoff = PROFILE_RETRY_BACKOFF * 2u32.pow(retry.attempts - 1);
+            retry.next_attempt = Some(Instant::now() + backoff);
+            tracing::debug!(
+                "Retrying profile lookup for {} in {}s",
+                u64::from(*steamid),
+                backoff.as_secs()
+            );
+        }
+    }
+
+    /// Re-queues connected players whose cached profile has expired, leaving
+    /// out any already waiting on a lookup or a retry, or recently given up on.
+    fn queue_expired(&mut self, state: &MACState) {
+        for s in &state.players.connected {
+            if !state
+                .players
+                .steam_info
+                .get(s)
+                .is_some_and(|si| si.expired(state.settings.steam_cache_ttl()))
+            {
+                continue;
+            }
+
+            if self.in_progress.contains(s)
+                || self.retries.contains_key(s)
+                || self.given_up.contains_key(s)
+                || self.batch_buffer.contains(s)
+            {
+                continue;
+            }
+
+            self.batch_buffer.push_back(*s);
+        }
+    }
+
+    /// Moves any retries that are due into the batch buffer, and forgets
+    /// about give-ups which have cooled down.
+    fn queue_due_retries(&mut self) {
+        let now = Instant::now();
+        self.given_up.retain(|_, not_before| *not_before > now);
+        for (steamid, retry) in &mut self.retries {
+            if retry.next_attempt.is_some_and(|t| t <= now) {
+                retry.next_attempt = None;
+                self.batch_buffer.push_back(*steamid);
+            }
         }
     }
 }
@@ -126,10 +432,19 @@ impl Default for LookupProfiles {
 
 impl<IM, OM> MessageHandler<MACState, IM, OM> for LookupProfiles
 where
-    IM: Is<NewPlayers> + Is<ProfileLookupBatchTick> + Is<Preferences> + Is<ProfileLookupRequest>,
+    IM: Is<NewPlayers>
+        + Is<ProfileLookupBatchTick>
+        + Is<Preferences>
+        + Is<ProfileLookupRequest>
+        + Is<ProfileLookupResult>,
     OM: Is<ProfileLookupResult>,
 {
     fn handle_message(&mut self, state: &MACState, message: &IM) -> Option<Handled<OM>> {
+        if let Some(result) = try_get::<ProfileLookupResult>(message) {
+            self.handle_lookup_result(result);
+            return Handled::none();
+        }
+
         // Re-request connected players if the API key has changed
         if let Some(Preferences {
             internal:
@@ -168,9 +483,16 @@ where
 
         // Send of lookup batch
         if try_get::<ProfileLookupBatchTick>(message).is_some() {
+            if !self.prefetch_queued && state.settings.prefetch_record_profiles() {
+                self.queue_prefetch(state);
+            }
+
+            self.queue_due_retries();
+            self.queue_expired(state);
+
             self.batch_buffer.retain(|s| {
-                // Already retrieving
-                if self.in_progress.contains(s) {
+                // Already retrieving, or gave up on recently
+                if self.in_progress.contains(s) || self.given_up.contains_key(s) {
                     return false;
                 }
 
@@ -179,13 +501,15 @@ where
                     .players
                     .steam_info
                     .get(s)
-                    .is_some_and(|si| !si.expired())
+                    .is_some_and(|si| !si.expired(state.settings.steam_cache_ttl()))
             });
+            self.queue_due_prefetch();
             if self.batch_buffer.is_empty() {
                 return Handled::none();
             }
 
             let key = state.settings.steam_api_key().to_owned();
+            let limiter = state.steam_api_limiter.clone();
             let batch: Vec<_> = self
                 .batch_buffer
                 .drain(0..BATCH_SIZE.min(self.batch_buffer.len()))
@@ -195,7 +519,10 @@ where
 
             return Handled::future(async move {
                 let client = SteamAPI::new(key);
-                Some(ProfileLookupResult(request_steam_info(&client, &batch).await).into())
+                Some(
+                    ProfileLookupResult(request_steam_info(&client, &limiter, &batch).await, None)
+                        .into(),
+                )
             });
         }
 
@@ -218,17 +545,19 @@ impl LookupFriends {
     fn lookup_players<'a, M: Is<FriendLookupResult>>(
         &mut self,
         key: &str,
+        limiter: &SteamApiLimiter,
         players: impl IntoIterator<Item = &'a SteamID>,
     ) -> Option<Handled<M>> {
         Handled::multiple(players.into_iter().map(|&p| {
             self.in_progess.push(p);
             let key = key.to_owned();
+            let limiter = limiter.clone();
             Handled::future(async move {
                 let client = SteamAPI::new(key);
                 Some(
                     FriendLookupResult {
                         steamid: p,
-                        result: request_account_friends(&client, p).await,
+                        result: request_account_friends(&client, &limiter, p).await,
                     }
                     .into(),
                 )
@@ -251,6 +580,57 @@ impl LookupFriends {
         key: &str,
         force: bool,
     ) -> Option<Handled<M>> {
+        let mut queued_friendlist_req = Self::select_players(state, players, policy, force);
+
+        queued_friendlist_req.retain(|s| {
+            !state
+                .players
+                .friend_info
+                .get(s)
+                .is_some_and(|f| f.public.is_some())
+                && !self.in_progess.contains(s)
+        });
+
+        if queued_friendlist_req.is_empty() {
+            return Handled::none();
+        }
+
+        self.lookup_players(key, &state.steam_api_limiter, &queued_friendlist_req)
+    }
+
+    /// Looks up the friends of every connected player that fits the friend
+    /// lookup policy again after the friends cache has been cleared.
+    fn handle_cleared<M: Is<FriendLookupResult>>(
+        &mut self,
+        state: &MACState,
+    ) -> Option<Handled<M>> {
+        let mut queued_friendlist_req = Self::select_players(
+            state,
+            &state.players.connected,
+            state.settings.friends_api_usage(),
+            false,
+        );
+        queued_friendlist_req.retain(|s| !self.in_progess.contains(s));
+
+        if queued_friendlist_req.is_empty() {
+            return Handled::none();
+        }
+
+        self.lookup_players(
+            state.settings.steam_api_key(),
+            &state.steam_api_limiter,
+            &queued_friendlist_req,
+        )
+    }
+
+    /// Picks out the players whose friends should be looked up under the
+    /// given policy.
+    fn select_players<'a>(
+        state: &MACState,
+        players: impl IntoIterator<Item = &'a SteamID>,
+        policy: FriendsAPIUsage,
+        force: bool,
+    ) -> Vec<SteamID> {
         // Need all friends if there's a cheater/bot on the server with a private
         // friends list
         let need_all_friends = force
@@ -291,20 +671,51 @@ impl LookupFriends {
             }
         }
 
-        queued_friendlist_req.retain(|s| {
-            !state
-                .players
-                .friend_info
-                .get(s)
-                .is_some_and(|f| f.public.is_some())
-                && !self.in_progess.contains(s)
-        });
+        queued_friendlist_req
+    }
 
-        if queued_friendlist_req.is_empty() {
-            return Handled::none();
+    /// Looks up a single player's friends list on request of the user,
+    /// ignoring the friend lookup policy. Responds with whether a lookup was
+    /// actually started.
+    fn handle_requested_lookup<M: Is<FriendLookupResult>>(
+        &mut self,
+        state: &MACState,
+        steamid: SteamID,
+        tx: &UnboundedSender<WebResponse>,
+    ) -> Option<Handled<M>> {
+        #[derive(Serialize)]
+        struct Response {
+            initiated: bool,
+            reason: Option<&'static str>,
+        }
+
+        let (out, reason) = if state.settings.steam_api_key().is_empty() {
+            (Handled::none(), Some("No Steam API key is set."))
+        } else if self.in_progess.contains(&steamid) {
+            (Handled::none(), Some("A lookup is already in progress."))
+        } else {
+            (
+                self.lookup_players(
+                    state.settings.steam_api_key(),
+                    &state.steam_api_limiter,
+                    &[steamid],
+                ),
+                None,
+            )
+        };
+
+        let response = Response {
+            initiated: reason.is_none(),
+            reason,
+        };
+        if tx
+            .send(serde_json::to_string(&response).map_err(WebError::from))
+            .is_err()
+        {
+            tracing::error!("Failed to send response to API task.");
         }
 
-        self.lookup_players(key, &queued_friendlist_req)
+        out
     }
 }
 
@@ -316,14 +727,29 @@ impl Default for LookupFriends {
 
 impl<IM, OM> MessageHandler<MACState, IM, OM> for LookupFriends
 where
-    IM: Is<NewPlayers> + Is<FriendLookupResult> + Is<UserUpdates> + Is<Preferences>,
+    IM: Is<NewPlayers>
+        + Is<ClearCaches>
+        + Is<FriendLookupResult>
+        + Is<UserUpdates>
+        + Is<Preferences>
+        + Is<WebRequest>,
     OM: Is<FriendLookupResult>,
 {
     fn handle_message(&mut self, state: &MACState, message: &IM) -> Option<Handled<OM>> {
+        if let Some(WebRequest::LookupFriends(steamid, tx)) = try_get(message) {
+            return self.handle_requested_lookup(state, *steamid, tx);
+        }
+
         if state.settings.steam_api_key().is_empty() {
             return Handled::none();
         }
 
+        // The cache is only emptied once this message reaches the state, so
+        // the stale friends lists mustn't stop the lookups here
+        if let Some(ClearCaches { friends: true, .. }) = try_get(message) {
+            return self.handle_cleared(state);
+        }
+
         if let Some(NewPlayers(new_players)) = try_get(message) {
             return self.handle_players(
                 state,
@@ -437,12 +863,14 @@ where
 /// found or failed to parse.
 pub async fn request_steam_info(
     client: &SteamAPI,
+    limiter: &SteamApiLimiter,
     playerids: &[SteamID],
 ) -> Result<Vec<(SteamID, Result<SteamInfo, SteamAPIError>)>, SteamAPIError> {
     tracing::debug!("Requesting steam accounts: {:?}", playerids);
 
-    let summaries = request_player_summary(client, playerids).await?;
-    let bans = request_account_bans(client, playerids).await?;
+    let summaries =
+        retry_rate_limited(limiter, || request_player_summary(client, playerids)).await?;
+    let bans = retry_rate_limited(limiter, || request_account_bans(client, playerids)).await?;
 
     let id_to_summary: HashMap<_, _> = summaries
         .into_iter()
@@ -492,20 +920,72 @@ pub async fn request_steam_info(
         .collect())
 }
 
+/// Makes a Steam API call, retrying with a backoff if Steam responds that
+/// we're being rate limited. Honors `Retry-After` when it's available.
+async fn retry_rate_limited<T, F, Fut>(
+    limiter: &SteamApiLimiter,
+    mut request: F,
+) -> Result<T, SteamAPIError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, SteamAPIError>>,
+{
+    let mut attempt = 0;
+    loop {
+        limiter.acquire().await;
+        let e = match request().await {
+            Ok(t) => return Ok(t),
+            Err(e) if attempt < RATE_LIMIT_RETRIES && is_rate_limited(&e) => e,
+            Err(e) => return Err(e),
+        };
+
+        let wait = retry_after(&e)
+            .unwrap_or_else(|| RATE_LIMIT_BACKOFF.saturating_mul(2u32.saturating_pow(attempt)))
+            .min(MAX_RATE_LIMIT_BACKOFF);
+        attempt += 1;
+        tracing::warn!(
+            "Steam API rate limited, retrying in {}s ({attempt}/{RATE_LIMIT_RETRIES})",
+            wait.as_secs()
+        );
+        tokio::time::sleep(wait).await;
+    }
+}
+
+fn is_rate_limited(e: &SteamAPIError) -> bool {
+    matches!(e, SteamAPIError::Status { status, .. } if *status == StatusCode::TOO_MANY_REQUESTS)
+}
+
+/// How long Steam asked us to wait, if it said
+const fn retry_after(e: &SteamAPIError) -> Option<Duration> {
+    match e {
+        SteamAPIError::Status { retry_after, .. } => *retry_after,
+        _ => None,
+    }
+}
+
+/// Reads a `Retry-After` header given in seconds. Steam doesn't send the
+/// HTTP date form.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
 async fn request_player_summary(
     client: &SteamAPI,
     players: &[SteamID],
 ) -> Result<Vec<PlayerSummary>, SteamAPIError> {
+    let steamids = join_steamids(players);
     let summaries = client
-        .get()
-        .ISteamUser()
-        .GetPlayerSummaries(
-            players
-                .iter()
-                .map(|player| format!("{}", u64::from(*player)))
-                .collect(),
+        .get(
+            "ISteamUser/GetPlayerSummaries/v2",
+            &[("steamids", &steamids)],
         )
-        .execute()
         .await?;
     let summaries = serde_json::from_str::<GetPlayerSummariesResponseBase>(&summaries)?;
     Ok(summaries.response.players)
@@ -516,17 +996,20 @@ async fn request_player_summary(
 /// or the account does not exist.
 pub async fn request_account_friends(
     client: &SteamAPI,
+    limiter: &SteamApiLimiter,
     player: SteamID,
 ) -> Result<Vec<Friend>, SteamAPIError> {
     tracing::debug!(
         "Requesting friends list from Steam API for {}",
         u64::from(player)
     );
+    limiter.acquire().await;
+    let steamid = u64::from(player).to_string();
     let friends = client
-        .get()
-        .ISteamUser()
-        .GetFriendList(player.into(), "all".to_string())
-        .execute()
+        .get(
+            "ISteamUser/GetFriendList/v1",
+            &[("steamid", &steamid), ("relationship", "all")],
+        )
         .await?;
     let friends = serde_json::from_str::<GetFriendListResponseBase>(&friends)?;
     Ok(friends
@@ -548,17 +1031,69 @@ async fn request_account_bans(
     client: &SteamAPI,
     players: &[SteamID],
 ) -> Result<Vec<PlayerBans>, SteamAPIError> {
+    let steamids = join_steamids(players);
     let bans = client
-        .get()
-        .ISteamUser()
-        .GetPlayerBans(
-            players
-                .iter()
-                .map(|player| format!("{}", u64::from(*player)))
-                .collect(),
-        )
-        .execute()
+        .get("ISteamUser/GetPlayerBans/v1", &[("steamids", &steamids)])
         .await?;
     let bans = serde_json::from_str::<GetPlayerBansResponseBase>(&bans)?;
     Ok(bans.players)
 }
+
+/// The comma separated list of ids the batch endpoints take
+fn join_steamids(players: &[SteamID]) -> String {
+    players
+        .iter()
+        .map(|player| u64::from(*player).to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use reqwest::{
+        header::{HeaderMap, HeaderValue, RETRY_AFTER},
+        StatusCode,
+    };
+
+    use super::{is_rate_limited, parse_retry_after, retry_after, SteamAPIError};
+
+    #[test]
+    pub fn rate_limit_from_status() {
+        let limited = SteamAPIError::Status {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            retry_after: Some(Duration::from_secs(7)),
+        };
+        assert!(is_rate_limited(&limited));
+        assert_eq!(retry_after(&limited), Some(Duration::from_secs(7)));
+
+        let failed = SteamAPIError::Status {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            retry_after: None,
+        };
+        assert!(!is_rate_limited(&failed));
+        assert_eq!(retry_after(&failed), None);
+
+        // Error text mentioning 429 isn't mistaken for a rate limit
+        let serde = SteamAPIError::Serde(
+            serde_json::from_str::<u32>("\"429 Too Many Requests\"").unwrap_err(),
+        );
+        assert!(!is_rate_limited(&serde));
+    }
+
+    #[test]
+    pub fn retry_after_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("30"));
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(30)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+}
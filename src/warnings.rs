@@ -0,0 +1,127 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::web::broadcast_event;
+
+/// Problems that the user needs to do something about, which should be shown
+/// in the UI rather than only ending up in the log. The `code` lets the
+/// frontend show specific help for each one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "code", rename_all = "camelCase")]
+pub enum AppWarning {
+    /// TF2 rejected the RCON password
+    RconAuthFailed,
+    /// The web server's port is taken, most likely by another copy of the
+    /// client
+    PortInUse { port: u16 },
+    /// The web server couldn't be started for any other reason
+    WebServerFailed { reason: String },
+}
+
+impl AppWarning {
+    /// A human readable description of the problem and how to fix it
+    #[must_use]
+    pub fn message(&self) -> String {
+        match self {
+            Self::RconAuthFailed => "TF2 rejected the RCON password. Make sure the rcon_password \
+                                     in your launch options matches the one in the settings."
+                .to_string(),
+            Self::PortInUse { port } => format!(
+                "Port {port} is already in use. The client may already be running, or another \
+                 application is using the port."
+            ),
+            Self::WebServerFailed { reason } => format!("Failed to start web server: {reason}"),
+        }
+    }
+}
+
+/// Sent to SSE subscribers when a warning is raised, in the same shape as the
+/// other SSE events.
+#[derive(Serialize)]
+struct WarningEvent<'a> {
+    #[serde(rename = "type")]
+    event_type: &'static str,
+    uuid: Uuid,
+    time: DateTime<Utc>,
+    event: WarningBody<'a>,
+}
+
+#[derive(Serialize)]
+struct WarningBody<'a> {
+    #[serde(flatten)]
+    warning: &'a AppWarning,
+    message: String,
+}
+
+/// Warnings which are still relevant, so a UI that connects later can still
+/// show them.
+static ACTIVE_WARNINGS: Mutex<Vec<AppWarning>> = Mutex::new(Vec::new());
+
+/// Logs a warning and, if it isn't already active, broadcasts it to any
+/// connected UIs.
+///
+/// # Panics
+/// If the warnings lock was poisoned
+pub fn raise(warning: AppWarning) {
+    tracing::error!("{}", warning.message());
+
+    {
+        let mut active = ACTIVE_WARNINGS.lock().expect("Warnings lock poisoned");
+        if active.contains(&warning) {
+            return;
+        }
+        active.push(warning.clone());
+    }
+
+    let event = WarningEvent {
+        event_type: "AppWarning",
+        uuid: Uuid::new_v4(),
+        time: Utc::now(),
+        event: WarningBody {
+            message: warning.message(),
+            warning: &warning,
+        },
+    };
+    let json = serde_json::to_string(&event).expect("Serialisation failure");
+
+    // There's no one to broadcast to if the async runtime isn't running yet
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        handle.spawn(broadcast_event(json));
+    }
+}
+
+/// Removes a warning once the problem has gone away.
+///
+/// # Panics
+/// If the warnings lock was poisoned
+pub fn resolve(warning: &AppWarning) {
+    ACTIVE_WARNINGS
+        .lock()
+        .expect("Warnings lock poisoned")
+        .retain(|w| w != warning);
+}
+
+/// All the warnings that are currently active, along with their messages
+///
+/// # Errors
+/// If the warnings could not be serialized
+///
+/// # Panics
+/// If the warnings lock was poisoned
+pub fn active_json() -> serde_json::Result<String> {
+    let active = ACTIVE_WARNINGS
+        .lock()
+        .expect("Warnings lock poisoned")
+        .clone();
+    let bodies: Vec<WarningBody> = active
+        .iter()
+        .map(|warning| WarningBody {
+            warning,
+            message: warning.message(),
+        })
+        .collect();
+    serde_json::to_string(&bodies)
+}
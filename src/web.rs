@@ -1,70 +1,168 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     convert::Infallible,
-    net::SocketAddr,
+    io::{Read, Seek, SeekFrom},
+    net::{IpAddr, SocketAddr},
     path::{Path, PathBuf},
     sync::Arc,
 };
 
 use axum::{
-    extract::{Query, State},
+    extract::{
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
     http::{header, StatusCode},
-    response::{sse::Event, IntoResponse, Redirect, Sse},
-    routing::{get, post, put},
+    response::{sse::Event, IntoResponse, Redirect, Response, Sse},
+    routing::{delete, get, post, put},
     Json, Router,
 };
 use chrono::{DateTime, Utc};
 use event_loop::{try_get, Handled, Is, MessageHandler};
-use futures::Stream;
+use futures::{SinkExt, Stream, StreamExt};
 use include_dir::Dir;
 use serde::{Deserialize, Serialize};
 use steamid_ng::SteamID;
-use tappet::SteamAPI;
+use thiserror::Error;
 use tokio::sync::{
     mpsc::{UnboundedReceiver, UnboundedSender},
-    Mutex,
+    Mutex, RwLock,
 };
 use tokio_stream::wrappers::ReceiverStream;
 
 use super::command_manager::Command;
 use crate::{
-    events::{InternalPreferences, Preferences, UserUpdate, UserUpdates},
-    player::{serialize_steamid_as_string, Friend, FriendInfo, Player, Players, SteamInfo},
-    server::Gamemode,
+    avatars::{avatar_path, locate_avatar_cache_directory},
+    events::{
+        ClearCaches, DeleteRecords, InternalPreferences, PinPlayer, Preferences, RecomputeParties,
+        Refresh, RefreshReason, UserUpdate, UserUpdates,
+    },
+    parties::{party_id, Parties},
+    player::{
+        serialize_maybe_steamid_as_string, serialize_steamid_as_string, Friend, FriendInfo, Player,
+        Players, SteamInfo, Team,
+    },
+    player_records::Verdict,
+    server::{Gamemode, MvmState},
+    settings::{validate_display_styles, LOG_FILE_PATH},
     state::MACState,
-    steam_api::{request_steam_info, ProfileLookupResult},
+    steam_api::{request_steam_info, ProfileLookupRequest, ProfileLookupResult, SteamAPI},
+    warnings::{self, AppWarning},
 };
 const HEADERS: [(header::HeaderName, &str); 2] = [
     (header::CONTENT_TYPE, "application/json"),
     (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
 ];
 
+/// Errors encountered by the main thread while responding to an API request
+#[derive(Debug, Error)]
+#[allow(clippy::module_name_repetitions)]
+pub enum WebError {
+    #[error("Failed to serialize response: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("This endpoint is disabled. {0}")]
+    Disabled(&'static str),
+    #[error("Invalid request: {0}")]
+    BadRequest(String),
+    #[error("Missing or invalid token")]
+    Unauthorized,
+}
+
+impl IntoResponse for WebError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::Serialization(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Disabled(_) => StatusCode::FORBIDDEN,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+        };
+        // Bad requests are the client's problem and can be triggered at will,
+        // so they shouldn't fill the log
+        if status.is_server_error() {
+            tracing::error!("Failed to respond to API request: {self}");
+        } else {
+            tracing::debug!("Rejected API request ({status}): {self}");
+        }
+        let body = serde_json::json!({ "error": self.to_string() }).to_string();
+        (status, HEADERS, body).into_response()
+    }
+}
+
+/// The response to an API request, as produced by the main thread
+#[allow(clippy::module_name_repetitions)]
+pub type WebResponse = Result<String, WebError>;
+
 #[derive(Debug)]
 #[allow(clippy::module_name_repetitions)]
 pub enum WebRequest {
     /// Retrieve info on the active game
-    GetGame(UnboundedSender<String>),
+    GetGame(UnboundedSender<WebResponse>),
+    /// Retrieve info on the local user
+    GetSelf(UnboundedSender<WebResponse>),
     /// Retrieve info on specific accounts
-    PostUser(UserPostRequest, UnboundedSender<String>),
+    PostUser(UserPostRequest, UnboundedSender<WebResponse>),
     /// Set Verdict and customData for specific accounts
     PutUser(HashMap<SteamID, UserUpdate>),
+    /// Remove the records of specific accounts, retrieving how many existed
+    DeleteUser(Vec<SteamID>, UnboundedSender<WebResponse>),
+    /// Keep an account at the top of the player list for this session
+    PinPlayer(SteamID),
+    UnpinPlayer(SteamID),
     /// Retrieve client preferences
-    GetPrefs(UnboundedSender<String>),
+    GetPrefs(UnboundedSender<WebResponse>),
     /// Set client preferences
     PutPrefs(Preferences),
+    /// Retrieve the settings in use after all overrides are applied
+    GetEffectiveSettings(UnboundedSender<WebResponse>),
     /// Retrieve a range of player history
-    GetHistory(Pagination, UnboundedSender<String>),
+    GetHistory(Pagination, UnboundedSender<WebResponse>),
+    /// Retrieve a range of player history along with how much history there
+    /// is in total
+    GetHistoryPage(Pagination, UnboundedSender<WebResponse>),
     /// Retrieve the current playerlist
-    GetPlayerlist(UnboundedSender<String>),
+    GetPlayerlist(UnboundedSender<WebResponse>),
     /// Tell the client to execute console commands
     PostCommand(RequestedCommands),
-    GetChat(UnboundedSender<String>),
-    GetKillfeed(UnboundedSender<String>),
+    GetChat(UnboundedSender<WebResponse>),
+    GetKillfeed(UnboundedSender<WebResponse>),
+    /// Fetch the friends list of an account regardless of the friends API policy
+    LookupFriends(SteamID, UnboundedSender<WebResponse>),
+    /// Find accounts that might belong to the same person as this one
+    GetPossibleAlts(SteamID, UnboundedSender<WebResponse>),
+    /// Empty the requested caches so they are fetched again
+    ClearCaches(ClearCaches, UnboundedSender<WebResponse>),
+    /// Retrieve the parties found among the connected players
+    GetParties(UnboundedSender<WebResponse>),
+    /// Run party detection again and retrieve the parties found
+    RecomputeParties(UnboundedSender<WebResponse>),
+    /// Broadcast a made up event to all SSE subscribers
+    TestEvent(UnboundedSender<WebResponse>),
+    /// Find players by part of their current or previous names, or their
+    /// SteamID64
+    SearchPlayers(String, UnboundedSender<WebResponse>),
+    /// Retrieve the players who joined within the given number of seconds
+    GetRecentlyConnected(u64, UnboundedSender<WebResponse>),
+    /// Retrieve the scoreboard as plain text
+    GetScoreboard(UnboundedSender<WebResponse>),
+    /// Retrieve how many Steam API calls can still be made this minute
+    GetSteamApiBudget(UnboundedSender<WebResponse>),
+    /// Fetch the player list from the game now rather than waiting
+    Refresh,
+    /// Retrieve the counters for this session
+    GetSessionStats(UnboundedSender<WebResponse>),
+    /// Retrieve counters covering the whole time the client has been running
+    GetMetrics(UnboundedSender<WebResponse>),
+    /// Set the session counters back to 0, retrieving what they were before
+    ResetSessionStats(UnboundedSender<WebResponse>),
+    /// List the demos that have been recorded
+    GetDemos(UnboundedSender<WebResponse>),
 }
 impl<S> event_loop::Message<S> for WebRequest {}
 
 struct PostUserRequest {
-    send: UnboundedSender<String>,
+    /// Matches up the profile lookups made for this request
+    id: u64,
+    send: UnboundedSender<WebResponse>,
     users: Vec<(SteamID, Option<SteamInfo>)>,
     waiting_users: Vec<SteamID>,
 }
@@ -73,12 +171,22 @@ struct PostUserRequest {
 pub struct WebAPIHandler {
     profile_requests_in_progress: Vec<SteamID>,
     post_user_queue: Vec<PostUserRequest>,
+    next_request_id: u64,
 }
 
 impl<IM, OM> MessageHandler<MACState, IM, OM> for WebAPIHandler
 where
     IM: Is<WebRequest> + Is<ProfileLookupResult>,
-    OM: Is<Command> + Is<Preferences> + Is<UserUpdates> + Is<ProfileLookupResult>,
+    OM: Is<Command>
+        + Is<Preferences>
+        + Is<UserUpdates>
+        + Is<DeleteRecords>
+        + Is<ProfileLookupResult>
+        + Is<ProfileLookupRequest>
+        + Is<ClearCaches>
+        + Is<PinPlayer>
+        + Is<RecomputeParties>
+        + Is<Refresh>,
 {
     #[allow(clippy::cognitive_complexity)]
     fn handle_message(
@@ -86,8 +194,8 @@ where
         state: &MACState,
         message: &IM,
     ) -> Option<event_loop::Handled<OM>> {
-        fn send(tx: &UnboundedSender<String>, payload: String) {
-            if tx.send(payload).is_err() {
+        fn send(tx: &UnboundedSender<WebResponse>, payload: serde_json::Result<String>) {
+            if tx.send(payload.map_err(WebError::from)).is_err() {
                 tracing::error!("Failed to send response to API task.");
             }
         }
@@ -100,21 +208,51 @@ where
             WebRequest::GetGame(tx) => {
                 send(tx, get_game_response(state));
             }
+            WebRequest::GetSelf(tx) => {
+                send(tx, get_self_response(state));
+            }
+            WebRequest::GetPossibleAlts(steamid, tx) => {
+                send(tx, get_possible_alts_response(state, *steamid));
+            }
             WebRequest::PostUser(users, tx) => {
                 return self.handle_post_user_request(state, users, tx.clone());
             }
             WebRequest::PutUser(users) => {
                 return Handled::single(OM::from(UserUpdates(users.clone())));
             }
+            WebRequest::DeleteUser(users, tx) => {
+                let unique: HashSet<&SteamID> = users.iter().collect();
+                let deleted = unique
+                    .into_iter()
+                    .filter(|s| state.players.records.contains_key(s))
+                    .count();
+                send(
+                    tx,
+                    serde_json::to_string(&serde_json::json!({ "deleted": deleted })),
+                );
+                return Handled::single(OM::from(DeleteRecords(users.clone())));
+            }
+            WebRequest::PinPlayer(steamid) => {
+                return Handled::single(OM::from(PinPlayer::Pin(*steamid)));
+            }
+            WebRequest::UnpinPlayer(steamid) => {
+                return Handled::single(OM::from(PinPlayer::Unpin(*steamid)));
+            }
             WebRequest::GetPrefs(tx) => {
                 send(tx, get_prefs_response(state));
             }
             WebRequest::PutPrefs(prefs) => {
                 return Handled::single(OM::from(prefs.clone()));
             }
+            WebRequest::GetEffectiveSettings(tx) => {
+                send(tx, get_effective_settings_response(state));
+            }
             WebRequest::GetHistory(page, tx) => {
                 send(tx, get_history_response(state, page));
             }
+            WebRequest::GetHistoryPage(page, tx) => {
+                send(tx, get_history_page_response(state, page));
+            }
             WebRequest::GetPlayerlist(tx) => {
                 send(tx, get_playerlist_response(state));
             }
@@ -129,6 +267,88 @@ where
             WebRequest::GetKillfeed(tx) => {
                 send(tx, get_killfeed_response(state));
             }
+            // Handled by `LookupFriends`
+            WebRequest::LookupFriends(..) => {}
+            // Handled by `DemoManager`
+            WebRequest::GetDemos(_) => {}
+            WebRequest::ClearCaches(clear, tx) => {
+                send(tx, serde_json::to_string(clear));
+
+                let mut out = vec![Handled::single(OM::from(*clear))];
+                // Fetch fresh profiles for everyone still on the server
+                if clear.steam_info && !state.players.connected.is_empty() {
+                    out.push(Handled::single(OM::from(ProfileLookupRequest::Multiple(
+                        state.players.connected.clone(),
+                    ))));
+                }
+                return Handled::multiple(out);
+            }
+            WebRequest::TestEvent(tx) => {
+                if !state.settings.debug_endpoints() {
+                    if tx
+                        .send(Err(WebError::Disabled(
+                            "Launch with --debug-endpoints to enable it.",
+                        )))
+                        .is_err()
+                    {
+                        tracing::error!("Failed to send response to API task.");
+                    }
+                    return Handled::none();
+                }
+
+                send(
+                    tx,
+                    serde_json::to_string(&serde_json::json!({ "sent": true })),
+                );
+                return Handled::future(async {
+                    broadcast_event(test_event_json()).await;
+                    None
+                });
+            }
+            WebRequest::SearchPlayers(query, tx) => {
+                send(tx, get_search_response(state, query));
+            }
+            WebRequest::GetRecentlyConnected(seconds, tx) => {
+                send(tx, get_recently_connected_response(state, *seconds));
+            }
+            WebRequest::GetScoreboard(tx) => {
+                send(tx, Ok(state.players.scoreboard_text()));
+            }
+            WebRequest::GetSteamApiBudget(tx) => {
+                send(tx, get_steam_api_budget_response(state));
+            }
+            WebRequest::GetSessionStats(tx) => {
+                send(tx, serde_json::to_string(&state.session_stats.snapshot()));
+            }
+            WebRequest::GetMetrics(tx) => {
+                let mut metrics = Metrics::from_state(state);
+                let tx = tx.clone();
+                // Counting the subscribers needs their lock, which is async
+                return Handled::future(async move {
+                    metrics.event_subscribers = subscriber_count().await;
+                    send(&tx, serde_json::to_string(&metrics));
+                    None
+                });
+            }
+            WebRequest::ResetSessionStats(tx) => {
+                send(tx, serde_json::to_string(&state.session_stats.reset()));
+            }
+            WebRequest::GetParties(tx) => {
+                send(tx, get_current_parties_response(&state.players.parties));
+            }
+            WebRequest::RecomputeParties(tx) => {
+                // The state won't be updated until after we respond, so find the
+                // parties here as well
+                let mut parties = Parties::new();
+                parties.find_parties(&state.players.friend_info, &state.players.connected);
+                send(tx, get_parties_response(&parties));
+                return Handled::single(OM::from(RecomputeParties));
+            }
+            WebRequest::Refresh => {
+                return Handled::single(Refresh {
+                    reason: RefreshReason::Manual,
+                });
+            }
         }
 
         Handled::none()
@@ -141,6 +361,7 @@ impl WebAPIHandler {
         Self {
             profile_requests_in_progress: Vec::new(),
             post_user_queue: Vec::new(),
+            next_request_id: 0,
         }
     }
 
@@ -148,13 +369,17 @@ impl WebAPIHandler {
         &mut self,
         state: &MACState,
         users: &UserPostRequest,
-        send: UnboundedSender<String>,
+        send: UnboundedSender<WebResponse>,
     ) -> Option<Handled<OM>> {
         if state.settings.steam_api_key().is_empty() {
             return None;
         }
 
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+
         let mut request = PostUserRequest {
+            id,
             send,
             users: users
                 .users
@@ -175,7 +400,7 @@ impl WebAPIHandler {
                         .players
                         .steam_info
                         .get(s)
-                        .map_or(true, SteamInfo::expired)
+                        .map_or(true, |si| si.expired(state.settings.steam_cache_ttl()))
             })
             .copied()
             .collect();
@@ -188,8 +413,15 @@ impl WebAPIHandler {
         let out = Handled::multiple(request.waiting_users.chunks(100).map(|accounts| {
             let accounts = accounts.to_vec();
             let client = client.clone();
+            let limiter = state.steam_api_limiter.clone();
             Handled::future(async move {
-                Some(ProfileLookupResult(request_steam_info(&client, &accounts).await).into())
+                Some(
+                    ProfileLookupResult(
+                        request_steam_info(&client, &limiter, &accounts).await,
+                        Some(id),
+                    )
+                    .into(),
+                )
             })
         }));
 
@@ -212,6 +444,7 @@ impl WebAPIHandler {
                 // because something has gone wrong.
                 self.post_user_queue
                     .iter_mut()
+                    .filter(|req| result.1.map_or(true, |request| request == req.id))
                     .for_each(|req| req.waiting_users.clear());
                 self.send_waiting_post_user_responses(state);
 
@@ -232,12 +465,17 @@ impl WebAPIHandler {
                 Ok(a) => a,
             };
 
-            self.post_user_queue.iter_mut().for_each(|req| {
-                if let Some((id, info)) = req.users.iter_mut().find(|(s, _)| s == id) {
-                    *info = Some(steam_info.clone());
-                    req.waiting_users.retain(|s| s != id);
-                }
-            });
+            // Results from lookups that weren't made for a particular request
+            // are offered to all of them
+            self.post_user_queue
+                .iter_mut()
+                .filter(|req| result.1.map_or(true, |request| request == req.id))
+                .for_each(|req| {
+                    if let Some((id, info)) = req.users.iter_mut().find(|(s, _)| s == id) {
+                        *info = Some(steam_info.clone());
+                        req.waiting_users.retain(|s| s != id);
+                    }
+                });
         }
 
         self.send_waiting_post_user_responses(state);
@@ -264,7 +502,7 @@ impl WebAPIHandler {
                     .collect();
 
                 req.send
-                    .send(serde_json::to_string(&users).expect("Epic serialization fail"))
+                    .send(serde_json::to_string(&users).map_err(WebError::from))
                     .ok();
             });
 
@@ -283,17 +521,29 @@ impl Default for WebAPIHandler {
 #[allow(clippy::module_name_repetitions)]
 pub struct WebState {
     pub request: UnboundedSender<WebRequest>,
-    pub ui: UISource,
+    /// Shared so it can be changed while the server is running, see
+    /// [`put_ui_source`]
+    pub ui: Arc<RwLock<UISource>>,
+    /// The directory given with `--web-dir`, which the UI can only be moved
+    /// within
+    ui_root: Option<Arc<Path>>,
+    /// Required to use the API, if set
+    pub token: Option<Arc<str>>,
 }
 
 impl WebState {
     #[must_use]
-    pub fn new(ui: &UISource) -> (Self, UnboundedReceiver<WebRequest>) {
+    pub fn new(ui: &UISource, token: Option<&str>) -> (Self, UnboundedReceiver<WebRequest>) {
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
         (
             Self {
                 request: tx,
-                ui: ui.clone(),
+                ui: Arc::new(RwLock::new(ui.clone())),
+                ui_root: match ui {
+                    UISource::Dynamic(dir) => dir.canonicalize().ok().map(Arc::from),
+                    UISource::Bundled(_) | UISource::None => None,
+                },
+                token: token.map(Arc::from),
             },
             rx,
         )
@@ -305,26 +555,65 @@ impl WebState {
 /// # Panics
 /// If the web server could not be started
 #[allow(clippy::module_name_repetitions)]
-pub async fn web_main(web_state: WebState, port: u16) {
+pub async fn web_main(web_state: WebState, bind_address: IpAddr, port: u16) {
+    // Kept out of the compression layer, which would hold back events until
+    // enough had been buffered
+    let events = Router::new()
+        .route(EVENTS_ROUTE, get(get_events))
+        .route(EVENTS_WS_ROUTE, get(get_events_ws));
+
     let api = Router::new()
         .route("/", get(ui_redirect))
         .route("/ui", get(ui_redirect))
         .route("/ui/*ui", get(get_ui))
+        .route("/mac/ui-source/v1", put(put_ui_source))
         .route("/mac/game/v1", get(get_game))
+        .route("/mac/self/v1", get(get_self))
         .route("/mac/user/v1", post(post_user))
         .route("/mac/user/v1", put(put_user))
+        .route("/mac/user/v1", delete(delete_user))
+        .route("/mac/user/bulk-verdict/v1", post(post_user_bulk_verdict))
+        .route("/mac/user/pin/v1", put(put_user_pin))
+        .route("/mac/user/friends/v1", post(post_user_friends))
+        .route("/mac/user/alts/v1", post(post_user_alts))
         .route("/mac/pref/v1", get(get_prefs))
         .route("/mac/pref/v1", put(put_prefs))
-        .route("/mac/game/events/v1", get(get_events))
+        .route("/mac/settings/effective/v1", get(get_effective_settings))
+        .route("/mac/events/test/v1", post(post_test_event))
         .route("/mac/history/v1", get(get_history))
+        .route("/mac/history/v2", get(get_history_page))
         .route("/mac/playerlist/v1", get(get_playerlist))
         .route("/mac/commands/v1", post(post_commands))
         .route("/mac/chat/v1", get(get_chat))
         .route("/mac/killfeed/v1", get(get_killfeed))
+        .route("/mac/avatar/v1", get(get_avatar))
+        .route("/mac/cache/clear/v1", post(post_clear_caches))
+        .route("/mac/parties/v1", get(get_parties))
+        .route("/mac/parties/v1", post(post_parties))
+        .route("/mac/refresh/v1", post(post_refresh))
+        .route("/mac/steamapi/budget/v1", get(get_steam_api_budget))
+        .route("/mac/stats/v1", get(get_session_stats))
+        .route("/mac/demos/v1", get(get_demos))
+        .route("/mac/stats/reset/v1", post(post_reset_session_stats))
+        .route("/mac/metrics/v1", get(get_metrics))
+        .route("/mac/scoreboard/v1", get(get_scoreboard))
+        .route("/mac/players/recent/v1", get(get_recently_connected))
+        .route("/mac/search/v1", get(get_search))
+        .route("/mac/logs/v1", get(get_logs))
+        .route("/mac/warnings/v1", get(get_warnings))
+        .layer(tower_http::compression::CompressionLayer::new())
+        .merge(events)
+        .layer(axum::middleware::from_fn_with_state(
+            web_state.clone(),
+            require_token,
+        ))
         .layer(tower_http::cors::CorsLayer::permissive())
-        .with_state(web_state);
+        .with_state(web_state.clone());
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let addr = SocketAddr::from((bind_address, port));
+    if !bind_address.is_loopback() && web_state.token.is_none() {
+        tracing::warn!("The web interface is being hosted on {bind_address}, so it can be reached from other machines. The API has no authentication, anyone who can reach it can control the client and read your settings. Set `webui_token` to require a token.");
+    }
     tracing::info!("Starting web interface at http://{addr}");
     match tokio::net::TcpListener::bind(&addr).await {
         Ok(builder) => {
@@ -332,14 +621,75 @@ pub async fn web_main(web_state: WebState, port: u16) {
                 .await
                 .expect("Server wasn't meant to exit.");
         }
+        // Without the web server there's no UI to show the warning in, so it
+        // only ends up in the log
         Err(e) => {
-            tracing::error!("Failed to start web server: {e}");
-            tracing::error!("This may occur if the app is already running.");
+            warnings::raise(if e.kind() == std::io::ErrorKind::AddrInUse {
+                AppWarning::PortInUse { port }
+            } else {
+                AppWarning::WebServerFailed {
+                    reason: e.to_string(),
+                }
+            });
             std::process::exit(1);
         }
     }
 }
 
+/// The event streams, which browsers connect to with `EventSource` and
+/// `WebSocket` that can't set headers
+const EVENTS_ROUTE: &str = "/mac/game/events/v1";
+const EVENTS_WS_ROUTE: &str = "/mac/game/events/ws/v1";
+
+/// Rejects requests to the API which don't provide the configured token as an
+/// `Authorization: Bearer` header. The event streams can't be given headers,
+/// so they take it as an `access_token` query parameter instead, which no
+/// other route accepts since it would end up in logs and browser history. The
+/// UI itself is always served so it can load and then supply the token.
+async fn require_token(
+    State(state): State<WebState>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let Some(token) = state.token.as_deref() else {
+        return next.run(request).await;
+    };
+    if !request.uri().path().starts_with("/mac/") {
+        return next.run(request).await;
+    }
+
+    let from_header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let streaming = matches!(request.uri().path(), EVENTS_ROUTE | EVENTS_WS_ROUTE);
+    let from_query = request.uri().query().filter(|_| streaming).and_then(|q| {
+        url::form_urlencoded::parse(q.as_bytes())
+            .find(|(k, _)| k == "access_token")
+            .map(|(_, v)| v.into_owned())
+    });
+
+    if from_header.is_some_and(|t| token_matches(t, token))
+        || from_query.is_some_and(|t| token_matches(&t, token))
+    {
+        next.run(request).await
+    } else {
+        WebError::Unauthorized.into_response()
+    }
+}
+
+/// Compares a provided token to the configured one in constant time, so how
+/// long the comparison takes doesn't give away how much of it was right
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
 async fn ui_redirect() -> impl IntoResponse {
     Redirect::permanent("/ui/index.html")
 }
@@ -350,7 +700,46 @@ async fn get_ui(
     State(state): State<WebState>,
     axum::extract::Path(path): axum::extract::Path<String>,
 ) -> impl IntoResponse {
-    state.ui.get_ui(&path).await
+    // Cloned so the lock isn't held while the file is read
+    let ui = state.ui.read().await.clone();
+    ui.get_ui(&path).await
+}
+
+#[derive(Debug, Deserialize)]
+struct UISourceRequest {
+    path: PathBuf,
+}
+
+/// Serves the UI from a different directory without restarting, e.g. to try
+/// out a new build of it. Only allowed when the UI is already being served
+/// from disk, since the bundled UI can't be switched back to. The new
+/// directory has to be inside the one given with `--web-dir`, otherwise any
+/// page could serve itself arbitrary files from the user's machine.
+async fn put_ui_source(
+    State(state): State<WebState>,
+    request: Json<UISourceRequest>,
+) -> Result<impl IntoResponse, WebError> {
+    tracing::debug!("API: PUT ui source");
+    let mut ui = state.ui.write().await;
+    let (UISource::Dynamic(_), Some(root)) = (&*ui, &state.ui_root) else {
+        return Err(WebError::Disabled(
+            "Launch with --web-dir to serve the UI from disk first.",
+        ));
+    };
+
+    let path = match root.join(&request.path).canonicalize() {
+        Ok(path) if path.is_dir() && path.starts_with(root) => path,
+        _ => {
+            return Err(WebError::BadRequest(format!(
+                "{:?} is not a directory inside {root:?}",
+                request.path
+            )));
+        }
+    };
+
+    tracing::info!("Serving web UI from {path:?}");
+    *ui = UISource::Dynamic(path);
+    Ok((StatusCode::OK, HEADERS))
 }
 
 #[derive(Clone, Debug, Default)]
@@ -462,21 +851,27 @@ fn guess_content_type(path: &Path) -> &'static str {
     }
 }
 
+/// Waits for the main thread to respond to an API request
+async fn await_response(mut rx: UnboundedReceiver<WebResponse>) -> Response {
+    match rx.recv().await {
+        Some(Ok(resp)) => (StatusCode::OK, HEADERS, resp).into_response(),
+        Some(Err(e)) => e.into_response(),
+        None => (StatusCode::SERVICE_UNAVAILABLE, HEADERS, String::new()).into_response(),
+    }
+}
+
 // Game
 
 async fn get_game(State(state): State<WebState>) -> impl IntoResponse {
     tracing::debug!("API: GET game");
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
     if state.request.send(WebRequest::GetGame(tx)).is_err() {
         tracing::error!("Couldn't send API request to main thread.");
     }
-    (rx.recv().await).map_or_else(
-        || (StatusCode::SERVICE_UNAVAILABLE, HEADERS, String::new()),
-        |resp| (StatusCode::OK, HEADERS, resp),
-    )
+    await_response(rx).await
 }
 
-fn get_game_response(state: &MACState) -> String {
+fn get_game_response(state: &MACState) -> serde_json::Result<String> {
     #[derive(Serialize)]
     #[allow(non_snake_case)]
     struct Game<'a> {
@@ -486,6 +881,7 @@ fn get_game_response(state: &MACState) -> String {
         maxPlayers: Option<u32>,
         numPlayers: Option<u32>,
         gamemode: Option<&'a Gamemode>,
+        mvm: Option<&'a MvmState>,
         players: &'a Players,
     }
 
@@ -496,10 +892,46 @@ fn get_game_response(state: &MACState) -> String {
         maxPlayers: state.server.max_players(),
         numPlayers: state.server.num_players(),
         gamemode: state.server.gamemode(),
+        mvm: state.server.mvm(),
         players: &state.players,
     };
 
-    serde_json::to_string(&game).expect("Epic serialization fail")
+    serde_json::to_string(&game)
+}
+
+// Self
+
+async fn get_self(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: GET self");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state.request.send(WebRequest::GetSelf(tx)).is_err() {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+fn get_self_response(state: &MACState) -> serde_json::Result<String> {
+    #[derive(Serialize)]
+    #[allow(non_snake_case)]
+    struct SelfResponse<'a> {
+        #[serde(serialize_with = "serialize_maybe_steamid_as_string")]
+        steamID64: Option<SteamID>,
+        name: Option<&'a str>,
+        team: Option<Team>,
+        connected: bool,
+    }
+
+    let user = state.players.user;
+    let response = SelfResponse {
+        steamID64: user,
+        name: user.and_then(|u| state.players.get_name(u)),
+        team: user
+            .and_then(|u| state.players.game_info.get(&u))
+            .map(|gi| gi.team),
+        connected: user.is_some_and(|u| state.players.connected.contains(&u)),
+    };
+
+    serde_json::to_string(&response)
 }
 
 // User
@@ -514,7 +946,7 @@ async fn post_user(
     users: Json<UserPostRequest>,
 ) -> impl IntoResponse {
     tracing::debug!("API: POST user");
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
     if state
         .request
         .send(WebRequest::PostUser(users.0, tx))
@@ -522,10 +954,7 @@ async fn post_user(
     {
         tracing::error!("Couldn't send API request to main thread.");
     }
-    (rx.recv().await).map_or_else(
-        || (StatusCode::SERVICE_UNAVAILABLE, HEADERS, String::new()),
-        |resp| (StatusCode::OK, HEADERS, resp),
-    )
+    await_response(rx).await
 }
 
 async fn put_user(
@@ -537,21 +966,440 @@ async fn put_user(
     (StatusCode::OK, HEADERS)
 }
 
+async fn delete_user(
+    State(state): State<WebState>,
+    users: Json<Vec<SteamID>>,
+) -> impl IntoResponse {
+    tracing::debug!("API: DELETE user");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state
+        .request
+        .send(WebRequest::DeleteUser(users.0, tx))
+        .is_err()
+    {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BulkVerdictRequest {
+    pub steamids: Vec<SteamID>,
+    /// Checked by hand so an unknown verdict is a 400 rather than a 422
+    pub verdict: String,
+}
+
+/// Marks many players with the same verdict at once, e.g. during a bot swarm
+async fn post_user_bulk_verdict(
+    State(state): State<WebState>,
+    request: Json<BulkVerdictRequest>,
+) -> Result<impl IntoResponse, WebError> {
+    tracing::debug!("API: POST user bulk verdict");
+    let Ok(verdict) =
+        serde_json::from_value::<Verdict>(serde_json::Value::String(request.verdict.clone()))
+    else {
+        return Err(WebError::BadRequest(format!(
+            "Unknown verdict {:?}",
+            request.verdict
+        )));
+    };
+
+    let users = request
+        .0
+        .steamids
+        .into_iter()
+        .map(|s| {
+            (
+                s,
+                UserUpdate {
+                    local_verdict: Some(verdict),
+                    custom_data: None,
+                    muted: None,
+                },
+            )
+        })
+        .collect();
+    state.request.send(WebRequest::PutUser(users)).ok();
+    Ok((StatusCode::OK, HEADERS))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PinRequest {
+    pub user: SteamID,
+    pub pinned: bool,
+}
+
+async fn put_user_pin(
+    State(state): State<WebState>,
+    request: Json<PinRequest>,
+) -> impl IntoResponse {
+    tracing::debug!("API: PUT user pin");
+    let request = if request.pinned {
+        WebRequest::PinPlayer(request.user)
+    } else {
+        WebRequest::UnpinPlayer(request.user)
+    };
+    state.request.send(request).ok();
+    (StatusCode::OK, HEADERS)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FriendsLookupRequest {
+    pub user: SteamID,
+}
+
+async fn post_user_friends(
+    State(state): State<WebState>,
+    request: Json<FriendsLookupRequest>,
+) -> impl IntoResponse {
+    tracing::debug!("API: POST user friends");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state
+        .request
+        .send(WebRequest::LookupFriends(request.user, tx))
+        .is_err()
+    {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+async fn post_user_alts(
+    State(state): State<WebState>,
+    request: Json<FriendsLookupRequest>,
+) -> impl IntoResponse {
+    tracing::debug!("API: POST user alts");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state
+        .request
+        .send(WebRequest::GetPossibleAlts(request.user, tx))
+        .is_err()
+    {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+/// Only worked out when asked for a single player, since it has to look
+/// through every record. Advisory only, see [`Players::possible_alts`].
+fn get_possible_alts_response(state: &MACState, steamid: SteamID) -> serde_json::Result<String> {
+    let alts: Vec<String> = state
+        .players
+        .possible_alts(steamid)
+        .into_iter()
+        .map(|s| u64::from(s).to_string())
+        .collect();
+    serde_json::to_string(&serde_json::json!({ "possibleAlts": alts }))
+}
+
+// Refresh
+
+async fn post_refresh(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: POST refresh");
+    state.request.send(WebRequest::Refresh).ok();
+    (StatusCode::OK, HEADERS)
+}
+
+// Caches
+
+async fn post_clear_caches(
+    State(state): State<WebState>,
+    request: Json<ClearCaches>,
+) -> impl IntoResponse {
+    tracing::debug!("API: POST clear caches");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state
+        .request
+        .send(WebRequest::ClearCaches(request.0, tx))
+        .is_err()
+    {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+// Recently connected
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct RecentQuery {
+    pub seconds: u64,
+}
+
+impl Default for RecentQuery {
+    fn default() -> Self {
+        Self { seconds: 30 }
+    }
+}
+
+async fn get_recently_connected(
+    State(state): State<WebState>,
+    query: Query<RecentQuery>,
+) -> impl IntoResponse {
+    tracing::debug!("API: GET recently connected");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state
+        .request
+        .send(WebRequest::GetRecentlyConnected(query.seconds, tx))
+        .is_err()
+    {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+fn get_recently_connected_response(state: &MACState, seconds: u64) -> serde_json::Result<String> {
+    let players: Vec<String> = state
+        .players
+        .recently_connected(std::time::Duration::from_secs(seconds))
+        .iter()
+        .map(|&s| format!("{}", u64::from(s)))
+        .collect();
+
+    serde_json::to_string(&players)
+}
+
+// Search
+
+/// The most players returned from a single search
+const MAX_SEARCH_RESULTS: usize = 50;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SearchQuery {
+    pub q: String,
+}
+
+async fn get_search(State(state): State<WebState>, query: Query<SearchQuery>) -> impl IntoResponse {
+    tracing::debug!("API: GET search");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state
+        .request
+        .send(WebRequest::SearchPlayers(query.0.q, tx))
+        .is_err()
+    {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+fn get_search_response(state: &MACState, query: &str) -> serde_json::Result<String> {
+    let players: Vec<Player> = state
+        .players
+        .search(query, MAX_SEARCH_RESULTS)
+        .into_iter()
+        .map(|s| state.players.get_serializable_player(s))
+        .collect();
+
+    serde_json::to_string(&players)
+}
+
+// Scoreboard
+
+async fn get_scoreboard(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: GET scoreboard");
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    if state.request.send(WebRequest::GetScoreboard(tx)).is_err() {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+
+    let headers = [
+        (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+        (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
+    ];
+    match rx.recv().await {
+        Some(Ok(text)) => (StatusCode::OK, headers, text).into_response(),
+        Some(Err(e)) => e.into_response(),
+        None => (StatusCode::SERVICE_UNAVAILABLE, headers, String::new()).into_response(),
+    }
+}
+
+// Demos
+
+async fn get_demos(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: GET demos");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state.request.send(WebRequest::GetDemos(tx)).is_err() {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+// Session stats
+
+async fn get_session_stats(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: GET session stats");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state.request.send(WebRequest::GetSessionStats(tx)).is_err() {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+// Metrics
+
+/// Counters for monitoring the client. All of them count from when the client
+/// started, except `eventSubscribers` which is how many are connected now.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Metrics {
+    /// Messages handled by the event loop, by type
+    messages: BTreeMap<&'static str, u64>,
+    tasks_spawned: u64,
+    tasks_completed: u64,
+    /// Tasks which panicked or were aborted for running too long
+    tasks_failed: u64,
+    dropped_messages: u64,
+    steam_api_calls: u64,
+    rcon_reconnects: u64,
+    event_subscribers: usize,
+}
+
+impl Metrics {
+    fn from_state(state: &MACState) -> Self {
+        let event_loop = state.event_loop_metrics.snapshot();
+        Self {
+            messages: event_loop.messages,
+            tasks_spawned: event_loop.tasks_spawned,
+            tasks_completed: event_loop.tasks_completed,
+            tasks_failed: event_loop.tasks_failed,
+            dropped_messages: event_loop.dropped_messages,
+            steam_api_calls: state.session_stats.total_steam_api_calls(),
+            rcon_reconnects: state.session_stats.rcon_reconnects(),
+            event_subscribers: 0,
+        }
+    }
+}
+
+async fn get_metrics(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: GET metrics");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state.request.send(WebRequest::GetMetrics(tx)).is_err() {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+async fn post_reset_session_stats(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: POST reset session stats");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state
+        .request
+        .send(WebRequest::ResetSessionStats(tx))
+        .is_err()
+    {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+// Steam API
+
+async fn get_steam_api_budget(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: GET steam API budget");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state
+        .request
+        .send(WebRequest::GetSteamApiBudget(tx))
+        .is_err()
+    {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+fn get_steam_api_budget_response(state: &MACState) -> serde_json::Result<String> {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Budget {
+        /// Calls allowed per minute, with 0 meaning unlimited
+        calls_per_minute: u32,
+        remaining: Option<u32>,
+    }
+
+    serde_json::to_string(&Budget {
+        calls_per_minute: state.settings.steam_api_calls_per_minute(),
+        remaining: state.steam_api_limiter.remaining(),
+    })
+}
+
+// Parties
+
+async fn get_parties(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: GET parties");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state.request.send(WebRequest::GetParties(tx)).is_err() {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+async fn post_parties(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: POST parties");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state
+        .request
+        .send(WebRequest::RecomputeParties(tx))
+        .is_err()
+    {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+#[derive(Serialize)]
+struct SerializableParty {
+    id: u32,
+    members: Vec<String>,
+}
+
+fn serializable_parties(parties: &Parties) -> Vec<SerializableParty> {
+    parties
+        .parties()
+        .iter()
+        .filter(|p| p.len() >= 2)
+        .map(|p| {
+            let mut members: Vec<u64> = p.iter().map(|&s| u64::from(s)).collect();
+            members.sort_unstable();
+            SerializableParty {
+                id: party_id(p),
+                members: members.iter().map(ToString::to_string).collect(),
+            }
+        })
+        .collect()
+}
+
+fn get_parties_response(parties: &Parties) -> serde_json::Result<String> {
+    serde_json::to_string(&serializable_parties(parties))
+}
+
+/// The parties currently in use, along with when they were found
+fn get_current_parties_response(parties: &Parties) -> serde_json::Result<String> {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct CurrentParties {
+        parties: Vec<SerializableParty>,
+        last_computed: Option<DateTime<Utc>>,
+    }
+
+    serde_json::to_string(&CurrentParties {
+        parties: serializable_parties(parties),
+        last_computed: parties.last_computed(),
+    })
+}
+
 // Preferences
 
 async fn get_prefs(State(state): State<WebState>) -> impl IntoResponse {
     tracing::debug!("API: GET prefs");
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
     if state.request.send(WebRequest::GetPrefs(tx)).is_err() {
         tracing::error!("Couldn't send API request to main thread.");
     }
-    (rx.recv().await).map_or_else(
-        || (StatusCode::SERVICE_UNAVAILABLE, HEADERS, String::new()),
-        |resp| (StatusCode::OK, HEADERS, resp),
-    )
+    await_response(rx).await
 }
 
-fn get_prefs_response(state: &MACState) -> String {
+fn get_prefs_response(state: &MACState) -> serde_json::Result<String> {
     let settings = &state.settings;
     let prefs = Preferences {
         internal: Some(InternalPreferences {
@@ -563,18 +1411,71 @@ fn get_prefs_response(state: &MACState) -> String {
             masterbase_host: Some(settings.masterbase_host().to_owned()),
             rcon_port: Some(settings.rcon_port()),
             dumb_autokick: Some(settings.autokick_bots()),
+            autokick_reason: Some(settings.autokick_reason()),
+            autokick_vote_interval_secs: Some(settings.autokick_vote_interval_secs()),
             tos_agreement_date: settings.tos_agreement_date().map(|date| date.to_rfc3339()),
+            cache_avatars: Some(settings.cache_avatars()),
+            name_source: Some(settings.name_source()),
+            disconnect_grace_period: Some(settings.disconnect_grace_period()),
+            removal_grace_period: Some(settings.removal_grace_period()),
+            prefetch_record_profiles: Some(settings.prefetch_record_profiles()),
+            report_policy: Some(settings.report_policy()),
+            player_sort: Some(settings.player_sort()),
+            max_history_len: Some(settings.max_history_len()),
+            autoexec_commands: Some(settings.autoexec_commands().to_vec()),
+            steam_api_calls_per_minute: Some(settings.steam_api_calls_per_minute()),
+            preexisting_demo_mode: Some(settings.preexisting_demo_mode()),
+            recent_ban_days: Some(settings.recent_ban_days()),
+            mark_recently_banned: Some(settings.mark_recently_banned()),
+            display_styles: Some(settings.display_styles().clone()),
+            disabled_demo_events: Some(settings.disabled_demo_events().to_vec()),
+            wait_for_tf2: Some(settings.wait_for_tf2()),
+            masterbase_session_retries: Some(settings.masterbase_session_retries()),
+            masterbase_retry_delay: Some(settings.masterbase_retry_delay()),
+            rcon_command_interval_ms: Some(settings.rcon_command_interval_ms()),
+            prioritize_rcon_commands: Some(settings.prioritize_rcon_commands()),
+            steam_cache_ttl_minutes: Some(settings.steam_cache_ttl_minutes()),
+            replay_console_log: Some(settings.replay_console_log()),
+            trusted_friends: Some(settings.trusted_friends().to_vec()),
         }),
         external: Some(settings.external_preferences().clone()),
     };
 
-    serde_json::to_string(&prefs).expect("Epic serialization fail")
+    serde_json::to_string(&prefs)
 }
 
-async fn put_prefs(State(state): State<WebState>, prefs: Json<Preferences>) -> impl IntoResponse {
+async fn put_prefs(
+    State(state): State<WebState>,
+    prefs: Json<Preferences>,
+) -> Result<impl IntoResponse, WebError> {
     tracing::debug!("API: PUT prefs");
+    if let Some(styles) = prefs
+        .internal
+        .as_ref()
+        .and_then(|i| i.display_styles.as_ref())
+    {
+        validate_display_styles(styles).map_err(WebError::BadRequest)?;
+    }
+
     state.request.send(WebRequest::PutPrefs(prefs.0)).ok();
-    (StatusCode::OK, HEADERS)
+    Ok((StatusCode::OK, HEADERS))
+}
+
+async fn get_effective_settings(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: GET effective settings");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state
+        .request
+        .send(WebRequest::GetEffectiveSettings(tx))
+        .is_err()
+    {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+fn get_effective_settings_response(state: &MACState) -> serde_json::Result<String> {
+    serde_json::to_string(&state.settings.effective())
 }
 
 // History
@@ -594,7 +1495,7 @@ impl Default for Pagination {
 
 async fn get_history(State(state): State<WebState>, page: Query<Pagination>) -> impl IntoResponse {
     tracing::debug!("API: GET history");
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
     if state
         .request
         .send(WebRequest::GetHistory(page.0, tx))
@@ -602,39 +1503,65 @@ async fn get_history(State(state): State<WebState>, page: Query<Pagination>) ->
     {
         tracing::error!("Couldn't send API request to main thread.");
     }
-    (rx.recv().await).map_or_else(
-        || (StatusCode::SERVICE_UNAVAILABLE, HEADERS, String::new()),
-        |resp| (StatusCode::OK, HEADERS, resp),
-    )
+    await_response(rx).await
 }
 
-fn get_history_response(state: &MACState, page: &Pagination) -> String {
-    // let hVecDeque<SteamID> = &server.players().history;
-    let history: Vec<Player> = state
+/// Like `/mac/history/v1`, but the players are wrapped in an object with the
+/// total amount of history so clients know how far they can page.
+async fn get_history_page(
+    State(state): State<WebState>,
+    page: Query<Pagination>,
+) -> impl IntoResponse {
+    tracing::debug!("API: GET history page");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state
+        .request
+        .send(WebRequest::GetHistoryPage(page.0, tx))
+        .is_err()
+    {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+fn history_page<'a>(state: &'a MACState, page: &Pagination) -> Vec<Player<'a>> {
+    state
         .players
         .history
         .iter()
         .rev()
         .skip(page.from)
-        .take(page.to - page.from)
+        .take(page.to.saturating_sub(page.from))
         .map(|&s| state.players.get_serializable_player(s))
-        .collect();
+        .collect()
+}
 
-    serde_json::to_string(&history).expect("Epic serialization fail")
+fn get_history_response(state: &MACState, page: &Pagination) -> serde_json::Result<String> {
+    serde_json::to_string(&history_page(state, page))
+}
+
+fn get_history_page_response(state: &MACState, page: &Pagination) -> serde_json::Result<String> {
+    #[derive(Serialize)]
+    struct HistoryPage<'a> {
+        total: usize,
+        players: Vec<Player<'a>>,
+    }
+
+    serde_json::to_string(&HistoryPage {
+        total: state.players.history.len(),
+        players: history_page(state, page),
+    })
 }
 
 // Playerlist
 
 async fn get_playerlist(State(state): State<WebState>) -> impl IntoResponse {
     tracing::debug!("API: GET playerlist");
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
     if state.request.send(WebRequest::GetPlayerlist(tx)).is_err() {
         tracing::error!("Couldn't send API request to main thread.");
     }
-    (rx.recv().await).map_or_else(
-        || (StatusCode::SERVICE_UNAVAILABLE, HEADERS, String::new()),
-        |resp| (StatusCode::OK, HEADERS, resp),
-    )
+    await_response(rx).await
 }
 
 // Allowing non-snake-case here because this is the format the UI expects.
@@ -647,6 +1574,9 @@ struct PlayerRecordResponse<'a> {
     steamID64: SteamID,
     convicted: Option<bool>,
     localVerdict: Option<String>,
+    muted: bool,
+    timesSeen: u32,
+    lastSeen: Option<DateTime<Utc>>,
     steamInfo: Option<&'a SteamInfo>,
     customData: &'a serde_json::Value,
     previousNames: Option<&'a [String]>,
@@ -656,7 +1586,7 @@ struct PlayerRecordResponse<'a> {
     created: DateTime<Utc>,
 }
 
-fn get_playerlist_response(state: &MACState) -> String {
+fn get_playerlist_response(state: &MACState) -> serde_json::Result<String> {
     let records = &state.players.records.records;
 
     let records_mapped: Vec<PlayerRecordResponse> = records
@@ -670,6 +1600,9 @@ fn get_playerlist_response(state: &MACState) -> String {
                 steamID64: *id,
                 convicted: Some(false),
                 localVerdict: Some(record.verdict().to_string()),
+                muted: record.muted(),
+                timesSeen: record.times_seen(),
+                lastSeen: record.last_seen(),
                 steamInfo: state.players.steam_info.get(id),
                 customData: record.custom_data(),
                 previousNames: Some(record.previous_names()),
@@ -681,43 +1614,152 @@ fn get_playerlist_response(state: &MACState) -> String {
         })
         .collect();
 
-    serde_json::to_string(&records_mapped).expect("Epic serialization fail")
+    serde_json::to_string(&records_mapped)
 }
 
 // Chat
 
 async fn get_chat(State(state): State<WebState>) -> impl IntoResponse {
     tracing::debug!("API: GET chat");
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
     if state.request.send(WebRequest::GetChat(tx)).is_err() {
         tracing::error!("Couldn't send API request to main thread.");
     }
-    (rx.recv().await).map_or_else(
-        || (StatusCode::SERVICE_UNAVAILABLE, HEADERS, String::new()),
-        |resp| (StatusCode::OK, HEADERS, resp),
-    )
+    await_response(rx).await
 }
 
-fn get_chat_response(state: &MACState) -> String {
-    serde_json::to_string(state.server.chat_history()).expect("Epic serialization fail")
+fn get_chat_response(state: &MACState) -> serde_json::Result<String> {
+    serde_json::to_string(state.server.chat_history())
 }
 
 // Killfeed
 
 async fn get_killfeed(State(state): State<WebState>) -> impl IntoResponse {
     tracing::debug!("API: GET killfeed");
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
     if state.request.send(WebRequest::GetKillfeed(tx)).is_err() {
         tracing::error!("Couldn't send API request to main thread.");
     }
-    (rx.recv().await).map_or_else(
-        || (StatusCode::SERVICE_UNAVAILABLE, HEADERS, String::new()),
-        |resp| (StatusCode::OK, HEADERS, resp),
-    )
+    await_response(rx).await
 }
 
-fn get_killfeed_response(state: &MACState) -> String {
-    serde_json::to_string(state.server.kill_history()).expect("Epic serialization fail")
+fn get_killfeed_response(state: &MACState) -> serde_json::Result<String> {
+    serde_json::to_string(state.server.kill_history())
+}
+
+// Avatars
+
+#[derive(Deserialize, Debug)]
+pub struct AvatarQuery {
+    pub hash: String,
+}
+
+async fn get_avatar(query: Query<AvatarQuery>) -> impl IntoResponse {
+    tracing::debug!("API: GET avatar");
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            ([(header::CONTENT_TYPE, "text/html")]),
+            "<body><h1>404 Not Found</h1></body>",
+        )
+            .into_response()
+    };
+
+    let Some(path) = locate_avatar_cache_directory()
+        .ok()
+        .and_then(|dir| avatar_path(&dir, &query.hash))
+    else {
+        return not_found();
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(contents) => {
+            let headers = [
+                (header::CONTENT_TYPE, guess_content_type(&path)),
+                (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
+            ];
+            (StatusCode::OK, headers, contents).into_response()
+        }
+        Err(_) => not_found(),
+    }
+}
+
+// Logs
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct LogsQuery {
+    pub lines: usize,
+}
+
+impl Default for LogsQuery {
+    fn default() -> Self {
+        Self { lines: 100 }
+    }
+}
+
+async fn get_warnings() -> impl IntoResponse {
+    tracing::debug!("API: GET warnings");
+    match warnings::active_json() {
+        Ok(body) => (StatusCode::OK, HEADERS, body).into_response(),
+        Err(e) => WebError::from(e).into_response(),
+    }
+}
+
+async fn get_logs(query: Query<LogsQuery>) -> impl IntoResponse {
+    const MAX_LINES: usize = 1000;
+
+    tracing::debug!("API: GET logs");
+    let lines = query.lines.min(MAX_LINES);
+
+    match tokio::task::spawn_blocking(move || tail_lines(Path::new(LOG_FILE_PATH), lines)).await {
+        Ok(Ok(lines)) => match serde_json::to_string(&lines) {
+            Ok(body) => (StatusCode::OK, HEADERS, body).into_response(),
+            Err(e) => WebError::from(e).into_response(),
+        },
+        Ok(Err(e)) => {
+            tracing::error!("Failed to read log file: {e}");
+            (StatusCode::NOT_FOUND, HEADERS, String::new()).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to read log file: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, HEADERS, String::new()).into_response()
+        }
+    }
+}
+
+/// Reads the last `n` lines of a file, reading backwards from the end in
+/// chunks so the whole file doesn't need to be loaded.
+fn tail_lines(path: &Path, n: usize) -> std::io::Result<Vec<String>> {
+    const CHUNK_SIZE: u64 = 8192;
+
+    let mut file = std::fs::File::open(path)?;
+    let len = file.seek(SeekFrom::End(0))?;
+
+    // Read chunks from the end until we've seen enough newlines. One more than
+    // `n` is needed since the file usually ends with a newline.
+    let mut pos = len;
+    let mut buf = Vec::new();
+    let mut newlines = 0;
+    while pos > 0 && newlines <= n {
+        let read = CHUNK_SIZE.min(pos);
+        pos -= read;
+
+        let mut chunk = vec![0; usize::try_from(read).unwrap_or(0)];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut chunk)?;
+        newlines += chunk.split(|&b| b == b'\n').count() - 1;
+
+        chunk.extend_from_slice(&buf);
+        buf = chunk;
+    }
+
+    let text = String::from_utf8_lossy(&buf);
+    let lines: Vec<&str> = text.lines().collect();
+    Ok(lines[lines.len().saturating_sub(n)..]
+        .iter()
+        .map(|&l| l.to_owned())
+        .collect())
 }
 
 // Commands
@@ -738,9 +1780,55 @@ async fn post_commands(
 
 // Events
 
-type Subscriber = tokio::sync::mpsc::Sender<Result<Event, Infallible>>;
+/// Somewhere to send events to, one for each client listening for them
+enum Subscriber {
+    Sse(tokio::sync::mpsc::Sender<Result<Event, Infallible>>),
+    WebSocket(tokio::sync::mpsc::Sender<String>),
+}
+
+impl Subscriber {
+    fn is_closed(&self) -> bool {
+        match self {
+            Self::Sse(tx) => tx.is_closed(),
+            Self::WebSocket(tx) => tx.is_closed(),
+        }
+    }
+
+    async fn send(&self, event_json: &str) {
+        match self {
+            Self::Sse(tx) => {
+                tx.send(Ok(Event::default().data(event_json))).await.ok();
+            }
+            Self::WebSocket(tx) => {
+                tx.send(event_json.to_owned()).await.ok();
+            }
+        }
+    }
+}
+
 static SUBSCRIBERS: Mutex<Option<Vec<Subscriber>>> = Mutex::const_new(None);
 
+async fn subscribe(subscriber: Subscriber) {
+    let mut subscribers = SUBSCRIBERS.lock().await;
+    if subscribers.is_none() {
+        *subscribers = Some(Vec::new());
+    }
+    // subscribers will never be None here, so calling expect will never panic.
+    subscribers
+        .as_mut()
+        .expect("Just set it to Some")
+        .push(subscriber);
+}
+
+/// How many SSE and WebSocket clients are currently listening for events
+async fn subscriber_count() -> usize {
+    SUBSCRIBERS
+        .lock()
+        .await
+        .as_ref()
+        .map_or(0, |subs| subs.iter().filter(|s| !s.is_closed()).count())
+}
+
 /// Gets a SSE stream to listen for any updates the client can provide.
 /// This returns the `rx` channel to the client that hit this endpoint. The corresponding `tx` channel is stored in the SUBSCRIBERS
 /// Mutex lock. You may send events to these subscribed clients by calling 'Send' on the `tx` channel.
@@ -748,17 +1836,75 @@ async fn get_events() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     tracing::debug!("API: Events subcription");
 
     let (tx, rx) = tokio::sync::mpsc::channel::<Result<Event, Infallible>>(16);
+    subscribe(Subscriber::Sse(tx)).await;
 
-    {
-        let mut subscribers = SUBSCRIBERS.lock().await;
-        if subscribers.is_none() {
-            *subscribers = Some(Vec::new());
+    Sse::new(ReceiverStream::new(rx))
+}
+
+/// The same events as [`get_events`], but over a WebSocket for clients which
+/// can't use SSE, e.g. because of a proxy. Each event is sent as a text
+/// message containing the same JSON.
+async fn get_events_ws(ws: WebSocketUpgrade) -> Response {
+    tracing::debug!("API: Events websocket subscription");
+    ws.on_upgrade(forward_events)
+}
+
+/// Sends events down the socket until the client goes away. Dropping the
+/// receiver then closes the channel, so the subscriber gets pruned.
+async fn forward_events(socket: WebSocket) {
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(16);
+    subscribe(Subscriber::WebSocket(tx)).await;
+
+    let (mut sink, mut stream) = socket.split();
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let Some(event) = event else {
+                    break;
+                };
+                if sink.send(WsMessage::Text(event)).await.is_err() {
+                    break;
+                }
+            }
+            // Nothing is expected from the client, but it has to be read to
+            // notice it closing
+            message = stream.next() => {
+                if matches!(message, None | Some(Err(_) | Ok(WsMessage::Close(_)))) {
+                    break;
+                }
+            }
         }
-        // subscribers will never be None here, so calling expect will never panic.
-        subscribers.as_mut().expect("Just set it to Some").push(tx);
     }
+}
 
-    Sse::new(ReceiverStream::new(rx))
+async fn post_test_event(State(state): State<WebState>) -> impl IntoResponse {
+    tracing::debug!("API: POST test event");
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    if state.request.send(WebRequest::TestEvent(tx)).is_err() {
+        tracing::error!("Couldn't send API request to main thread.");
+    }
+    await_response(rx).await
+}
+
+/// A fake chat message in the same shape as real chat events, marked with
+/// `test` so the UI can tell it apart.
+fn test_event_json() -> String {
+    let now = Utc::now();
+    serde_json::json!({
+        "type": "ChatMessage",
+        "uuid": uuid::Uuid::new_v4(),
+        "time": now,
+        "test": true,
+        "event": {
+            "player_name": "MAC",
+            "steamid": null,
+            "message": "This is a test event. If you can see this, events are working!",
+            "timestamp": now,
+            "scope": "All",
+            "dead": false,
+        },
+    })
+    .to_string()
 }
 
 /// Given a serialised JSON string (we do not actually verify the string is json, but it is expected), broadcast to all subscribers.
@@ -776,9 +1922,7 @@ pub async fn broadcast_event(event_json: String) {
         // prune closed tx/rx pairs out of the subscribers list
         subs.retain(|sender| !sender.is_closed());
         // futs stands for Futures, not... hentai women
-        let futs = subs
-            .iter()
-            .map(|sender| sender.send(Ok(Event::default().data(&event_json))));
+        let futs = subs.iter().map(|sender| sender.send(&event_json));
 
         // We have created an iterator of Futures that promise to send the message down the channel
         // So we await them all by calling join_all, which does this, but without promising true concurrency.
@@ -786,3 +1930,17 @@ pub async fn broadcast_event(event_json: String) {
         drop(subscribers);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::token_matches;
+
+    #[test]
+    pub fn token_comparison() {
+        assert!(token_matches("hunter2", "hunter2"));
+        assert!(!token_matches("hunter3", "hunter2"));
+        assert!(!token_matches("hunter", "hunter2"));
+        assert!(!token_matches("hunter22", "hunter2"));
+        assert!(!token_matches("", "hunter2"));
+    }
+}